@@ -0,0 +1,97 @@
+// Extraction of a file split into thousands of small data nodes, the
+// layout mkfs.jffs2 produces with its default 4KB pages.
+//
+//     cargo bench --bench fragments -- --save-baseline before
+//     (apply the change)
+//     cargo bench --bench fragments -- --baseline before
+//
+// The image is assembled here, with a mix of zlib, zero and uncompressed
+// nodes, so the benchmark needs no fixture.
+
+use std::io::Write;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use flate2::write::ZlibEncoder;
+use jffs2::crc::jffs2_crc32;
+use jffs2::Jffs2Reader;
+
+const PAGE: usize = 4096;
+const PAGES: usize = 4096;
+
+/// Append a node with its common header, padded to 4 bytes
+fn node(image: &mut Vec<u8>, nodetype: u16, body: &[u8]) {
+    let mut header = vec![];
+    header.extend(0x1985u16.to_le_bytes());
+    header.extend(nodetype.to_le_bytes());
+    header.extend((12 + body.len() as u32).to_le_bytes());
+    header.extend(jffs2_crc32(&header).to_le_bytes());
+    image.extend(header);
+    image.extend(body);
+    image.resize(image.len().next_multiple_of(4), 0xff);
+}
+
+fn image() -> Vec<u8> {
+    let mut image = vec![];
+
+    let name = b"firmware.bin";
+    let mut dirent = vec![];
+    for field in [1u32, 1, 2, 0] {
+        dirent.extend(field.to_le_bytes());
+    }
+    dirent.extend([name.len() as u8, 8, 0, 0]);
+    dirent.extend([0; 4]);
+    dirent.extend(jffs2_crc32(name).to_le_bytes());
+    dirent.extend(name);
+    node(&mut image, 0xE001, &dirent);
+
+    for page in 0..PAGES {
+        let text: Vec<u8> = (0..PAGE).map(|i| (i * 7 + page) as u8 % 64 + b' ').collect();
+        let (compr, data) = match page % 3 {
+            0 => {
+                let mut encoder = ZlibEncoder::new(vec![], flate2::Compression::default());
+                encoder.write_all(&text).unwrap();
+                (6u8, encoder.finish().unwrap())
+            }
+            1 => (1, vec![]),
+            _ => (0, text),
+        };
+
+        let mut inode = vec![];
+        for field in [2u32, page as u32 + 1, 0o100644] {
+            inode.extend(field.to_le_bytes());
+        }
+        inode.extend([0; 4]);
+        inode.extend(((PAGES * PAGE) as u32).to_le_bytes());
+        inode.extend([0; 12]);
+        for field in [page * PAGE, data.len(), PAGE] {
+            inode.extend((field as u32).to_le_bytes());
+        }
+        inode.extend([compr, 0, 0, 0]);
+        inode.extend(jffs2_crc32(&data).to_le_bytes());
+        inode.extend([0; 4]);
+        inode.extend(&data);
+        node(&mut image, 0xE002, &inode);
+    }
+
+    image
+}
+
+fn bench_fragments(c: &mut Criterion) {
+    let image = image();
+    let mut reader = Jffs2Reader::from_bytes(&image[..]).unwrap();
+    reader.scan().unwrap();
+    let output = std::env::temp_dir().join(format!("jffs2-rs-bench-{}", std::process::id()));
+
+    let mut group = c.benchmark_group("fragments");
+    group.throughput(Throughput::Bytes((PAGES * PAGE) as u64));
+    group.bench_function("dump", |b| b.iter(|| reader.dump(&output).unwrap()));
+    group.bench_function("read_file", |b| {
+        b.iter(|| reader.read_file("firmware.bin").unwrap())
+    });
+    group.finish();
+
+    std::fs::remove_dir_all(output).unwrap();
+}
+
+criterion_group!(benches, bench_fragments);
+criterion_main!(benches);
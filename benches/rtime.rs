@@ -0,0 +1,86 @@
+// Compares the rtime decoder with the byte-at-a-time loop it replaced.
+//
+//     cargo bench --bench rtime
+//
+// Any stream of (value, repeat) pairs is valid rtime input, so the inputs
+// are generated pair by pair instead of going through a compressor.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+fn bytewise_decompress(compressed_buffer: &[u8], dstlen: usize) -> Vec<u8> {
+    let mut dst = vec![];
+    let mut pos = 0;
+    let mut position = vec![0; 256];
+
+    while dst.len() < dstlen {
+        let val = compressed_buffer[pos];
+        pos += 1;
+        dst.push(val);
+
+        let mut repeat = compressed_buffer[pos];
+        pos += 1;
+        let mut backoffs = position[val as usize];
+
+        position[val as usize] = dst.len();
+        if repeat != 0 {
+            if backoffs + repeat as usize >= dst.len() {
+                while repeat != 0 {
+                    dst.push(dst[backoffs]);
+                    backoffs += 1;
+                    repeat -= 1;
+                }
+            } else {
+                let slice = &dst[backoffs..backoffs + repeat as usize].to_owned();
+                dst.extend(slice);
+            }
+        }
+    }
+
+    dst
+}
+
+/// Build a stream whose runs are at most `max_repeat` long, returns it
+/// with the length it decodes to
+fn stream(pairs: usize, alphabet: u32, max_repeat: u32) -> (Vec<u8>, usize) {
+    let mut state = 0x1985_e002u32;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state
+    };
+
+    // each pair decodes to the value plus `repeat` copied bytes
+    let mut input = Vec::with_capacity(pairs * 2);
+    let mut len = 0;
+    for _ in 0..pairs {
+        let repeat = next() % (max_repeat + 1);
+        input.push((next() % alphabet) as u8);
+        input.push(repeat as u8);
+        len += 1 + repeat as usize;
+    }
+    (input, len)
+}
+
+fn bench_rtime(c: &mut Criterion) {
+    let mut group = c.benchmark_group("rtime");
+    let cases = [
+        ("literals", stream(4096, 256, 0)),
+        ("short-runs", stream(1024, 64, 8)),
+        ("long-runs", stream(256, 4, 255)),
+    ];
+
+    for (name, (input, dstlen)) in &cases {
+        group.throughput(Throughput::Bytes(*dstlen as u64));
+        group.bench_with_input(BenchmarkId::new("bytewise", name), input, |b, input| {
+            b.iter(|| bytewise_decompress(black_box(input), *dstlen))
+        });
+        group.bench_with_input(BenchmarkId::new("current", name), input, |b, input| {
+            b.iter(|| jffs2::rtime::decompress(black_box(input), *dstlen).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_rtime);
+criterion_main!(benches);
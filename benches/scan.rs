@@ -0,0 +1,127 @@
+// Scan of a 36MB image of 8192 small files, about 40000 nodes, with the
+// allocations made by one scan counted first.
+//
+//     cargo bench --bench scan
+//
+// Each file is a dirent and four uncompressed 1KB data nodes. A scan that
+// copied node payloads would allocate more than the image itself.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use jffs2::crc::jffs2_crc32;
+use jffs2::Jffs2Reader;
+
+const FILES: usize = 8192;
+const PAGES: usize = 4;
+const PAGE: usize = 1024;
+
+/// The system allocator, counting the allocations and bytes asked for
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED.fetch_add(new_size.saturating_sub(layout.size()), Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// Append a node with its common header, padded to 4 bytes
+fn node(image: &mut Vec<u8>, nodetype: u16, body: &[u8]) {
+    let mut header = vec![];
+    header.extend(0x1985u16.to_le_bytes());
+    header.extend(nodetype.to_le_bytes());
+    header.extend((12 + body.len() as u32).to_le_bytes());
+    header.extend(jffs2_crc32(&header).to_le_bytes());
+    image.extend(header);
+    image.extend(body);
+    image.resize(image.len().next_multiple_of(4), 0xff);
+}
+
+fn image() -> Vec<u8> {
+    let mut image = vec![];
+    for file in 0..FILES {
+        let ino = file as u32 + 2;
+        let name = format!("file{}", file);
+        let mut dirent = vec![];
+        for field in [1u32, 1, ino, 0] {
+            dirent.extend(field.to_le_bytes());
+        }
+        dirent.extend([name.len() as u8, 8, 0, 0]);
+        dirent.extend([0; 4]);
+        dirent.extend(jffs2_crc32(name.as_bytes()).to_le_bytes());
+        dirent.extend(name.as_bytes());
+        node(&mut image, 0xE001, &dirent);
+
+        for page in 0..PAGES {
+            let data: Vec<u8> = (0..PAGE).map(|i| (i * 7 + page) as u8 % 64 + b' ').collect();
+            let mut inode = vec![];
+            for field in [ino, page as u32 + 1, 0o100644] {
+                inode.extend(field.to_le_bytes());
+            }
+            inode.extend([0; 4]);
+            inode.extend(((PAGES * PAGE) as u32).to_le_bytes());
+            inode.extend([0; 12]);
+            for field in [page * PAGE, data.len(), PAGE] {
+                inode.extend((field as u32).to_le_bytes());
+            }
+            inode.extend([0, 0, 0, 0]);
+            inode.extend(jffs2_crc32(&data).to_le_bytes());
+            inode.extend([0; 4]);
+            inode.extend(&data);
+            node(&mut image, 0xE002, &inode);
+        }
+    }
+    image
+}
+
+fn scan(image: &[u8]) -> Jffs2Reader<'_> {
+    let mut reader = Jffs2Reader::from_bytes(image).unwrap();
+    reader.scan().unwrap();
+    reader
+}
+
+fn bench_scan(c: &mut Criterion) {
+    let image = image();
+
+    let counts = || (ALLOCATIONS.load(Ordering::Relaxed), ALLOCATED.load(Ordering::Relaxed));
+    let before = counts();
+    let reader = scan(&image);
+    let (allocations, allocated) = (counts().0 - before.0, counts().1 - before.1);
+    assert_eq!(reader.stats().inodes(), (FILES * PAGES) as u64);
+    println!(
+        "scan of {} nodes in {} bytes: {} allocations, {} bytes allocated",
+        FILES * (PAGES + 1),
+        image.len(),
+        allocations,
+        allocated
+    );
+    assert!(allocated < image.len(), "the scan copies node payloads");
+    drop(reader);
+
+    let mut group = c.benchmark_group("scan");
+    group.throughput(Throughput::Bytes(image.len() as u64));
+    group.bench_function("linear", |b| b.iter(|| scan(&image)));
+    group.finish();
+}
+
+criterion_group!(benches, bench_scan);
+criterion_main!(benches);
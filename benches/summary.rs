@@ -0,0 +1,151 @@
+// Scan of a 32MB image written with erase block summaries, the layout
+// mkfs.jffs2 --with-summary or sumtool produce, with and without using them.
+//
+//     cargo bench --bench summary
+//
+// Each 64KB erase block holds the zlib nodes of one file, erased space and
+// the summary closing the block.
+
+use std::io::Write;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use flate2::write::ZlibEncoder;
+use jffs2::crc::jffs2_crc32;
+use jffs2::Jffs2ReaderBuilder;
+
+const BLOCK: usize = 64 * 1024;
+const BLOCKS: usize = 512;
+const PAGE: usize = 4096;
+
+/// The common node header followed by `body`, padded to 4 bytes
+fn node(nodetype: u16, body: &[u8]) -> Vec<u8> {
+    let mut node = vec![];
+    node.extend(0x1985u16.to_le_bytes());
+    node.extend(nodetype.to_le_bytes());
+    node.extend((12 + body.len() as u32).to_le_bytes());
+    node.extend(jffs2_crc32(&node).to_le_bytes());
+    node.extend(body);
+    node.resize(node.len().next_multiple_of(4), 0xff);
+    node
+}
+
+fn dirent(ino: u32, name: &[u8]) -> Vec<u8> {
+    let mut body = vec![];
+    for field in [1u32, 1, ino, 0] {
+        body.extend(field.to_le_bytes());
+    }
+    body.extend([name.len() as u8, 8, 0, 0]);
+    body.extend([0; 4]);
+    body.extend(jffs2_crc32(name).to_le_bytes());
+    body.extend(name);
+    node(0xE001, &body)
+}
+
+fn inode(ino: u32, page: usize, pages: usize, text: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(vec![], flate2::Compression::default());
+    encoder.write_all(text).unwrap();
+    let data = encoder.finish().unwrap();
+
+    let mut body = vec![];
+    for field in [ino, page as u32 + 1, 0o100644] {
+        body.extend(field.to_le_bytes());
+    }
+    body.extend([0; 4]);
+    body.extend(((pages * PAGE) as u32).to_le_bytes());
+    body.extend([0; 12]);
+    for field in [page * PAGE, data.len(), PAGE] {
+        body.extend((field as u32).to_le_bytes());
+    }
+    body.extend([6, 0, 0, 0]);
+    body.extend(jffs2_crc32(&data).to_le_bytes());
+    body.extend([0; 4]);
+    body.extend(&data);
+    node(0xE002, &body)
+}
+
+/// The summary node ending an erase block, listing `entries`
+fn summary(offset: usize, entries: &[Vec<u8>]) -> Vec<u8> {
+    let len = BLOCK - offset;
+    let mut data = entries.concat();
+    data.resize(len - 32 - 8, 0xff);
+    data.extend((offset as u32).to_le_bytes());
+    data.extend(0x0285_1885u32.to_le_bytes());
+
+    let mut node = vec![];
+    node.extend(0x1985u16.to_le_bytes());
+    node.extend(0x2006u16.to_le_bytes());
+    node.extend((len as u32).to_le_bytes());
+    node.extend(jffs2_crc32(&node).to_le_bytes());
+    for field in [entries.len() as u32, 0, 0, jffs2_crc32(&data)] {
+        node.extend(field.to_le_bytes());
+    }
+    let node_crc = jffs2_crc32(&node[..24]);
+    node.extend(node_crc.to_le_bytes());
+    node.extend(data);
+    node
+}
+
+fn image() -> Vec<u8> {
+    let mut image = Vec::with_capacity(BLOCK * BLOCKS);
+    for block in 0..BLOCKS {
+        let start = image.len();
+        let ino = block as u32 + 2;
+        let mut nodes = vec![dirent(ino, format!("file{}", block).as_bytes())];
+        let pages = 10 + block % 5;
+        for page in 0..pages {
+            let text: Vec<u8> = (0..PAGE)
+                .map(|i| (i * 7 + page * 3 + block) as u8 % 96 + b' ')
+                .collect();
+            nodes.push(inode(ino, page, pages, &text));
+        }
+
+        let mut entries = vec![];
+        for node in nodes {
+            let offset = ((image.len() - start) as u32).to_le_bytes();
+            let totlen = &node[4..8];
+            let mut entry = node[2..4].to_vec();
+            if node[2] == 0x01 {
+                // dirent: totlen, offset, pino, version, ino, nsize, type, name
+                entry.extend(totlen);
+                entry.extend(offset);
+                entry.extend(&node[12..24]);
+                entry.extend(&node[28..30]);
+                entry.extend(&node[40..40 + node[28] as usize]);
+            } else {
+                // inode: ino, version, offset, totlen
+                entry.extend(&node[12..20]);
+                entry.extend(offset);
+                entry.extend(totlen);
+            }
+            entries.push(entry);
+            image.extend(node);
+        }
+
+        let size = (32 + entries.iter().map(Vec::len).sum::<usize>() + 8).next_multiple_of(4);
+        image.resize(start + BLOCK - size, 0xff);
+        image.extend(summary(BLOCK - size, &entries));
+    }
+    image
+}
+
+fn bench_summary(c: &mut Criterion) {
+    let image = image();
+    let scan = |summaries: bool| {
+        let mut reader = Jffs2ReaderBuilder::from_bytes(&image[..])
+            .summaries(summaries)
+            .build()
+            .unwrap();
+        reader.scan().unwrap();
+        reader
+    };
+    assert_eq!(scan(true).entries().unwrap().len(), BLOCKS);
+
+    let mut group = c.benchmark_group("summary");
+    group.throughput(Throughput::Bytes(image.len() as u64));
+    group.bench_function("linear", |b| b.iter(|| scan(false)));
+    group.bench_function("summaries", |b| b.iter(|| scan(true)));
+    group.finish();
+}
+
+criterion_group!(benches, bench_summary);
+criterion_main!(benches);
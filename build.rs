@@ -1,6 +1,7 @@
 use target_build_utils::TargetInfo;
 
 fn main() {
+    let rubin = std::env::var_os("CARGO_FEATURE_RUBIN").is_some();
     let target = TargetInfo::new().expect("could not get target info");
 
     if target.target_os() == "macos" {
@@ -10,15 +11,17 @@ fn main() {
             "arm64"
         };
 
-        println!("cargo:rerun-if-changed=rubin");
-        let dst = cmake::Config::new("rubin")
-            .define("CMAKE_OSX_ARCHITECTURES", arch)
-            .build();
-        println!(
-            "cargo:rustc-link-search=native={}",
-            dst.join("lib").display()
-        );
-        println!("cargo:rustc-link-lib=static=rubin");
+        if rubin {
+            println!("cargo:rerun-if-changed=rubin");
+            let dst = cmake::Config::new("rubin")
+                .define("CMAKE_OSX_ARCHITECTURES", arch)
+                .build();
+            println!(
+                "cargo:rustc-link-search=native={}",
+                dst.join("lib").display()
+            );
+            println!("cargo:rustc-link-lib=static=rubin");
+        }
 
         println!("cargo:rerun-if-changed=lzo");
         let dst2 = cmake::Config::new("lzo")
@@ -30,14 +33,16 @@ fn main() {
         );
         println!("cargo:rustc-link-lib=static=lzo2");
     } else {
-        println!("cargo:rerun-if-changed=rubin");
+        if rubin {
+            println!("cargo:rerun-if-changed=rubin");
 
-        let dst = cmake::build("rubin");
-        println!(
-            "cargo:rustc-link-search=native={}",
-            dst.join("lib").display()
-        );
-        println!("cargo:rustc-link-lib=static=rubin");
+            let dst = cmake::build("rubin");
+            println!(
+                "cargo:rustc-link-search=native={}",
+                dst.join("lib").display()
+            );
+            println!("cargo:rustc-link-lib=static=rubin");
+        }
 
         println!("cargo:rerun-if-changed=lzo");
         let dst2 = cmake::build("lzo");
@@ -0,0 +1,91 @@
+//! Print a directory tree with one walker for both a real directory and a jffs2 image.
+//!
+//! cargo run --example walk -- <directory> <image.jffs2>
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use jffs2::fs::Jffs2Fs;
+
+/// The few `std::fs` operations the walker relies on
+trait Tree {
+    fn read_dir(&self, path: &Path) -> Result<Vec<PathBuf>>;
+    fn is_dir(&self, path: &Path) -> Result<bool>;
+    fn len(&self, path: &Path) -> Result<u64>;
+}
+
+struct RealFs;
+
+impl Tree for RealFs {
+    fn read_dir(&self, path: &Path) -> Result<Vec<PathBuf>> {
+        let mut paths = vec![];
+        for entry in std::fs::read_dir(path)? {
+            paths.push(entry?.path());
+        }
+        paths.sort();
+        Ok(paths)
+    }
+
+    fn is_dir(&self, path: &Path) -> Result<bool> {
+        Ok(std::fs::metadata(path)?.is_dir())
+    }
+
+    fn len(&self, path: &Path) -> Result<u64> {
+        Ok(std::fs::metadata(path)?.len())
+    }
+}
+
+impl Tree for Jffs2Fs {
+    fn read_dir(&self, path: &Path) -> Result<Vec<PathBuf>> {
+        let mut paths = vec![];
+        for entry in Jffs2Fs::read_dir(self, path)? {
+            paths.push(entry?.path());
+        }
+        Ok(paths)
+    }
+
+    fn is_dir(&self, path: &Path) -> Result<bool> {
+        Ok(self.metadata(path)?.is_dir())
+    }
+
+    fn len(&self, path: &Path) -> Result<u64> {
+        Ok(self.metadata(path)?.len())
+    }
+}
+
+/// The walker, unaware of what it is walking
+fn walk(tree: &impl Tree, path: &Path, depth: usize) -> Result<()> {
+    for child in tree.read_dir(path)? {
+        let name = child.file_name().unwrap_or_default().to_string_lossy();
+        if tree.is_dir(&child)? {
+            println!("{}{}/", "  ".repeat(depth), name);
+            walk(tree, &child, depth + 1)?;
+        } else {
+            println!(
+                "{}{} ({} bytes)",
+                "  ".repeat(depth),
+                name,
+                tree.len(&child)?
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() != 3 {
+        eprintln!("usage: {} <directory> <image.jffs2>", args[0]);
+        std::process::exit(1);
+    }
+
+    println!("== {}", args[1]);
+    walk(&RealFs, Path::new(&args[1]), 0)?;
+
+    println!("== {}", args[2]);
+    let image = Jffs2Fs::open(&args[2])?;
+    walk(&image, Path::new("/"), 0)?;
+
+    Ok(())
+}
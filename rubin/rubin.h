@@ -7,3 +7,9 @@ void dynrubin_decompress(
     uint32_t dstlen
 );
 
+void rubinmips_decompress(
+    unsigned char *data_in, 
+    unsigned char *cpage_out,
+	uint32_t sourcelen, 
+    uint32_t dstlen
+);
@@ -4,8 +4,12 @@
 #define UPPER_BIT_RUBIN    (((long) 1)<<(RUBIN_REG_SIZE-1))
 #define LOWER_BITS_RUBIN   ((((long) 1)<<(RUBIN_REG_SIZE-1))-1)
 
-void rubin_do_decompress(unsigned char *bits, unsigned char *in,
-			 unsigned char *page_out, uint32_t destlen)
+#define BIT_DIVIDER_MIPS 1043
+static int bits_mips[8] = { 277, 249, 290, 267, 229, 341, 212, 241 };
+
+/* bits[i] out of bit_divider is the weight of a zero for bit i of a byte */
+static void rubin_do_decompress_div(long bit_divider, long *bits, unsigned char *in,
+				    unsigned char *page_out, uint32_t destlen)
 {
 	char *curr = (char *)page_out;
 	char *end = (char *)(page_out + destlen);
@@ -50,7 +54,7 @@ void rubin_do_decompress(unsigned char *bits, unsigned char *in,
 					in = (unsigned char *)p;
 				}
 			}
-			i0 =  (bits[i] * p) >> 8;
+			i0 = (bits[i] * (long) p) / bit_divider;
 
 			if (i0 <= 0) i0 = 1;
 			/* if it fails, it fails, we have our crc
@@ -70,6 +74,18 @@ void rubin_do_decompress(unsigned char *bits, unsigned char *in,
 	}
 }
 
+void rubin_do_decompress(unsigned char *bits, unsigned char *in,
+			 unsigned char *page_out, uint32_t destlen)
+{
+	long weights[8];
+	int c;
+
+	for (c=0; c<8; c++)
+		weights[c] = bits[c];
+
+	rubin_do_decompress_div(256, weights, in, page_out, destlen);
+}
+
 void dynrubin_decompress(unsigned char *data_in, unsigned char *cpage_out,
 		   uint32_t sourcelen, uint32_t dstlen)
 {
@@ -81,3 +97,15 @@ void dynrubin_decompress(unsigned char *data_in, unsigned char *cpage_out,
 
 	rubin_do_decompress(bits, data_in+8, cpage_out, dstlen);
 }
+
+void rubinmips_decompress(unsigned char *data_in, unsigned char *cpage_out,
+		   uint32_t sourcelen, uint32_t dstlen)
+{
+	long weights[8];
+	int c;
+
+	for (c=0; c<8; c++)
+		weights[c] = BIT_DIVIDER_MIPS - bits_mips[c];
+
+	rubin_do_decompress_div(BIT_DIVIDER_MIPS, weights, data_in, cpage_out, dstlen);
+}
@@ -0,0 +1,149 @@
+//! Tar export.
+//!
+//! Writes the entries of an image as a tar archive without touching the
+//! local filesystem, the file contents streamed one data node at a time.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use tar::{Builder, EntryType, Header};
+
+use crate::{FileKind, Jffs2Reader};
+
+impl Jffs2Reader<'_> {
+    /// Write every entry of the image to `out` as a tar archive, with its
+    /// mode, owner and modification time. The other names of a file are hard
+    /// links to the first one by path, and sockets, which tar cannot hold,
+    /// are left out with a warning.
+    pub fn dump_tar<W: Write>(&self, out: W) -> Result<()> {
+        let mut builder = Builder::new(out);
+        let mut written_at: HashMap<u32, PathBuf> = HashMap::new();
+        for entry in self.entries()? {
+            let mut header = Header::new_gnu();
+            header.set_mode(entry.mode & 0o7777);
+            header.set_uid(entry.uid as u64);
+            header.set_gid(entry.gid as u64);
+            header.set_mtime(entry.mtime as u64);
+            header.set_size(0);
+            let path = &entry.path;
+            match entry.kind {
+                FileKind::Regular => {
+                    if let Some(original) = written_at.get(&entry.ino) {
+                        header.set_entry_type(EntryType::Link);
+                        builder.append_link(&mut header, path, original)?;
+                        continue;
+                    }
+                    written_at.insert(entry.ino, path.clone());
+                    header.set_entry_type(EntryType::Regular);
+                    header.set_size(entry.size);
+                    builder.append_data(&mut header, path, self.open_inode(entry.ino)?)?;
+                }
+                FileKind::Dir => {
+                    header.set_entry_type(EntryType::Directory);
+                    builder.append_data(&mut header, path, std::io::empty())?;
+                }
+                FileKind::Symlink => {
+                    let Some(target) = entry.symlink_target.as_deref() else {
+                        log::warn!("{}: symbolic link without a target, left out", path.display());
+                        continue;
+                    };
+                    header.set_entry_type(EntryType::Symlink);
+                    builder.append_link(&mut header, path, target)?;
+                }
+                FileKind::CharDev | FileKind::BlockDev | FileKind::Fifo => {
+                    header.set_entry_type(match entry.kind {
+                        FileKind::CharDev => EntryType::Char,
+                        FileKind::BlockDev => EntryType::Block,
+                        _ => EntryType::Fifo,
+                    });
+                    if let Some((major, minor)) = entry.device {
+                        header.set_device_major(major)?;
+                        header.set_device_minor(minor)?;
+                    }
+                    builder.append_data(&mut header, path, std::io::empty())?;
+                }
+                FileKind::Socket => {
+                    log::warn!("{}: sockets cannot go in a tar archive, left out", path.display());
+                }
+            }
+        }
+        builder.into_inner()?.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Read;
+    use std::path::Path;
+
+    use super::*;
+    use crate::testutil::*;
+    use crate::{DT_CHR, DT_REG, DT_SOCK, S_IFCHR, S_IFSOCK};
+
+    #[test]
+    fn test_dump_tar() {
+        let mut passwd = InodeNode::file(3, 1, 0, b"root:x:0:0\n");
+        passwd.mode = crate::S_IFREG | 0o600;
+        passwd.uid = 1000;
+        passwd.gid = 100;
+        passwd.mtime = 1_600_000_000;
+        let image = ImageBuilder::new()
+            .dir(1, 2, "etc")
+            .dirent(2, 3, 1, DT_REG, "passwd")
+            .inode(&passwd)
+            .dirent(1, 3, 1, DT_REG, "passwd.bak")
+            .symlink(1, 4, "motd", "etc/motd")
+            .special(1, 5, "null", DT_CHR, S_IFCHR | 0o666, &0x103u32.to_le_bytes())
+            .special(1, 6, "log", DT_SOCK, S_IFSOCK | 0o666, &[])
+            .file(1, 7, "sparse", b"")
+            .inode(&InodeNode::file(7, 2, 8192, b"tail"))
+            .build();
+        let mut reader = Jffs2Reader::from_bytes(&image[..]).unwrap();
+        reader.scan().unwrap();
+
+        let mut tar = vec![];
+        reader.dump_tar(&mut tar).unwrap();
+        let mut archive = tar::Archive::new(&tar[..]);
+        let mut found = vec![];
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let path = entry.path().unwrap().to_string_lossy().trim_end_matches('/').to_string();
+            let header = entry.header().clone();
+            match path.as_str() {
+                "etc/passwd" => {
+                    assert_eq!(header.mode().unwrap(), 0o600);
+                    assert_eq!((header.uid().unwrap(), header.gid().unwrap()), (1000, 100));
+                    assert_eq!(header.mtime().unwrap(), 1_600_000_000);
+                    let mut data = vec![];
+                    entry.read_to_end(&mut data).unwrap();
+                    assert_eq!(data, b"root:x:0:0\n");
+                }
+                "passwd.bak" => {
+                    assert_eq!(header.entry_type(), EntryType::Link);
+                    assert_eq!(entry.link_name().unwrap().unwrap(), Path::new("etc/passwd"));
+                }
+                "motd" => {
+                    assert_eq!(header.entry_type(), EntryType::Symlink);
+                    assert_eq!(entry.link_name().unwrap().unwrap(), Path::new("etc/motd"));
+                }
+                "null" => {
+                    assert_eq!(header.entry_type(), EntryType::Char);
+                    assert_eq!(header.device_major().unwrap(), Some(1));
+                    assert_eq!(header.device_minor().unwrap(), Some(3));
+                }
+                "sparse" => {
+                    let mut data = vec![];
+                    entry.read_to_end(&mut data).unwrap();
+                    assert_eq!(data.len(), 8196);
+                    assert!(data[..8192].iter().all(|b| *b == 0));
+                }
+                _ => {}
+            }
+            found.push(path);
+        }
+        assert_eq!(found, ["etc", "etc/passwd", "motd", "null", "passwd.bak", "sparse"]);
+    }
+}
@@ -0,0 +1,101 @@
+//! The CRC32 of JFFS2.
+//!
+//! JFFS2 checksums every header, node and payload with the reflected CRC32
+//! polynomial (0xEDB88320), starting from a zero register and without the
+//! final inversion. This is what the kernel's `crc32(0, ...)` computes, and
+//! what zlib's `crc32` returns when seeded with 0xFFFFFFFF and xor-ed with
+//! 0xFFFFFFFF afterwards, so `crc32fast` with its defaults doesn't match.
+
+const fn make_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+static CRC_TABLE: [u32; 256] = make_table();
+
+/// Continue a JFFS2 CRC32 computation over `data`
+pub fn jffs2_crc32_update(crc: u32, data: &[u8]) -> u32 {
+    data.iter().fold(crc, |crc, b| {
+        CRC_TABLE[((crc ^ *b as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// CRC32 of `data` as stored in JFFS2 node headers
+pub fn jffs2_crc32(data: &[u8]) -> u32 {
+    jffs2_crc32_update(0, data)
+}
+
+/// Incremental [`jffs2_crc32`], for data that arrives in pieces. Bytes can
+/// also be written to it, e.g. with `std::io::copy`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Jffs2Crc32 {
+    crc: u32,
+}
+
+impl Jffs2Crc32 {
+    pub fn new() -> Self {
+        Jffs2Crc32::default()
+    }
+
+    /// Add `data` to the checksum
+    pub fn update(&mut self, data: &[u8]) {
+        self.crc = jffs2_crc32_update(self.crc, data);
+    }
+
+    /// Checksum of everything added so far
+    pub fn finalize(&self) -> u32 {
+        self.crc
+    }
+}
+
+impl std::io::Write for Jffs2Crc32 {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cleanmarker_hdr_crc() {
+        // the well-known 12 byte cleanmarker written by flash_erase -j
+        let header = [0x85, 0x19, 0x03, 0x20, 0x0c, 0x00, 0x00, 0x00];
+        assert_eq!(jffs2_crc32(&header), 0xe41e_b0b1);
+    }
+
+    #[test]
+    fn test_incremental() {
+        let data = b"the quick brown fox jumps over the lazy dog";
+        let mut hasher = Jffs2Crc32::new();
+        for chunk in data.chunks(7) {
+            hasher.update(chunk);
+        }
+        assert_eq!(hasher.finalize(), jffs2_crc32(data));
+
+        let mut hasher = Jffs2Crc32::new();
+        std::io::copy(&mut &data[..], &mut hasher).unwrap();
+        assert_eq!(hasher.finalize(), jffs2_crc32(data));
+    }
+}
@@ -0,0 +1,429 @@
+//! Graphviz export of the directory tree of a jffs2 image.
+//!
+//! Directories are drawn as folders and linked to their entries. Entries
+//! whose parent directory is missing from the image hang off a synthetic
+//! `lost+found` node, unlinked names are grayed out and symbolic links get a
+//! dashed edge to their target when it resolves inside the image. All the
+//! names of a file with hard links are filled in yellow, and each name after
+//! the first gets a dotted edge to the first one.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use crate::fs::{PathIndex, MAX_SYMLINK_HOPS};
+use crate::{Jffs2Dirent, Jffs2Reader, DT_BLK, DT_CHR, DT_DIR, DT_FIFO, DT_LNK, DT_REG, DT_SOCK};
+
+const LOST_FOUND: &str = "lost_found";
+/// Style of every name of a file with hard links
+const HARD_LINK: &str = "shape=box, style=filled, fillcolor=lightyellow";
+
+/// Controls how much of the tree [`Jffs2Reader::to_dot`] draws
+#[derive(Debug, Clone, Default)]
+pub struct DotOptions {
+    max_depth: Option<usize>,
+    collapse_files: bool,
+}
+
+impl DotOptions {
+    pub fn new() -> Self {
+        DotOptions::default()
+    }
+
+    /// Don't draw entries more than `depth` directories below the root,
+    /// truncated directories get a single node counting their entries
+    pub fn max_depth(mut self, depth: Option<usize>) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Replace the regular files of each directory by one node counting them
+    pub fn collapse_files(mut self, collapse: bool) -> Self {
+        self.collapse_files = collapse;
+        self
+    }
+}
+
+/// Escape `s` for a quoted DOT string, control characters are spelled out
+/// so a hostile file name can't break the label
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(escaped, "\\\\x{:02x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn type_name(ntype: u8) -> &'static str {
+    match ntype {
+        DT_DIR => "dir",
+        DT_REG => "file",
+        DT_LNK => "symlink",
+        DT_FIFO => "fifo",
+        DT_CHR => "chr",
+        DT_BLK => "blk",
+        DT_SOCK => "sock",
+        _ => "unknown",
+    }
+}
+
+struct DotWriter<'r, 'a> {
+    reader: &'r Jffs2Reader<'a>,
+    index: &'r PathIndex,
+    opts: &'r DotOptions,
+    out: String,
+    drawn: HashSet<u32>,
+    /// Number of extra names drawn for each file with hard links
+    links: HashMap<u32, usize>,
+    symlinks: Vec<(u32, u32)>,
+}
+
+impl<'r> DotWriter<'r, '_> {
+    /// Draw a node whose label shows `lines` one below the other
+    fn node(&mut self, id: &str, lines: &[&str], attrs: &str) {
+        let label: Vec<String> = lines.iter().map(|line| escape(line)).collect();
+        let _ = writeln!(
+            self.out,
+            "    {} [label=\"{}\", {}];",
+            id,
+            label.join("\\n"),
+            attrs
+        );
+    }
+
+    fn edge(&mut self, from: &str, to: &str, attrs: &str) {
+        if attrs.is_empty() {
+            let _ = writeln!(self.out, "    {} -> {};", from, to);
+        } else {
+            let _ = writeln!(self.out, "    {} -> {} [{}];", from, to, attrs);
+        }
+    }
+
+    /// Entries of a directory sorted by name
+    fn children(&self, ino: u32) -> Vec<&'r Jffs2Dirent> {
+        let mut children: Vec<_> = self.reader.children(ino).collect();
+        children.sort_by(|a, b| a.fname.cmp(&b.fname));
+        children
+    }
+
+    fn entry(&mut self, parent: &str, dirent: &Jffs2Dirent, depth: usize, edge_attrs: &str) {
+        let ino = dirent.ino;
+        let ntype = dirent.ntype;
+        let name = dirent.fname.to_string_lossy().into_owned();
+        if !self.drawn.insert(ino) {
+            // a directory loop is already in the graph, another name of a
+            // file points to the first one
+            if ntype != DT_DIR {
+                self.hard_link(parent, ino, &name, edge_attrs);
+            }
+            return;
+        }
+
+        let id = format!("n{}", ino);
+        let details = match ntype {
+            DT_DIR => format!("ino {} dir", ino),
+            _ => format!(
+                "ino {} {} {}",
+                ino,
+                type_name(ntype),
+                self.reader.file_size(ino)
+            ),
+        };
+        let attrs = match ntype {
+            DT_DIR => "shape=folder",
+            DT_LNK => "shape=box, style=rounded",
+            _ if self.index.names(ino) > 1 => HARD_LINK,
+            _ => "shape=box",
+        };
+        if ntype == DT_LNK {
+            self.symlinks.push((dirent.pino, ino));
+        }
+
+        self.node(&id, &[&name, &details], attrs);
+        self.edge(parent, &id, edge_attrs);
+        if ntype == DT_DIR {
+            self.directory(&id, ino, depth + 1);
+        }
+    }
+
+    /// Draw another name of the file `ino`, linked to the node of its first
+    /// name
+    fn hard_link(&mut self, parent: &str, ino: u32, name: &str, edge_attrs: &str) {
+        let count = self.links.entry(ino).or_default();
+        *count += 1;
+        let id = format!("n{}_link{}", ino, count);
+        let details = format!("ino {} hard link", ino);
+        self.node(&id, &[name, &details], HARD_LINK);
+        self.edge(parent, &id, edge_attrs);
+        self.edge(&id, &format!("n{}", ino), "style=dotted, constraint=false");
+    }
+
+    /// Draw the entries of directory `ino`, which sit `depth` levels below
+    /// the root
+    fn directory(&mut self, id: &str, ino: u32, depth: usize) {
+        let children = self.children(ino);
+
+        if self
+            .opts
+            .max_depth
+            .is_some_and(|max_depth| depth > max_depth)
+        {
+            if !children.is_empty() {
+                let more = format!("{}_more", id);
+                let label = format!("{} more", children.len());
+                self.node(&more, &[&label], "shape=plaintext");
+                self.edge(id, &more, "style=dotted");
+            }
+            return;
+        }
+
+        let mut files = 0;
+        for child in children {
+            if self.opts.collapse_files && child.ntype == DT_REG {
+                files += 1;
+                continue;
+            }
+            self.entry(id, child, depth, "");
+        }
+
+        if files > 0 {
+            let collapsed = format!("{}_files", id);
+            let label = format!("{} file{}", files, if files == 1 { "" } else { "s" });
+            self.node(&collapsed, &[&label], "shape=note");
+            self.edge(id, &collapsed, "");
+        }
+
+        let mut deleted: Vec<String> = match self.reader.deletions.get(&ino) {
+            Some(names) => names.keys().map(|name| name.to_string_lossy().into_owned()).collect(),
+            None => vec![],
+        };
+        deleted.sort();
+        for (i, name) in deleted.iter().enumerate() {
+            let deleted_id = format!("{}_deleted{}", id, i);
+            self.node(
+                &deleted_id,
+                &[name, "deleted"],
+                "shape=box, style=dashed, color=gray, fontcolor=gray",
+            );
+            self.edge(id, &deleted_id, "style=dashed, color=gray");
+        }
+    }
+
+    fn is_directory(&self, ino: u32) -> bool {
+        ino == self.reader.root_ino
+            || self
+                .reader
+                .dirent_of(ino)
+                .is_some_and(|dirent| dirent.ntype == DT_DIR)
+    }
+
+    /// True when following the parents of `ino` goes around in circles
+    /// instead of ending at the root or at a missing directory
+    fn in_loop(&self, ino: u32) -> bool {
+        let mut seen = HashSet::new();
+        let mut current = ino;
+        while seen.insert(current) {
+            if current == self.reader.root_ino || !self.is_directory(current) && current != ino {
+                return false;
+            }
+            match self.reader.dirent_of(current) {
+                Some(dirent) => current = dirent.pino,
+                None => return false,
+            }
+        }
+        true
+    }
+
+    /// Entries whose parent directory doesn't exist, plus directory loops
+    /// that can't be reached from the root
+    fn lost_found(&mut self) {
+        let mut orphans: Vec<&Jffs2Dirent> = self
+            .reader
+            .all_dirents()
+            .filter(|dirent| !self.is_directory(dirent.pino))
+            .collect();
+        orphans.sort_unstable_by_key(|dirent| (dirent.ino, &dirent.fname));
+
+        let mut loops: Vec<&Jffs2Dirent> = self
+            .reader
+            .all_dirents()
+            .filter(|dirent| self.in_loop(dirent.ino))
+            .collect();
+        loops.sort_unstable_by_key(|dirent| (dirent.ino, &dirent.fname));
+
+        if orphans.is_empty() && loops.is_empty() {
+            return;
+        }
+
+        self.node(
+            LOST_FOUND,
+            &["lost+found"],
+            "shape=folder, style=filled, fillcolor=lightpink",
+        );
+        for dirent in orphans.into_iter().chain(loops) {
+            if self.drawn.contains(&dirent.ino) {
+                continue;
+            }
+            self.entry(LOST_FOUND, dirent, 1, "color=red");
+        }
+    }
+
+    fn symlink_edges(&mut self) {
+        for (pino, ino) in std::mem::take(&mut self.symlinks) {
+            let target = match self.reader.link_target(ino) {
+                Ok(target) => target,
+                Err(_) => continue,
+            };
+            let resolved =
+                self.index
+                    .resolve_from(self.reader, pino, &target, true, MAX_SYMLINK_HOPS);
+            if let Ok((target, _)) = resolved {
+                if self.drawn.contains(&target) {
+                    self.edge(
+                        &format!("n{}", ino),
+                        &format!("n{}", target),
+                        "style=dashed, constraint=false",
+                    );
+                }
+            }
+        }
+    }
+}
+
+impl Jffs2Reader<'_> {
+    /// Describe the scanned directory tree as a Graphviz DOT graph
+    pub fn to_dot(&self, opts: &DotOptions) -> String {
+        let mut writer = DotWriter {
+            reader: self,
+            index: self.path_index(),
+            opts,
+            out: String::new(),
+            drawn: HashSet::new(),
+            links: HashMap::new(),
+            symlinks: Vec::new(),
+        };
+
+        writer.out.push_str("digraph jffs2 {\n");
+        writer.out.push_str("    node [fontname=monospace];\n");
+        let root = format!("n{}", self.root_ino);
+        let details = format!("ino {} dir", self.root_ino);
+        writer.node(&root, &["/", &details], "shape=folder");
+        writer.drawn.insert(self.root_ino);
+        writer.directory(&root, self.root_ino, 1);
+        writer.lost_found();
+        writer.symlink_edges();
+        writer.out.push_str("}\n");
+
+        writer.out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testutil::{write_temp_image, ImageBuilder};
+
+    fn sample_reader() -> Jffs2Reader<'static> {
+        let image = ImageBuilder::new()
+            .dir(1, 2, "etc")
+            .file(2, 3, "passwd", b"root:x:0:0\n")
+            .file(2, 4, "quote\"d\nname", b"")
+            .dir(1, 5, "bin")
+            .file(5, 6, "busybox", b"ELF")
+            .dirent(5, 6, 1, DT_REG, "ls")
+            .symlink(5, 7, "sh", "busybox")
+            .symlink(1, 8, "config", "/etc")
+            .file(99, 9, "stray", b"lost")
+            .dirent(2, 0, 2, DT_REG, "shadow")
+            .dir(2, 10, "init.d")
+            .file(10, 11, "rcS", b"#!/bin/sh\n")
+            .build();
+        let path = write_temp_image("dot", &image);
+        let mut reader = Jffs2Reader::new(&path).expect("Failed to open image");
+        reader.scan().expect("Failed to scan");
+        reader
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let dot = sample_reader().to_dot(&DotOptions::new());
+        assert_eq!(
+            dot,
+            r#"digraph jffs2 {
+    node [fontname=monospace];
+    n1 [label="/\nino 1 dir", shape=folder];
+    n5 [label="bin\nino 5 dir", shape=folder];
+    n1 -> n5;
+    n6 [label="busybox\nino 6 file 3", shape=box, style=filled, fillcolor=lightyellow];
+    n5 -> n6;
+    n6_link1 [label="ls\nino 6 hard link", shape=box, style=filled, fillcolor=lightyellow];
+    n5 -> n6_link1;
+    n6_link1 -> n6 [style=dotted, constraint=false];
+    n7 [label="sh\nino 7 symlink 7", shape=box, style=rounded];
+    n5 -> n7;
+    n8 [label="config\nino 8 symlink 4", shape=box, style=rounded];
+    n1 -> n8;
+    n2 [label="etc\nino 2 dir", shape=folder];
+    n1 -> n2;
+    n10 [label="init.d\nino 10 dir", shape=folder];
+    n2 -> n10;
+    n11 [label="rcS\nino 11 file 10", shape=box];
+    n10 -> n11;
+    n3 [label="passwd\nino 3 file 11", shape=box];
+    n2 -> n3;
+    n4 [label="quote\"d\\x0aname\nino 4 file 0", shape=box];
+    n2 -> n4;
+    n2_deleted0 [label="shadow\ndeleted", shape=box, style=dashed, color=gray, fontcolor=gray];
+    n2 -> n2_deleted0 [style=dashed, color=gray];
+    lost_found [label="lost+found", shape=folder, style=filled, fillcolor=lightpink];
+    n9 [label="stray\nino 9 file 4", shape=box];
+    lost_found -> n9 [color=red];
+    n7 -> n6 [style=dashed, constraint=false];
+    n8 -> n2 [style=dashed, constraint=false];
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_to_dot_collapsed() {
+        let opts = DotOptions::new().max_depth(Some(2)).collapse_files(true);
+        let dot = sample_reader().to_dot(&opts);
+        assert_eq!(
+            dot,
+            r#"digraph jffs2 {
+    node [fontname=monospace];
+    n1 [label="/\nino 1 dir", shape=folder];
+    n5 [label="bin\nino 5 dir", shape=folder];
+    n1 -> n5;
+    n7 [label="sh\nino 7 symlink 7", shape=box, style=rounded];
+    n5 -> n7;
+    n5_files [label="2 files", shape=note];
+    n5 -> n5_files;
+    n8 [label="config\nino 8 symlink 4", shape=box, style=rounded];
+    n1 -> n8;
+    n2 [label="etc\nino 2 dir", shape=folder];
+    n1 -> n2;
+    n10 [label="init.d\nino 10 dir", shape=folder];
+    n2 -> n10;
+    n10_more [label="1 more", shape=plaintext];
+    n10 -> n10_more [style=dotted];
+    n2_files [label="2 files", shape=note];
+    n2 -> n2_files;
+    n2_deleted0 [label="shadow\ndeleted", shape=box, style=dashed, color=gray, fontcolor=gray];
+    n2 -> n2_deleted0 [style=dashed, color=gray];
+    lost_found [label="lost+found", shape=folder, style=filled, fillcolor=lightpink];
+    n9 [label="stray\nino 9 file 4", shape=box];
+    lost_found -> n9 [color=red];
+    n8 -> n2 [style=dashed, constraint=false];
+}
+"#
+        );
+    }
+}
@@ -0,0 +1,140 @@
+// Which data node supplies each byte of a file. The nodes are laid over each
+// other in version order, so where they overlap the most recent one wins,
+// like the kernel's fragment tree.
+
+use std::collections::BTreeMap;
+
+/// A byte range of a file and the data node it comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Fragment {
+    /// File offset of the first byte
+    pub offset: u64,
+    pub len: u64,
+    /// Index of the node, in the order the nodes were inserted
+    pub node: usize,
+    /// Where the range starts in the decompressed data of the node
+    pub skip: u64,
+}
+
+impl Fragment {
+    fn end(&self) -> u64 {
+        self.offset + self.len
+    }
+}
+
+/// Non-overlapping fragments keyed by file offset
+#[derive(Debug, Default)]
+pub(crate) struct FragmentMap {
+    fragments: BTreeMap<u64, Fragment>,
+}
+
+impl FragmentMap {
+    pub fn new() -> Self {
+        FragmentMap::default()
+    }
+
+    /// Lay `len` bytes of `node` at file offset `offset` over what the
+    /// previous nodes supplied
+    pub fn insert(&mut self, offset: u64, len: u64, node: usize) {
+        if len == 0 {
+            return;
+        }
+        let end = offset + len;
+        // a fragment starting before the new one keeps its head
+        if let Some(mut before) = self.fragments.range(..offset).next_back().map(|(_, f)| *f) {
+            if before.end() > offset {
+                self.split_tail(&before, end);
+                before.len = offset - before.offset;
+                self.fragments.insert(before.offset, before);
+            }
+        }
+        let covered: Vec<Fragment> = self.fragments.range(offset..end).map(|(_, f)| *f).collect();
+        for fragment in covered {
+            self.fragments.remove(&fragment.offset);
+            self.split_tail(&fragment, end);
+        }
+        let fragment = Fragment {
+            offset,
+            len,
+            node,
+            skip: 0,
+        };
+        self.fragments.insert(offset, fragment);
+    }
+
+    /// Keep what `fragment` supplies past `end`
+    fn split_tail(&mut self, fragment: &Fragment, end: u64) {
+        if fragment.end() <= end {
+            return;
+        }
+        let cut = end - fragment.offset;
+        let tail = Fragment {
+            offset: end,
+            len: fragment.len - cut,
+            node: fragment.node,
+            skip: fragment.skip + cut,
+        };
+        self.fragments.insert(end, tail);
+    }
+
+    /// Drop everything past `size`
+    pub fn truncate(&mut self, size: u64) {
+        self.fragments.retain(|_, fragment| fragment.offset < size);
+        if let Some((_, last)) = self.fragments.iter_mut().next_back() {
+            last.len = last.len.min(size - last.offset);
+        }
+    }
+
+    /// The fragments ordered by file offset
+    pub fn into_fragments(self) -> Vec<Fragment> {
+        self.fragments.into_values().collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn fragment(offset: u64, len: u64, node: usize, skip: u64) -> Fragment {
+        Fragment {
+            offset,
+            len,
+            node,
+            skip,
+        }
+    }
+
+    #[test]
+    fn test_overlaps() {
+        let mut map = FragmentMap::new();
+        map.insert(0, 100, 0);
+        map.insert(40, 20, 1);
+        map.insert(50, 60, 2);
+        map.insert(200, 10, 3);
+        map.insert(0, 0, 4);
+        assert_eq!(
+            map.into_fragments(),
+            vec![
+                fragment(0, 40, 0, 0),
+                fragment(40, 10, 1, 0),
+                fragment(50, 60, 2, 0),
+                fragment(200, 10, 3, 0),
+            ]
+        );
+
+        let mut map = FragmentMap::new();
+        map.insert(0, 100, 0);
+        map.insert(10, 10, 1);
+        map.insert(90, 20, 2);
+        map.truncate(95);
+        assert_eq!(
+            map.into_fragments(),
+            vec![
+                fragment(0, 10, 0, 0),
+                fragment(10, 10, 1, 0),
+                fragment(20, 70, 0, 20),
+                fragment(90, 5, 2, 0),
+            ]
+        );
+    }
+}
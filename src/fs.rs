@@ -0,0 +1,542 @@
+//! A read-only, `std::fs`-like view of a jffs2 image.
+//!
+//! Paths are interpreted against the root of the image: `etc/passwd`,
+//! `/etc/passwd` and `./etc/../etc/passwd` all name the same file. Symbolic
+//! links are resolved within the image, absolute targets start over from the
+//! image root and relative ones from the directory holding the link.
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::io::{Error, ErrorKind};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+
+use crate::{Jffs2Reader, DT_DIR, DT_LNK, DT_REG};
+
+/// Maximum number of symbolic links followed while resolving one path,
+/// the same limit as Linux's MAXSYMLINKS
+pub(crate) const MAX_SYMLINK_HOPS: usize = 40;
+
+/// Type of an entry in the image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileType {
+    ntype: u8,
+}
+
+impl FileType {
+    pub fn is_dir(&self) -> bool {
+        self.ntype == DT_DIR
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.ntype == DT_REG
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.ntype == DT_LNK
+    }
+}
+
+/// Unix permission bits of an entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Permissions {
+    mode: u32,
+}
+
+impl Permissions {
+    /// Permission bits, including setuid/setgid/sticky
+    pub fn mode(&self) -> u32 {
+        self.mode & 0o7777
+    }
+
+    /// Returns true if nobody may write to the entry
+    pub fn readonly(&self) -> bool {
+        self.mode & 0o222 == 0
+    }
+}
+
+/// Metadata of an entry, see [`std::fs::Metadata`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    file_type: FileType,
+    len: u64,
+    mode: u32,
+    mtime: u32,
+}
+
+impl Metadata {
+    pub fn file_type(&self) -> FileType {
+        self.file_type
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.file_type.is_dir()
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.file_type.is_file()
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.file_type.is_symlink()
+    }
+
+    /// Size of the file contents in bytes, or of the link target for symlinks
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn permissions(&self) -> Permissions {
+        Permissions { mode: self.mode }
+    }
+
+    /// Last modification time
+    pub fn modified(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.mtime as u64)
+    }
+}
+
+/// An entry returned by [`Jffs2Fs::read_dir`]
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    path: PathBuf,
+    file_name: OsString,
+    metadata: Metadata,
+}
+
+impl DirEntry {
+    /// Path of the entry, made of the listed directory and the entry name
+    pub fn path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    pub fn file_name(&self) -> OsString {
+        self.file_name.clone()
+    }
+
+    pub fn file_type(&self) -> Result<FileType> {
+        Ok(self.metadata.file_type)
+    }
+
+    /// Metadata of the entry itself, symbolic links are not followed
+    pub fn metadata(&self) -> Result<Metadata> {
+        Ok(self.metadata.clone())
+    }
+}
+
+/// Iterator over the entries of a directory, sorted by name
+#[derive(Debug)]
+pub struct ReadDir {
+    entries: std::vec::IntoIter<DirEntry>,
+}
+
+impl Iterator for ReadDir {
+    type Item = Result<DirEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries.next().map(Ok)
+    }
+}
+
+/// A scanned jffs2 image exposing the usual read-only `std::fs` operations
+#[derive(Debug)]
+pub struct Jffs2Fs {
+    reader: Jffs2Reader<'static>,
+    follow_symlinks: bool,
+    max_symlink_hops: usize,
+}
+
+impl Jffs2Fs {
+    /// Open and scan a jffs2 image
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Jffs2Fs::from_reader(Jffs2Reader::open(path)?))
+    }
+
+    fn from_reader(reader: Jffs2Reader<'static>) -> Self {
+        Jffs2Fs {
+            reader,
+            follow_symlinks: true,
+            max_symlink_hops: MAX_SYMLINK_HOPS,
+        }
+    }
+
+    /// Whether symbolic links named by the last path component are followed,
+    /// links in the middle of a path are always followed
+    pub fn follow_symlinks(mut self, follow: bool) -> Self {
+        self.follow_symlinks = follow;
+        self
+    }
+
+    /// Maximum number of symbolic links followed while resolving one path
+    pub fn max_symlink_hops(mut self, hops: usize) -> Self {
+        self.max_symlink_hops = hops;
+        self
+    }
+
+    /// Read the entire contents of a file
+    pub fn read(&self, path: impl AsRef<Path>) -> Result<Vec<u8>> {
+        let (ino, ntype) = self.resolve(path.as_ref(), true)?;
+        if ntype == DT_DIR {
+            return Err(error(ErrorKind::Other, "is a directory", path.as_ref()));
+        }
+
+        self.reader.read_inode_data(ino)
+    }
+
+    /// Read the entire contents of a file into a string
+    pub fn read_to_string(&self, path: impl AsRef<Path>) -> Result<String> {
+        Ok(String::from_utf8(self.read(path)?)?)
+    }
+
+    /// Metadata of the entry at `path`, following a final symbolic link
+    /// unless [`Jffs2Fs::follow_symlinks`] was turned off
+    pub fn metadata(&self, path: impl AsRef<Path>) -> Result<Metadata> {
+        let (ino, ntype) = self.resolve(path.as_ref(), self.follow_symlinks)?;
+        Ok(self.metadata_of(ino, ntype))
+    }
+
+    /// Metadata of the entry at `path` without following a final symbolic link
+    pub fn symlink_metadata(&self, path: impl AsRef<Path>) -> Result<Metadata> {
+        let (ino, ntype) = self.resolve(path.as_ref(), false)?;
+        Ok(self.metadata_of(ino, ntype))
+    }
+
+    /// List the entries of a directory
+    pub fn read_dir(&self, path: impl AsRef<Path>) -> Result<ReadDir> {
+        let (ino, ntype) = self.resolve(path.as_ref(), true)?;
+        if ntype != DT_DIR {
+            return Err(error(ErrorKind::Other, "not a directory", path.as_ref()));
+        }
+
+        let mut entries = vec![];
+        for dirent in self.reader.children(ino) {
+            entries.push(DirEntry {
+                path: path.as_ref().join(&dirent.fname),
+                file_name: dirent.fname.clone(),
+                metadata: self.metadata_of(dirent.ino, dirent.ntype),
+            });
+        }
+        entries.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+
+        Ok(ReadDir {
+            entries: entries.into_iter(),
+        })
+    }
+
+    /// Target of a symbolic link, as stored in the image
+    pub fn read_link(&self, path: impl AsRef<Path>) -> Result<PathBuf> {
+        let (ino, ntype) = self.resolve(path.as_ref(), false)?;
+        if ntype != DT_LNK {
+            return Err(error(
+                ErrorKind::InvalidInput,
+                "not a symbolic link",
+                path.as_ref(),
+            ));
+        }
+
+        self.reader.link_target(ino)
+    }
+
+    /// Returns true if `path` names an existing entry, after following symbolic links
+    pub fn exists(&self, path: impl AsRef<Path>) -> bool {
+        self.resolve(path.as_ref(), true).is_ok()
+    }
+
+    fn metadata_of(&self, ino: u32, ntype: u8) -> Metadata {
+        let len = if ntype == DT_DIR {
+            0
+        } else {
+            self.reader.file_size(ino)
+        };
+
+        let (mode, mtime) = match self.reader.latest_inode(ino) {
+            Some(inode) => (inode.mode, inode.mtime),
+            None if ntype == DT_DIR => (0o755, 0),
+            None => (0o644, 0),
+        };
+
+        Metadata {
+            file_type: FileType { ntype },
+            len,
+            mode,
+            mtime,
+        }
+    }
+
+    fn resolve(&self, path: &Path, follow_last: bool) -> Result<(u32, u8)> {
+        self.reader
+            .path_index()
+            .resolve(&self.reader, path, follow_last, self.max_symlink_hops)
+    }
+}
+
+enum Step {
+    Root,
+    Parent,
+    Name(OsString),
+}
+
+/// Directory structure of a scanned image, used to resolve paths
+#[derive(Debug)]
+pub(crate) struct PathIndex {
+    /// Parent inode number and name of the dirent of each inode number, the
+    /// latest one for hard links
+    links: HashMap<u32, (u32, OsString)>,
+    /// How many dirents name each inode number
+    names: HashMap<u32, u32>,
+    root: u32,
+}
+
+impl PathIndex {
+    pub(crate) fn new(reader: &Jffs2Reader) -> Self {
+        let mut links: HashMap<u32, (u32, OsString)> = HashMap::new();
+        let mut versions = HashMap::new();
+        let mut names = HashMap::new();
+        for dirent in reader.all_dirents() {
+            *names.entry(dirent.ino).or_default() += 1;
+            let link = (dirent.version, dirent.pino, &dirent.fname);
+            let latest = versions.entry(dirent.ino).or_insert(link);
+            if link >= *latest {
+                *latest = link;
+                links.insert(dirent.ino, (dirent.pino, dirent.fname.clone()));
+            }
+        }
+
+        PathIndex {
+            links,
+            names,
+            root: reader.root_ino,
+        }
+    }
+
+    /// Parent inode number and name of a dirent of `ino`
+    pub(crate) fn link(&self, ino: u32) -> Option<&(u32, OsString)> {
+        self.links.get(&ino)
+    }
+
+    /// How many dirents name `ino`
+    pub(crate) fn names(&self, ino: u32) -> u32 {
+        self.names.get(&ino).copied().unwrap_or(0)
+    }
+
+    fn parent_of(&self, ino: u32) -> u32 {
+        match self.links.get(&ino) {
+            Some((pino, _)) if ino != self.root => *pino,
+            _ => self.root,
+        }
+    }
+
+    fn push_steps(steps: &mut Vec<Step>, path: &Path) {
+        for component in path.components().rev() {
+            match component {
+                Component::Prefix(_) | Component::RootDir => steps.push(Step::Root),
+                Component::CurDir => {}
+                Component::ParentDir => steps.push(Step::Parent),
+                Component::Normal(name) => {
+                    steps.push(Step::Name(name.to_os_string()))
+                }
+            }
+        }
+    }
+
+    /// Walk `path` from `start` and return the inode number and dirent type
+    /// it names, following at most `max_hops` symbolic links
+    pub(crate) fn resolve_from(
+        &self,
+        reader: &Jffs2Reader,
+        start: u32,
+        path: &Path,
+        follow_last: bool,
+        max_hops: usize,
+    ) -> Result<(u32, u8)> {
+        let mut steps = vec![];
+        PathIndex::push_steps(&mut steps, path);
+
+        let mut current = (start, DT_DIR);
+        let mut hops = 0;
+        while let Some(step) = steps.pop() {
+            let name = match step {
+                Step::Root => {
+                    current = (self.root, DT_DIR);
+                    continue;
+                }
+                Step::Parent => {
+                    if current.1 != DT_DIR {
+                        return Err(error(ErrorKind::Other, "not a directory", path));
+                    }
+                    current = (self.parent_of(current.0), DT_DIR);
+                    continue;
+                }
+                Step::Name(name) => name,
+            };
+
+            if current.1 != DT_DIR {
+                return Err(error(ErrorKind::Other, "not a directory", path));
+            }
+
+            let (ino, ntype) = match reader.dirent(current.0, &name) {
+                Some(dirent) => (dirent.ino, dirent.ntype),
+                None => {
+                    return Err(error(
+                        ErrorKind::NotFound,
+                        "no such file or directory",
+                        path,
+                    ))
+                }
+            };
+
+            if ntype == DT_LNK && (!steps.is_empty() || follow_last) {
+                hops += 1;
+                if hops > max_hops {
+                    return Err(error(
+                        ErrorKind::Other,
+                        "too many levels of symbolic links",
+                        path,
+                    ));
+                }
+
+                // the target replaces the link, relative targets start from
+                // the directory we are in
+                let target = reader.link_target(ino)?;
+                if target.as_os_str().is_empty() {
+                    return Err(error(ErrorKind::NotFound, "empty symbolic link", path));
+                }
+                PathIndex::push_steps(&mut steps, &target);
+                continue;
+            }
+
+            current = (ino, ntype);
+        }
+
+        Ok(current)
+    }
+
+    /// Walk `path` from the image root
+    pub(crate) fn resolve(
+        &self,
+        reader: &Jffs2Reader,
+        path: &Path,
+        follow_last: bool,
+        max_hops: usize,
+    ) -> Result<(u32, u8)> {
+        self.resolve_from(reader, self.root, path, follow_last, max_hops)
+    }
+}
+
+fn error(kind: ErrorKind, reason: &str, path: &Path) -> anyhow::Error {
+    Error::new(kind, format!("{}: {}", path.display(), reason)).into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testutil::*;
+
+    fn sample_fs() -> Jffs2Fs {
+        let mut builder = ImageBuilder::new();
+        builder.dir(1, 2, "etc");
+        builder.file(2, 3, "hostname", b"OpenWrt\n");
+        builder.dir(1, 4, "bin");
+        builder.file(4, 5, "busybox", b"\x7fELF");
+        builder.symlink(4, 6, "sh", "busybox");
+        builder.symlink(1, 7, "config", "/etc");
+        builder.symlink(2, 8, "name", "../etc/hostname");
+        builder.symlink(2, 9, "loop_a", "loop_b");
+        builder.symlink(2, 10, "loop_b", "./loop_a");
+        builder.symlink(2, 11, "dangling", "/nowhere");
+        builder.symlink(4, 12, "conf_link", "../config");
+        let path = write_temp_image("fs", &builder.build());
+
+        Jffs2Fs::open(&path).expect("Failed to open image")
+    }
+
+    #[test]
+    fn test_read() {
+        let fs = sample_fs();
+        assert_eq!(fs.read("etc/hostname").unwrap(), b"OpenWrt\n");
+        assert_eq!(fs.read_to_string("/etc/hostname").unwrap(), "OpenWrt\n");
+        assert_eq!(fs.read("./bin/../etc/hostname").unwrap(), b"OpenWrt\n");
+        assert!(fs.read("etc").is_err());
+
+        let err = fs.read("etc/shadow").unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Error>().unwrap().kind(),
+            ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn test_symlink_resolution() {
+        let fs = sample_fs();
+        // relative to the directory holding the link
+        assert_eq!(fs.read("bin/sh").unwrap(), b"\x7fELF");
+        assert_eq!(fs.read("etc/name").unwrap(), b"OpenWrt\n");
+        // absolute targets start from the image root
+        assert_eq!(fs.read("config/hostname").unwrap(), b"OpenWrt\n");
+        // chained links in the middle of a path
+        assert_eq!(fs.read("bin/conf_link/hostname").unwrap(), b"OpenWrt\n");
+        // `..` after a followed link goes to the parent of the target
+        assert_eq!(fs.read("config/../bin/busybox").unwrap(), b"\x7fELF");
+
+        assert!(!fs.exists("etc/dangling"));
+        assert!(fs.symlink_metadata("etc/dangling").unwrap().is_symlink());
+
+        let err = fs.read("etc/loop_a").unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("too many levels of symbolic links"));
+        let fs = fs.max_symlink_hops(1);
+        assert!(fs.read("bin/conf_link/hostname").is_err());
+        assert!(fs.read("bin/sh").is_ok());
+    }
+
+    #[test]
+    fn test_metadata_and_links() {
+        let fs = sample_fs();
+        let metadata = fs.metadata("bin/sh").unwrap();
+        assert!(metadata.is_file());
+        assert_eq!(metadata.len(), 4);
+        assert_eq!(metadata.permissions().mode(), 0o644);
+
+        assert!(fs.metadata("config").unwrap().is_dir());
+        assert!(fs.symlink_metadata("config").unwrap().is_symlink());
+        assert_eq!(fs.read_link("bin/sh").unwrap(), Path::new("busybox"));
+        assert_eq!(fs.read_link("/config").unwrap(), Path::new("/etc"));
+        assert!(fs.read_link("etc/hostname").is_err());
+
+        let fs = fs.follow_symlinks(false);
+        assert!(fs.metadata("bin/sh").unwrap().is_symlink());
+        // links in the middle of the path are still followed
+        assert!(fs.metadata("config/hostname").unwrap().is_file());
+    }
+
+    #[test]
+    fn test_read_dir() {
+        let fs = sample_fs();
+        let names: Vec<_> = fs
+            .read_dir("/bin")
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names, ["busybox", "conf_link", "sh"]);
+
+        let root: Vec<_> = fs
+            .read_dir("")
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(
+            root,
+            [Path::new("bin"), Path::new("config"), Path::new("etc")]
+        );
+
+        assert_eq!(fs.read_dir("config").unwrap().count(), 5);
+        assert!(fs.read_dir("etc/hostname").is_err());
+    }
+}
@@ -0,0 +1,167 @@
+// Storage behind a Jffs2Reader: a mapped file, a caller provided buffer, or
+// a seekable stream read on demand through a small read-ahead window. Any of
+// them can be seen through a layer exchanging the bytes of every 16 bit word.
+// A gzip-compressed file or buffer is decompressed into memory first.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::Mutex;
+
+use anyhow::{bail, Result};
+use memmap::MmapOptions;
+
+/// First bytes of a gzip stream
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Bytes fetched from a seekable stream at once, so the scan doesn't seek
+/// for every 4 byte step
+const READ_AHEAD: usize = 64 * 1024;
+
+pub(crate) trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek> ReadSeek for T {}
+
+pub(crate) struct SeekableImage<'a> {
+    base: u64,
+    len: u64,
+    state: Mutex<ReadAhead<'a>>,
+}
+
+struct ReadAhead<'a> {
+    inner: Box<dyn ReadSeek + Send + 'a>,
+    start: u64,
+    data: Vec<u8>,
+}
+
+impl<'a> SeekableImage<'a> {
+    /// The image is the `len` bytes following the current position of `r`
+    pub(crate) fn new(mut r: impl Read + Seek + Send + 'a, len: u64) -> Result<Self> {
+        let base = r.stream_position()?;
+        Ok(SeekableImage {
+            base,
+            len,
+            state: Mutex::new(ReadAhead {
+                inner: Box::new(r),
+                start: 0,
+                data: Vec::new(),
+            }),
+        })
+    }
+
+    fn read(&self, offset: u64, len: usize) -> Result<Vec<u8>> {
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        let end = offset + len as u64;
+        let cached = state.start..state.start + state.data.len() as u64;
+        if offset >= cached.start && end <= cached.end {
+            let from = (offset - state.start) as usize;
+            return Ok(state.data[from..from + len].to_vec());
+        }
+
+        state.inner.seek(SeekFrom::Start(self.base + offset))?;
+        if len > READ_AHEAD {
+            let mut data = vec![0; len];
+            state.inner.read_exact(&mut data)?;
+            return Ok(data);
+        }
+
+        let window = (READ_AHEAD as u64).min(self.len - offset) as usize;
+        let mut data = std::mem::take(&mut state.data);
+        data.resize(window, 0);
+        state.inner.read_exact(&mut data)?;
+        state.start = offset;
+        state.data = data;
+        Ok(state.data[..len].to_vec())
+    }
+}
+
+impl fmt::Debug for SeekableImage<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SeekableImage")
+            .field("base", &self.base)
+            .field("len", &self.len)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Bytes of the image, either mapped from a file, handed over by the caller
+/// or read from a stream when needed
+#[derive(Debug)]
+pub(crate) enum ImageBuffer<'a> {
+    Mapped(memmap::Mmap),
+    Bytes(Cow<'a, [u8]>),
+    Seekable(SeekableImage<'a>),
+    /// The inner image with the two bytes of every 16 bit word exchanged
+    Swapped(Box<ImageBuffer<'a>>),
+}
+
+impl ImageBuffer<'_> {
+    pub(crate) fn map(path: impl AsRef<Path>) -> Result<Self> {
+        let file = File::open(path)?;
+        let buffer = unsafe { MmapOptions::new().map(&file)? };
+        Ok(ImageBuffer::Mapped(buffer))
+    }
+
+    /// The decompressed image if this one is gzip-compressed, as images are
+    /// often distributed. Streams are left alone.
+    pub(crate) fn gunzip(self) -> Result<Self> {
+        let data: &[u8] = match &self {
+            ImageBuffer::Mapped(mmap) => mmap,
+            ImageBuffer::Bytes(bytes) => bytes,
+            _ => &[],
+        };
+        if !data.starts_with(&GZIP_MAGIC) {
+            return Ok(self);
+        }
+        let mut image = Vec::new();
+        if let Err(e) = flate2::read::MultiGzDecoder::new(data).read_to_end(&mut image) {
+            bail!("cannot decompress the gzip-compressed image: {}", e);
+        }
+        Ok(ImageBuffer::Bytes(Cow::Owned(image)))
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            ImageBuffer::Mapped(mmap) => mmap.len(),
+            ImageBuffer::Bytes(bytes) => bytes.len(),
+            ImageBuffer::Seekable(image) => image.len as usize,
+            ImageBuffer::Swapped(inner) => inner.len(),
+        }
+    }
+
+    /// `len` bytes at `offset`, borrowed when the whole image is in memory
+    pub(crate) fn read(&self, offset: usize, len: usize) -> Result<Cow<'_, [u8]>> {
+        if offset.checked_add(len).is_none_or(|end| end > self.len()) {
+            bail!(
+                "read of {} bytes at offset {} is out of an image of {} bytes",
+                len,
+                offset,
+                self.len()
+            );
+        }
+
+        match self {
+            ImageBuffer::Mapped(mmap) => Ok(Cow::Borrowed(&mmap[offset..offset + len])),
+            ImageBuffer::Bytes(bytes) => Ok(Cow::Borrowed(&bytes[offset..offset + len])),
+            ImageBuffer::Seekable(image) => Ok(Cow::Owned(image.read(offset as u64, len)?)),
+            ImageBuffer::Swapped(inner) => {
+                // whole words around the range, a trailing odd byte stays alone
+                let start = offset & !1;
+                let end = ((offset + len + 1) & !1).min(inner.len());
+                let mut data = inner.read(start, end - start)?.into_owned();
+                for word in data.chunks_exact_mut(2) {
+                    word.swap(0, 1);
+                }
+                data.drain(..offset - start);
+                data.truncate(len);
+                Ok(Cow::Owned(data))
+            }
+        }
+    }
+}
@@ -0,0 +1,350 @@
+//! On-disk cache of the scan results.
+//!
+//! An index holds the dirent and inode tables of one image together with a
+//! fingerprint of that image, so reopening a large image can skip the scan.
+//! The fingerprint is the image size plus a CRC over sampled regions; an
+//! index whose fingerprint doesn't match is rejected with [`StaleIndex`].
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::OnceLock;
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::crc::jffs2_crc32_update;
+use crate::{
+    DataCrcCheck, EndiannessDetection, ImageBuffer, Jffs2Dirent, Jffs2Inode, Jffs2Reader,
+    Jffs2Xattr, LzmaOptions, NameCrcCheck, NodeCrcCheck, ScanStats,
+};
+
+const INDEX_MAGIC: &[u8; 8] = b"JFFS2IDX";
+const INDEX_VERSION: u32 = 1;
+
+const SAMPLE_SIZE: usize = 4096;
+const SAMPLE_COUNT: usize = 64;
+
+/// Identifies the image an index was built from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fingerprint {
+    size: u64,
+    samples_crc: u32,
+}
+
+impl Fingerprint {
+    /// Fingerprint `image`, small images are hashed whole and larger ones
+    /// through evenly spaced samples including the first and last block
+    pub(crate) fn of(image: &ImageBuffer) -> Result<Self> {
+        let samples_crc = if image.len() <= SAMPLE_SIZE * SAMPLE_COUNT {
+            jffs2_crc32_update(0, &image.read(0, image.len())?)
+        } else {
+            let stride = (image.len() - SAMPLE_SIZE) / (SAMPLE_COUNT - 1);
+            let mut crc = 0;
+            for i in 0..SAMPLE_COUNT {
+                crc = jffs2_crc32_update(crc, &image.read(i * stride, SAMPLE_SIZE)?);
+            }
+            crc
+        };
+
+        Ok(Fingerprint {
+            size: image.len() as u64,
+            samples_crc,
+        })
+    }
+
+    /// Size of the fingerprinted image in bytes
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+/// The index was built for a different image, or the image changed since
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StaleIndex {
+    expected: Fingerprint,
+    found: Fingerprint,
+}
+
+impl StaleIndex {
+    /// Fingerprint stored in the index
+    pub fn expected(&self) -> Fingerprint {
+        self.expected
+    }
+
+    /// Fingerprint of the image being opened
+    pub fn found(&self) -> Fingerprint {
+        self.found
+    }
+}
+
+impl fmt::Display for StaleIndex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "stale index: built for a {} byte image with sample crc {:#010x}, \
+             found a {} byte image with sample crc {:#010x}",
+            self.expected.size, self.expected.samples_crc, self.found.size, self.found.samples_crc
+        )
+    }
+}
+
+impl std::error::Error for StaleIndex {}
+
+#[derive(Serialize, Deserialize)]
+struct Index {
+    fingerprint: Fingerprint,
+    little_endian: bool,
+    detection: EndiannessDetection,
+    settings: Settings,
+    stats: ScanStats,
+    skipped_nodes: Vec<u64>,
+    node_crc_failures: Vec<u64>,
+    name_crc_failures: Vec<u64>,
+    dirents: HashMap<u32, HashMap<OsString, Jffs2Dirent>>,
+    inodes: HashMap<u32, Vec<Jffs2Inode>>,
+    xattrs: HashMap<u32, Jffs2Xattr>,
+    xrefs: HashMap<u32, HashMap<u32, u32>>,
+    deletions: HashMap<u32, HashMap<OsString, u32>>,
+}
+
+/// The builder options the scan results and later reads depend on
+#[derive(Serialize, Deserialize)]
+struct Settings {
+    lenient: bool,
+    data_crc: Option<DataCrcCheck>,
+    node_crc: Option<NodeCrcCheck>,
+    name_crc: Option<NameCrcCheck>,
+    include_deleted: bool,
+    lzma: LzmaOptions,
+    summaries: bool,
+    erase_block_size: Option<usize>,
+    root_ino: u32,
+    max_depth: usize,
+    max_file_size: u64,
+    max_total_size: u64,
+}
+
+impl Jffs2Reader<'_> {
+    /// Save the scan results to `path` so the image can be reopened with
+    /// [`Jffs2Reader::open_with_index`] without scanning it again
+    pub fn save_index(&self, path: impl AsRef<Path>) -> Result<()> {
+        let index = Index {
+            fingerprint: Fingerprint::of(&self.buffer)?,
+            little_endian: self.little_endian,
+            detection: self.detection,
+            settings: Settings {
+                lenient: self.lenient,
+                data_crc: self.data_crc,
+                node_crc: self.node_crc,
+                name_crc: self.name_crc.clone(),
+                include_deleted: self.include_deleted,
+                lzma: self.lzma,
+                summaries: self.summaries,
+                erase_block_size: self.erase_block_size,
+                root_ino: self.root_ino,
+                max_depth: self.max_depth,
+                max_file_size: self.max_file_size,
+                max_total_size: self.max_total_size,
+            },
+            stats: self.stats,
+            skipped_nodes: self.skipped_nodes.clone(),
+            node_crc_failures: self.node_crc_failures.clone(),
+            name_crc_failures: self.name_crc_failures.clone(),
+            dirents: self.dirents.clone(),
+            inodes: self.inodes.clone(),
+            xattrs: self.xattrs.clone(),
+            xrefs: self.xrefs.clone(),
+            deletions: self.deletions.clone(),
+        };
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(INDEX_MAGIC)?;
+        writer.write_all(&INDEX_VERSION.to_le_bytes())?;
+        bincode::serialize_into(&mut writer, &index)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Open `image` with the scan results saved in `index`, and the reader
+    /// options they were scanned with. Fails with [`StaleIndex`] if the index
+    /// doesn't belong to this image.
+    pub fn open_with_index(image: impl AsRef<Path>, index: impl AsRef<Path>) -> Result<Self> {
+        let mut reader = BufReader::new(File::open(index)?);
+        let mut magic = [0u8; 8];
+        if reader.read_exact(&mut magic).is_err() || &magic != INDEX_MAGIC {
+            bail!("not a jffs2 index");
+        }
+        let mut version = [0u8; 4];
+        reader.read_exact(&mut version)?;
+        let version = u32::from_le_bytes(version);
+        if version != INDEX_VERSION {
+            bail!(
+                "unsupported index version {}, expected {}",
+                version,
+                INDEX_VERSION
+            );
+        }
+        let index: Index = bincode::deserialize_from(reader)?;
+
+        let mut buffer = ImageBuffer::map(image)?.gunzip()?;
+        if index.detection.swapped() {
+            buffer = ImageBuffer::Swapped(Box::new(buffer));
+        }
+        let found = Fingerprint::of(&buffer)?;
+        if found != index.fingerprint {
+            return Err(StaleIndex {
+                expected: index.fingerprint,
+                found,
+            }
+            .into());
+        }
+
+        let settings = index.settings;
+        Ok(Jffs2Reader {
+            buffer,
+            little_endian: index.little_endian,
+            detection: index.detection,
+            lenient: settings.lenient,
+            data_crc: settings.data_crc,
+            data_crc_mismatches: Default::default(),
+            node_crc: settings.node_crc,
+            node_crc_failures: index.node_crc_failures,
+            name_crc: settings.name_crc,
+            name_crc_failures: index.name_crc_failures,
+            skipped_nodes: index.skipped_nodes,
+            stats: index.stats,
+            include_deleted: settings.include_deleted,
+            lzma: settings.lzma,
+            summaries: settings.summaries,
+            erase_block_size: settings.erase_block_size,
+            root_ino: settings.root_ino,
+            max_depth: settings.max_depth,
+            max_file_size: settings.max_file_size,
+            max_total_size: settings.max_total_size,
+            scanned: true,
+            dirents: index.dirents,
+            inodes: index.inodes,
+            xattrs: index.xattrs,
+            xrefs: index.xrefs,
+            deletions: index.deletions,
+            paths: OnceLock::new(),
+            progress: Default::default(),
+            cancel: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testutil::*;
+
+    fn listing(reader: &Jffs2Reader) -> Vec<(String, bool, u64)> {
+        let mut listing: Vec<_> = reader
+            .entries()
+            .expect("Failed to list entries")
+            .iter()
+            .map(|entry| {
+                (
+                    entry.path().display().to_string(),
+                    entry.is_file(),
+                    entry.size(),
+                )
+            })
+            .collect();
+        listing.sort();
+        listing
+    }
+
+    #[test]
+    fn test_not_an_index() {
+        let image = ImageBuilder::new().file(1, 2, "README", b"hello").build();
+        let image_path = write_temp_image("not-an-index", &image);
+        // too short for the magic, then an image where the index belongs
+        let short_path = write_temp_image("not-an-index-short", b"JFF");
+        for index_path in [&short_path, &image_path] {
+            let err = Jffs2Reader::open_with_index(&image_path, index_path)
+                .expect_err("Not an index accepted");
+            assert_eq!(err.to_string(), "not a jffs2 index");
+        }
+    }
+
+    #[test]
+    fn test_index_roundtrip() {
+        let image = ImageBuilder::new()
+            .dir(1, 2, "etc")
+            .file(2, 3, "passwd", b"root:x:0:0\n")
+            .file(1, 4, "README", b"hello")
+            .build();
+        let image_path = write_temp_image("index", &image);
+        let index_path = image_path.with_extension("idx");
+
+        let mut reader = Jffs2Reader::new(&image_path).expect("Failed to open image");
+        reader.scan().expect("Failed to scan");
+        reader
+            .save_index(&index_path)
+            .expect("Failed to save index");
+
+        let cached = Jffs2Reader::open_with_index(&image_path, &index_path)
+            .expect("Failed to open with index");
+        assert_eq!(listing(&cached), listing(&reader));
+        assert_eq!(
+            cached.read_inode_data(3).expect("Failed to read"),
+            b"root:x:0:0\n"
+        );
+
+        let mut changed = image.clone();
+        let last = changed.len() - 1;
+        changed[last] ^= 0xff;
+        std::fs::write(&image_path, &changed).expect("Failed to write image");
+        let err = Jffs2Reader::open_with_index(&image_path, &index_path)
+            .expect_err("Stale index accepted");
+        let stale = err.downcast_ref::<StaleIndex>().expect("Not a stale index");
+        let fingerprint =
+            |image: &[u8]| Fingerprint::of(&ImageBuffer::Bytes(image.into())).unwrap();
+        assert_eq!(stale.expected(), fingerprint(&image));
+        assert_eq!(stale.found(), fingerprint(&changed));
+        std::fs::remove_file(index_path).unwrap();
+
+        // the reader options go with the scan results
+        let data = b"::sysinit:/etc/init.d/rcS\n".repeat(9);
+        let mut inode = InodeNode::file(3, 1, 0, &lzma_compress(&data));
+        inode.compr = crate::Compression::Lzma as u8;
+        inode.dsize = data.len() as u32;
+        inode.isize = data.len() as u32;
+        let image = ImageBuilder::new()
+            .dir(1, 2, "etc")
+            .dirent(2, 3, 1, crate::DT_REG, "inittab")
+            .inode(&inode)
+            .build();
+        let image_path = write_temp_image("index-settings", &image);
+        let index_path = image_path.with_extension("idx");
+
+        // the stream doesn't use the mkfs.jffs2 parameters
+        let lzma = crate::LzmaOptions::new().lc(3).pb(2).dict_size(0x80_0000);
+        let mut reader = Jffs2Reader::builder(&image_path)
+            .lzma_options(lzma)
+            .root_inode(2)
+            .max_file_size(4096)
+            .build()
+            .expect("Failed to open image");
+        reader.scan().expect("Failed to scan");
+        reader.save_index(&index_path).expect("Failed to save index");
+
+        let cached = Jffs2Reader::open_with_index(&image_path, &index_path)
+            .expect("Failed to open with index");
+        assert_eq!(listing(&cached), listing(&reader));
+        let listed = listing(&cached);
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].0.trim_end_matches('/'), "inittab");
+        assert_eq!(cached.read_file("inittab").expect("Failed to read"), data);
+        assert_eq!(cached.stats(), reader.stats());
+        assert_eq!(cached.stats().inodes(), 2);
+        assert_eq!(cached.max_file_size, 4096);
+        std::fs::remove_file(index_path).unwrap();
+    }
+}
@@ -1,37 +1,97 @@
+use std::borrow::Cow;
+use std::ffi::{OsStr, OsString};
 use std::path::Path;
 
 use std::fs::File;
 use std::io::prelude::*;
 
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use anyhow::{bail, Result};
+use anyhow::{anyhow, bail, Context, Result};
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 
 use lexiclean::Lexiclean;
 use lzma_rs::lzma_decompress;
-use memmap::MmapOptions;
 
 use byteorder_pack::UnpackFrom;
 
-const JFFS2_NODETYPE_DIRENT: u16 = 0xE001;
-const JFFS2_NODETYPE_INODE: u16 = 0xE002;
+#[cfg(feature = "tar")]
+mod archive;
+mod frag;
+mod image;
+mod names;
+pub mod crc;
+pub mod dot;
+pub mod fs;
+#[cfg(feature = "index-cache")]
+pub mod index;
+pub mod owners;
+pub mod rtime;
+pub mod verify;
+#[cfg(test)]
+mod testutil;
+
+use crc::jffs2_crc32;
+use frag::{Fragment, FragmentMap};
+use image::{ImageBuffer, SeekableImage};
+
+const JFFS2_MAGIC: u16 = 0x1985;
+
+/// Magic of the marker closing an erase block that carries a summary
+const JFFS2_SUM_MAGIC: u32 = 0x0285_1885;
+/// Fixed part of a summary node, up to and including its node CRC
+const SIZE_OF_SUMMARY: usize = 32;
+/// Erase block sizes tried when looking for summaries, 8KB to 2MB
+const ERASE_BLOCK_SIZES: std::ops::RangeInclusive<u32> = 13..=21;
 
+const DT_FIFO: u8 = 1;
+const DT_CHR: u8 = 2;
 const DT_DIR: u8 = 4;
+const DT_BLK: u8 = 6;
 const DT_REG: u8 = 8;
+const DT_LNK: u8 = 10;
+const DT_SOCK: u8 = 12;
 
-const JFFS2_COMPR_NONE: u8 = 0x00;
-const JFFS2_COMPR_ZERO: u8 = 0x01;
-const JFFS2_COMPR_RTIME: u8 = 0x02;
-const JFFS2_COMPR_RUBINMIPS: u8 = 0x03;
-const JFFS2_COMPR_COPY: u8 = 0x04;
-const JFFS2_COMPR_DYNRUBIN: u8 = 0x05;
-const JFFS2_COMPR_ZLIB: u8 = 0x06;
-const JFFS2_COMPR_LZO: u8 = 0x07;
-const JFFS2_COMPR_LZMA: u8 = 0x08;
+const S_IFMT: u32 = 0o170000;
+const S_IFSOCK: u32 = 0o140000;
+const S_IFLNK: u32 = 0o120000;
+const S_IFREG: u32 = 0o100000;
+const S_IFBLK: u32 = 0o060000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFCHR: u32 = 0o020000;
+const S_IFIFO: u32 = 0o010000;
 
+/// Inode number of the root directory
+const ROOT_INO: u32 = 1;
+/// Inode number given to the directory holding the quarantined dirents
+const QUARANTINE_INO: u32 = u32::MAX;
+
+const SIZE_OF_NODE_HEADER: usize = 12;
 const SIZE_OF_DIRENT: usize = 28;
 const SIZE_OF_INODE: usize = 56;
+const SIZE_OF_XATTR: usize = 20;
+const SIZE_OF_XREF: usize = 16;
+
+/// Version of a deleted xattr
+const XATTR_DELETE_MARKER: u32 = 0xffff_ffff;
+/// Set in the sequence number of a deleted xref
+const XREF_DELETE_MARKER: u32 = 1;
+
+const JFFS2_XPREFIX_USER: u8 = 1;
+const JFFS2_XPREFIX_SECURITY: u8 = 2;
+const JFFS2_XPREFIX_ACL_ACCESS: u8 = 3;
+const JFFS2_XPREFIX_ACL_DEFAULT: u8 = 4;
+const JFFS2_XPREFIX_TRUSTED: u8 = 5;
+
+const JFFS2_ACL_VERSION: u32 = 1;
+const POSIX_ACL_XATTR_VERSION: u32 = 2;
+const ACL_USER: u16 = 0x02;
+const ACL_GROUP: u16 = 0x08;
+const ACL_UNDEFINED_ID: u32 = 0xffff_ffff;
 
 const LZMA_BEST_LC: u8 = 0;
 const LZMA_BEST_LP: u8 = 0;
@@ -39,9 +99,49 @@ const LZMA_BEST_PB: u8 = 0;
 
 const DICT_SIZE: u32 = 0x2000;
 
-use std::os::raw::{c_int, c_uchar, c_uint, c_void};
+/// Written for the JFFS2_COMPR_ZERO nodes and filled holes instead of
+/// allocating their data
+static ZERO_PAGE: [u8; 4096] = [0; 4096];
+
+/// A page of erased flash, skipped at once by the scan like a zeroed one
+static ERASED_PAGE: [u8; 4096] = [0xff; 4096];
+
+/// Most the scan skips over empty pages before looking at the progress
+/// and cancellation again
+const EMPTY_SKIP_LIMIT: usize = 64 * 1024;
+
+/// Largest run of zeros [`Jffs2File`] produces at once for a hole
+const HOLE_CHUNK_SIZE: u64 = 64 * 1024;
+
+/// Default of [`Jffs2ReaderBuilder::max_depth`]
+const DEFAULT_MAX_DEPTH: usize = 256;
+
+/// Default of [`Jffs2ReaderBuilder::max_file_size`]
+const DEFAULT_MAX_FILE_SIZE: u64 = 1 << 30;
+
+/// Default of [`Jffs2ReaderBuilder::max_total_size`]
+const DEFAULT_MAX_TOTAL_SIZE: u64 = 16 << 30;
+
+/// How much of the image is inspected to guess its byte order
+const ENDIANNESS_DETECTION_WINDOW: usize = 64 * 1024;
+
+/// How often, in scanned bytes, the scan loop looks at the clock
+/// to decide whether a time-based progress report is due
+const SCAN_PROGRESS_CLOCK_CHECK: u64 = 64 * 1024;
+
+/// How often [`Jffs2Reader::scan`] reports to a progress hook
+const HOOK_PROGRESS_BYTES: u64 = 1024 * 1024;
+const HOOK_PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Number of scan steps between two looks at the cancellation token
+const CANCEL_CHECK_STEPS: u32 = 4096;
+
+#[cfg(feature = "rubin")]
+use std::os::raw::c_uint;
+use std::os::raw::{c_int, c_uchar, c_void};
 use std::path::Component;
 
+#[cfg(feature = "rubin")]
 extern "C" {
 
     fn dynrubin_decompress(
@@ -51,6 +151,16 @@ extern "C" {
         dstlen: c_uint,
     ) -> c_void;
 
+    fn rubinmips_decompress(
+        data_in: *const c_uchar,
+        cpage_out: *const c_uchar,
+        sourcelen: c_uint,
+        dstlen: c_uint,
+    ) -> c_void;
+}
+
+extern "C" {
+
     fn lzo1x_decompress_safe(
         in_data: *const c_uchar,
         in_len: usize,
@@ -71,7 +181,7 @@ impl JffsPathFixer for &Path {
         }
 
         let mut components = Vec::new();
-        let last = &self.components().last();
+        let last = &self.components().next_back();
         let mut ignore_last = false;
         if let Some(Component::Normal(a)) = last {
             if a.is_empty() {
@@ -91,7 +201,403 @@ impl JffsPathFixer for &Path {
     }
 }
 
+/// Byte order of the on-flash structures
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+/// Outcome of the byte order detection pass
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "index-cache", derive(serde::Serialize, serde::Deserialize))]
+pub struct EndiannessDetection {
+    little_endian_nodes: usize,
+    big_endian_nodes: usize,
+    overridden: bool,
+    swapped: bool,
+}
+
+impl EndiannessDetection {
+    /// Valid node headers found when reading the image as little-endian
+    pub fn little_endian_nodes(&self) -> usize {
+        self.little_endian_nodes
+    }
+
+    /// Valid node headers found when reading the image as big-endian
+    pub fn big_endian_nodes(&self) -> usize {
+        self.big_endian_nodes
+    }
+
+    /// Share of the valid node headers supporting the chosen byte order,
+    /// between 0.0 and 1.0
+    pub fn confidence(&self, endianness: Endianness) -> f64 {
+        let total = self.little_endian_nodes + self.big_endian_nodes;
+        if total == 0 {
+            return 0.0;
+        }
+
+        let support = match endianness {
+            Endianness::Little => self.little_endian_nodes,
+            Endianness::Big => self.big_endian_nodes,
+        };
+        support as f64 / total as f64
+    }
+
+    /// Returns true if the byte order was forced by the caller
+    /// rather than picked from the counts
+    pub fn overridden(&self) -> bool {
+        self.overridden
+    }
+
+    /// Returns true if the two bytes of every 16 bit word of the image are
+    /// exchanged, as in NAND dumps read through a programmer of the other
+    /// byte order. The node counts are those of the image once restored.
+    pub fn swapped(&self) -> bool {
+        self.swapped
+    }
+}
+
+/// What the scan does with a dirent or inode whose node CRC doesn't match
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "index-cache", derive(serde::Serialize, serde::Deserialize))]
+pub enum NodeCrcCheck {
+    /// Leave the node out, as if it wasn't there
+    Skip,
+    /// Use the node anyway, it is only reported
+    Keep,
+    /// Fail the scan
+    Fail,
+}
+
+/// What the scan does with a dirent whose name doesn't match its CRC
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "index-cache", derive(serde::Serialize, serde::Deserialize))]
+pub enum NameCrcCheck {
+    /// Leave the dirent out, its file can no longer be reached
+    Drop,
+    /// Move the file to `ino_<N>` in a directory of this name at the root,
+    /// `__corrupt__` for instance
+    Quarantine(String),
+}
+
+/// What reading a data node whose data CRC doesn't match does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "index-cache", derive(serde::Serialize, serde::Deserialize))]
+pub enum DataCrcCheck {
+    /// Fail the read or the extraction of the file
+    Fail,
+    /// Read the node as zeros
+    ZeroFill,
+    /// Log a warning and decompress the node anyway
+    Warn,
+}
+
+/// A data node whose compressed data didn't match its CRC, see
+/// [`Jffs2Reader::data_crc_mismatches`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataCrcMismatch {
+    path: PathBuf,
+    offset: u64,
+    expected: u32,
+    actual: u32,
+}
+
+impl DataCrcMismatch {
+    /// Path of the file the node belongs to, empty if it has no dirent
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Offset of the node in the image
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// CRC stored in the node
+    pub fn expected(&self) -> u32 {
+        self.expected
+    }
+
+    /// CRC of the data as read
+    pub fn actual(&self) -> u32 {
+        self.actual
+    }
+}
+
+/// A dirent left out of [`Jffs2Reader::entries_lossy`], its parent missing
+/// or in a cycle
+#[derive(Debug)]
+pub struct ResolveError {
+    pino: u32,
+    ino: u32,
+    name: OsString,
+    error: anyhow::Error,
+}
+
+impl ResolveError {
+    /// Inode number of the parent directory the dirent names
+    pub fn pino(&self) -> u32 {
+        self.pino
+    }
+
+    /// Inode number of the entry
+    pub fn ino(&self) -> u32 {
+        self.ino
+    }
+
+    /// Name of the entry in its parent directory
+    pub fn name(&self) -> &OsStr {
+        &self.name
+    }
+
+    /// Why the entry could not be listed
+    pub fn error(&self) -> &anyhow::Error {
+        &self.error
+    }
+}
+
+/// General information about an opened image
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImageInfo {
+    size: u64,
+    endianness: Endianness,
+    detection: EndiannessDetection,
+}
+
+impl ImageInfo {
+    /// Size of the image in bytes
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Byte order used to parse the image
+    pub fn endianness(&self) -> Endianness {
+        self.endianness
+    }
+
+    /// Node counts behind the byte order decision
+    pub fn detection(&self) -> &EndiannessDetection {
+        &self.detection
+    }
+
+    /// Confidence of the byte order decision, 1.0 when it was overridden
+    pub fn confidence(&self) -> f64 {
+        if self.detection.overridden {
+            1.0
+        } else {
+            self.detection.confidence(self.endianness)
+        }
+    }
+}
+
+/// Snapshot handed to the scan progress callback
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScanProgress {
+    offset: u64,
+    bytes_scanned: u64,
+    nodes: u64,
+    image_size: u64,
+}
+
+impl ScanProgress {
+    /// Current position of the scan within the image
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Number of bytes scanned so far
+    pub fn bytes_scanned(&self) -> u64 {
+        self.bytes_scanned
+    }
+
+    /// Number of dirent and inode nodes found so far
+    pub fn nodes(&self) -> u64 {
+        self.nodes
+    }
+
+    /// Total size of the image
+    pub fn image_size(&self) -> u64 {
+        self.image_size
+    }
+}
+
+/// Counts of the nodes a scan came across
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "index-cache", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScanStats {
+    dirents: u64,
+    inodes: u64,
+    xattrs: u64,
+    summaries: u64,
+    cleanmarkers: u64,
+    padding: u64,
+    unknown: u64,
+    skipped: u64,
+    node_crc_failures: u64,
+    bytes_scanned: u64,
+}
+
+impl ScanStats {
+    /// Number of dirent nodes, superseded ones included
+    pub fn dirents(&self) -> u64 {
+        self.dirents
+    }
+
+    /// Number of inode nodes, superseded ones included
+    pub fn inodes(&self) -> u64 {
+        self.inodes
+    }
+
+    /// Number of xattr and xref nodes
+    pub fn xattrs(&self) -> u64 {
+        self.xattrs
+    }
+
+    /// Number of erase block summary nodes
+    pub fn summaries(&self) -> u64 {
+        self.summaries
+    }
+
+    /// Number of cleanmarker nodes
+    pub fn cleanmarkers(&self) -> u64 {
+        self.cleanmarkers
+    }
+
+    /// Number of padding nodes
+    pub fn padding(&self) -> u64 {
+        self.padding
+    }
+
+    /// Number of nodes of a type the reader doesn't know
+    pub fn unknown(&self) -> u64 {
+        self.unknown
+    }
+
+    /// Number of nodes a lenient scan left out, see
+    /// [`Jffs2Reader::skipped_nodes`]
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+
+    /// Number of nodes whose node CRC didn't match, see
+    /// [`Jffs2Reader::node_crc_failures`]
+    pub fn node_crc_failures(&self) -> u64 {
+        self.node_crc_failures
+    }
+
+    /// Number of bytes of the image the scan went through
+    pub fn bytes_scanned(&self) -> u64 {
+        self.bytes_scanned
+    }
+
+    /// Count a node of type `nodetype` that wasn't skipped
+    fn node(&mut self, nodetype: u16) {
+        match NodeType::try_from(nodetype) {
+            Ok(NodeType::Dirent) => self.dirents += 1,
+            Ok(NodeType::Inode) => self.inodes += 1,
+            Ok(NodeType::Xattr | NodeType::Xref) => self.xattrs += 1,
+            Ok(NodeType::Summary) => self.summaries += 1,
+            Ok(NodeType::CleanMarker) => self.cleanmarkers += 1,
+            Ok(NodeType::Padding) => self.padding += 1,
+            Err(_) => self.unknown += 1,
+        }
+    }
+}
+
+/// Extraction state handed to the progress hook
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DumpProgress<'p> {
+    done: usize,
+    total: usize,
+    path: &'p Path,
+}
+
+impl DumpProgress<'_> {
+    /// Number of entries extracted so far, this one included
+    pub fn done(&self) -> usize {
+        self.done
+    }
+
+    /// Number of entries to extract
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// Output path of the entry just extracted
+    pub fn path(&self) -> &Path {
+        self.path
+    }
+}
+
+/// Error of an operation stopped through the token set with
+/// [`Jffs2Reader::set_cancel`], find it with `error.is::<Cancelled>()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("operation cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Context of an error reading a data node or resolving a dirent, with the
+/// offset of the node in the image, find it with
+/// `error.downcast_ref::<NodeError>()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NodeError {
+    offset: u64,
+}
+
+impl NodeError {
+    /// Offset of the node in the image
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+}
+
+impl std::fmt::Display for NodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "reading the node at offset {:#x}", self.offset)
+    }
+}
+
+/// What the hook set with [`Jffs2Reader::set_progress`] is told
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Progress<'p> {
+    Scan(ScanProgress),
+    Dump(DumpProgress<'p>),
+}
+
+type ProgressFn<'a> = Box<dyn FnMut(Progress<'_>) + Send + 'a>;
+
+/// Callback of [`Jffs2Reader::dump_with_progress`], locked so the extraction
+/// threads can share it
+type EntryFn<'f> = Mutex<dyn FnMut(&Path, u64, u64) + Send + 'f>;
+
+/// Progress hook of a reader, behind a lock so `&self` methods can call it
+#[derive(Default)]
+struct ProgressHook<'a>(Mutex<Option<ProgressFn<'a>>>);
+
+impl std::fmt::Debug for ProgressHook<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ProgressHook")
+    }
+}
+
+impl ProgressHook<'_> {
+    fn report(&self, progress: Progress<'_>) {
+        let mut hook = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(hook) = hook.as_mut() {
+            hook(progress);
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "index-cache", derive(serde::Serialize, serde::Deserialize))]
 #[allow(dead_code)]
 struct Jffs2Dirent {
     // jint32_t pino;
@@ -106,12 +612,33 @@ struct Jffs2Dirent {
     // uint8_t name[0];
     pino: u32,
     version: u32,
+    ino: u32,
     mctime: u32,
     ntype: u8,
-    fname: String,
+    fname: OsString,
+    /// Offset of the node in the image
+    offset: u64,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "index-cache", derive(serde::Serialize, serde::Deserialize))]
+struct Jffs2Xattr {
+    // jint32_t xid;
+    // jint32_t version;
+    // uint8_t xprefix;
+    // uint8_t name_len;
+    // jint16_t value_len;
+    // jint32_t data_crc;
+    // jint32_t node_crc;
+    // uint8_t data[0]; /* name, NUL, value */
+    version: u32,
+    /// Full name, the prefix included
+    name: String,
+    value: Vec<u8>,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "index-cache", derive(serde::Serialize, serde::Deserialize))]
 #[allow(dead_code)]
 pub struct Jffs2Inode {
     // jint32_t ino;        /* Inode number.  */
@@ -132,14 +659,22 @@ pub struct Jffs2Inode {
     // jint32_t data_crc;   /* CRC for the (compressed) data.  */
     // jint32_t node_crc;   /* CRC for the raw inode (excluding data)  */
     // uint8_t data[0];
+    ino: u32,
     version: u32,
+    mode: u32,
+    uid: u16,
+    gid: u16,
     iszie: u32,
+    atime: u32,
     mtime: u32,
+    ctime: u32,
     offset: u32,
     csize: u32,
     dsize: u32,
+    /// Raw compression byte, checked when the data is read
     compr: u8,
-    data: u32,
+    data_crc: u32,
+    data: u64,
 }
 
 impl Jffs2Inode {
@@ -147,6 +682,36 @@ impl Jffs2Inode {
         self.version
     }
 
+    /// File type and permission bits
+    pub fn mode(&self) -> u32 {
+        self.mode
+    }
+
+    /// User id of the owner
+    pub fn uid(&self) -> u16 {
+        self.uid
+    }
+
+    /// Group id of the owner
+    pub fn gid(&self) -> u16 {
+        self.gid
+    }
+
+    /// Last access time, in seconds since the epoch
+    pub fn atime(&self) -> u32 {
+        self.atime
+    }
+
+    /// Last modification time, in seconds since the epoch
+    pub fn mtime(&self) -> u32 {
+        self.mtime
+    }
+
+    /// Last status change time, in seconds since the epoch
+    pub fn ctime(&self) -> u32 {
+        self.ctime
+    }
+
     pub fn offset(&self) -> u32 {
         self.offset
     }
@@ -161,523 +726,6444 @@ impl Jffs2Inode {
         self.dsize
     }
 
-    /// Compression method
-    pub fn compression_method(&self) -> u8 {
-        self.compr
+    /// Compression method, none for a byte the kernel never writes
+    pub fn compression_method(&self) -> Option<Compression> {
+        Compression::try_from(self.compr).ok()
     }
 
     /// Data Offset in the file
-    pub fn data_offset(&self) -> u32 {
+    pub fn data_offset(&self) -> u64 {
         self.data
     }
+
+    /// Offset of the node in the image
+    pub fn node_offset(&self) -> u64 {
+        self.data - (SIZE_OF_NODE_HEADER + SIZE_OF_INODE) as u64
+    }
+
+    /// Compression method, failing for a byte the kernel never writes
+    fn compression(&self) -> Result<Compression> {
+        Compression::try_from(self.compr)
+    }
+}
+
+/// Type of a node, from its header
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u16)]
+pub enum NodeType {
+    Dirent = 0xE001,
+    Inode = 0xE002,
+    CleanMarker = 0x2003,
+    Padding = 0x2004,
+    Summary = 0x2006,
+    Xattr = 0xE008,
+    Xref = 0xE009,
+}
+
+impl TryFrom<u16> for NodeType {
+    type Error = anyhow::Error;
+
+    fn try_from(nodetype: u16) -> Result<Self> {
+        Ok(match nodetype {
+            0xE001 => NodeType::Dirent,
+            0xE002 => NodeType::Inode,
+            0x2003 => NodeType::CleanMarker,
+            0x2004 => NodeType::Padding,
+            0x2006 => NodeType::Summary,
+            0xE008 => NodeType::Xattr,
+            0xE009 => NodeType::Xref,
+            _ => bail!("unknown node type {:#06x}", nodetype),
+        })
+    }
+}
+
+/// Compression method of the data of an inode node
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum Compression {
+    None = 0x00,
+    Zero = 0x01,
+    Rtime = 0x02,
+    Rubinmips = 0x03,
+    Copy = 0x04,
+    Dynrubin = 0x05,
+    Zlib = 0x06,
+    Lzo = 0x07,
+    Lzma = 0x08,
+}
+
+impl Compression {
+    /// Short lowercase name, as used by mkfs.jffs2
+    pub fn name(&self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            Compression::Zero => "zero",
+            Compression::Rtime => "rtime",
+            Compression::Rubinmips => "rubinmips",
+            Compression::Copy => "copy",
+            Compression::Dynrubin => "dynrubin",
+            Compression::Zlib => "zlib",
+            Compression::Lzo => "lzo",
+            Compression::Lzma => "lzma",
+        }
+    }
+
+    /// Returns true if data compressed this way can be decompressed, the
+    /// rubin methods need the rubin feature
+    pub fn is_supported(&self) -> bool {
+        let rubin = matches!(self, Compression::Rubinmips | Compression::Dynrubin);
+        *self != Compression::Copy && (cfg!(feature = "rubin") || !rubin)
+    }
+}
+
+impl TryFrom<u8> for Compression {
+    type Error = anyhow::Error;
+
+    fn try_from(compr: u8) -> Result<Self> {
+        Ok(match compr {
+            0x00 => Compression::None,
+            0x01 => Compression::Zero,
+            0x02 => Compression::Rtime,
+            0x03 => Compression::Rubinmips,
+            0x04 => Compression::Copy,
+            0x05 => Compression::Dynrubin,
+            0x06 => Compression::Zlib,
+            0x07 => Compression::Lzo,
+            0x08 => Compression::Lzma,
+            _ => bail!("unknown compression type {:#04x}", compr),
+        })
+    }
 }
 
+/// Type of a file in the image
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FileKind {
+    Regular,
+    Dir,
+    Symlink,
+    CharDev,
+    BlockDev,
+    Fifo,
+    Socket,
+}
+
+impl FileKind {
+    /// File type held in the `S_IFMT` bits of an inode mode
+    pub fn from_mode(mode: u32) -> Option<FileKind> {
+        match mode & S_IFMT {
+            S_IFREG => Some(FileKind::Regular),
+            S_IFDIR => Some(FileKind::Dir),
+            S_IFLNK => Some(FileKind::Symlink),
+            S_IFCHR => Some(FileKind::CharDev),
+            S_IFBLK => Some(FileKind::BlockDev),
+            S_IFIFO => Some(FileKind::Fifo),
+            S_IFSOCK => Some(FileKind::Socket),
+            _ => None,
+        }
+    }
+
+    /// File type recorded in a dirent
+    fn from_dirent_type(ntype: u8) -> Option<FileKind> {
+        match ntype {
+            DT_REG => Some(FileKind::Regular),
+            DT_DIR => Some(FileKind::Dir),
+            DT_LNK => Some(FileKind::Symlink),
+            DT_CHR => Some(FileKind::CharDev),
+            DT_BLK => Some(FileKind::BlockDev),
+            DT_FIFO => Some(FileKind::Fifo),
+            DT_SOCK => Some(FileKind::Socket),
+            _ => None,
+        }
+    }
+}
+
+/// A file of the image. With the `serde` feature the paths serialize as
+/// strings, those that are not UTF-8 lossily, and the times as seconds since
+/// the epoch.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Jffs2Entry {
-    inodes: Vec<Jffs2Inode>,
+    size: u64,
     is_file: bool,
+    kind: FileKind,
+    mode: u32,
+    uid: u16,
+    gid: u16,
+    atime: u32,
+    mtime: u32,
+    ctime: u32,
+    nlink: u32,
+    compressed_size: u64,
+    data_size: u64,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_path"))]
     path: PathBuf,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_target"))]
+    symlink_target: Option<PathBuf>,
+    device: Option<(u32, u32)>,
+    ino: u32,
 }
 
 impl Jffs2Entry {
-    /// The original file size of the dirent
+    /// The original file size of the dirent, as recorded in the most recent
+    /// node for regular files
     pub fn size(&self) -> u64 {
-        let mut dirent_size = 0 as u64;
-        for node in &self.inodes {
-            dirent_size += node.decompressed_size() as u64;
-        }
-        
-        dirent_size
+        self.size
     }
 
     /// Returns true if the current dirent represents a file, 
-    /// otherwise, the current dirent represents a folder
+    /// otherwise, the current dirent represents a folder or a symbolic link
     pub fn is_file(&self) -> bool {
         self.is_file
     }
 
-    /// Path of the current dirent within the filesystem
-    pub fn path(&self) -> &PathBuf {
-        &self.path
+    /// Type of the file, device nodes, FIFOs and sockets included
+    pub fn kind(&self) -> FileKind {
+        self.kind
     }
-}
 
-#[derive(Debug)]
-struct Jffs2Reader {
-    buffer: memmap::Mmap,
-    little_endian: bool,
-    dirents: HashMap<u32, Jffs2Dirent>,
-    inodes: HashMap<u32, Vec<Jffs2Inode>>,
-}
+    /// Mode, file type bits included, taken from the most recent node like
+    /// the owner and times. 0 when there is no node.
+    pub fn mode(&self) -> u32 {
+        self.mode
+    }
 
-// reference :
-// https://github.com/sviehb/jefferson/blob/master/src/scripts/jefferson
+    /// Owner user id
+    pub fn uid(&self) -> u16 {
+        self.uid
+    }
 
-impl Jffs2Reader {
-    pub fn new(path: impl AsRef<Path>) -> Result<Self> {
-        let file = File::open(path)?;
-        let buffer = unsafe { MmapOptions::new().map(&file)? };
-        if buffer.len() < 2 {
-            bail!("image size is too small");
-        }
+    /// Owner group id
+    pub fn gid(&self) -> u16 {
+        self.gid
+    }
 
-        let initial = Jffs2Reader::read_uint16(&buffer[0..2], true, 0)?;
-        if initial != 0x1985 && initial != 0x8519 {
-            bail!("image is not jffs2");
-        }
+    /// Last access time, taken from the most recent node
+    pub fn atime(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.atime as u64)
+    }
 
-        let little_endian = initial == 0x1985;
-        Ok(Jffs2Reader {
-            buffer,
-            little_endian,
-            dirents: HashMap::new(),
-            inodes: HashMap::new(),
-        })
+    /// Last modification time, taken from the most recent node
+    pub fn mtime(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.mtime as u64)
     }
 
-    fn read_uint32(buffer: &[u8], little_endian: bool, offset: usize) -> Result<u32> {
-        if offset + 4 > buffer.len() {
-            bail!(
-                "offset out of bounds: {} in a buffer of {}",
-                offset,
-                buffer.len()
-            );
+    /// Last status change time, taken from the most recent node
+    pub fn ctime(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.ctime as u64)
+    }
+
+    /// Size of the compressed data of the nodes making up a regular file, see
+    /// [`Jffs2Reader::compression_summary`]
+    pub fn total_compressed_size(&self) -> u64 {
+        self.compressed_size
+    }
+
+    /// Compressed size over decompressed size of the nodes making up a
+    /// regular file, 1 when there is no data
+    pub fn compression_ratio(&self) -> f64 {
+        if self.data_size == 0 {
+            return 1.0;
         }
-        let buffer = &buffer[offset..offset + 4];
+        self.compressed_size as f64 / self.data_size as f64
+    }
+
+    /// Number of hard links, counted like `ls -l` does: the names of a file,
+    /// two plus the subdirectories for a directory
+    pub fn nlink(&self) -> u32 {
+        self.nlink
+    }
+
+    /// Returns true if the current dirent represents a symbolic link
+    pub fn is_symlink(&self) -> bool {
+        self.symlink_target.is_some()
+    }
+
+    /// Target of the symbolic link, as stored in the image
+    pub fn symlink_target(&self) -> Option<&Path> {
+        self.symlink_target.as_deref()
+    }
+
+    /// Owned copy of [`Jffs2Entry::symlink_target`], none for anything but a
+    /// symbolic link
+    pub fn link_target(&self) -> Option<PathBuf> {
+        self.symlink_target.clone()
+    }
+
+    /// Major and minor number of a character or block device
+    pub fn device(&self) -> Option<(u32, u32)> {
+        self.device
+    }
+
+    /// Path of the current dirent within the filesystem
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    /// Inode number, shared by the hard links of a file
+    pub fn ino(&self) -> u32 {
+        self.ino
+    }
+}
+
+/// A regular file of the image, its data is decompressed one node at a time
+/// as it is read
+#[derive(Debug)]
+pub struct Jffs2File<'r, 'a> {
+    reader: &'r Jffs2Reader<'a>,
+    nodes: Vec<Jffs2Inode>,
+    fragments: std::vec::IntoIter<Fragment>,
+    chunk: Vec<u8>,
+    // the node decompressed in `chunk`, if any
+    current: Option<usize>,
+    pos: usize,
+    end: usize,
+    offset: u64,
+    len: u64,
+}
+
+impl Jffs2File<'_, '_> {
+    /// Size of the file, as recorded in its most recent node
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns true if the file has no data
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Read for Jffs2File<'_, '_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.pos == self.end {
+            if self.offset >= self.len {
+                return Ok(0);
+            }
+            let next = self.fragments.as_slice().first().map_or(self.len, |frag| frag.offset);
+            if next > self.offset {
+                // a hole, read as zeros
+                let gap = (next.min(self.len) - self.offset).min(HOLE_CHUNK_SIZE);
+                self.chunk.clear();
+                self.chunk.resize(gap as usize, 0);
+                self.current = None;
+                self.pos = 0;
+                self.end = gap as usize;
+                continue;
+            }
+
+            let frag = self.fragments.next().unwrap();
+            // a node split by a newer one is decompressed once per piece
+            if self.current != Some(frag.node) {
+                self.reader
+                    .decompress_fragment(&self.nodes[frag.node], &mut self.chunk)
+                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+                self.current = Some(frag.node);
+            }
+            self.pos = frag.skip as usize;
+            self.end = (frag.skip + frag.len) as usize;
+        }
+
+        let n = buf
+            .len()
+            .min(self.end - self.pos)
+            .min((self.len - self.offset.min(self.len)) as usize);
+        buf[..n].copy_from_slice(&self.chunk[self.pos..self.pos + n]);
+        self.pos += n;
+        self.offset += n as u64;
+        Ok(n)
+    }
+}
+
+/// Parameters of the raw LZMA streams in the image, which leave out the
+/// header. The defaults are the ones of mkfs.jffs2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "index-cache", derive(serde::Serialize, serde::Deserialize))]
+pub struct LzmaOptions {
+    lc: u8,
+    lp: u8,
+    pb: u8,
+    dict_size: u32,
+}
+
+impl Default for LzmaOptions {
+    fn default() -> Self {
+        LzmaOptions {
+            lc: LZMA_BEST_LC,
+            lp: LZMA_BEST_LP,
+            pb: LZMA_BEST_PB,
+            dict_size: DICT_SIZE,
+        }
+    }
+}
+
+impl LzmaOptions {
+    pub fn new() -> Self {
+        LzmaOptions::default()
+    }
+
+    /// Number of literal context bits, at most 8
+    pub fn lc(mut self, lc: u8) -> Self {
+        self.lc = lc;
+        self
+    }
+
+    /// Number of literal position bits, at most 4
+    pub fn lp(mut self, lp: u8) -> Self {
+        self.lp = lp;
+        self
+    }
+
+    /// Number of position bits, at most 4
+    pub fn pb(mut self, pb: u8) -> Self {
+        self.pb = pb;
+        self
+    }
+
+    pub fn dict_size(mut self, dict_size: u32) -> Self {
+        self.dict_size = dict_size;
+        self
+    }
+
+    /// The properties byte of the LZMA header
+    fn properties(&self) -> u8 {
+        (self.pb * 5 + self.lp) * 9 + self.lc
+    }
+}
+
+/// What to write in place of a symbolic link where links cannot be created,
+/// as on Windows or on FAT
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SymlinkFallback {
+    /// A file holding the link target
+    #[default]
+    Placeholder,
+    /// Nothing, the link is reported as skipped
+    Skip,
+    /// A copy of the regular file the link points to within the image
+    CopyTarget,
+}
+
+/// What to do when an entry to extract is already there, directories aside
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverwritePolicy {
+    /// Replace it
+    #[default]
+    Overwrite,
+    /// Leave it untouched, the entry is reported as skipped
+    Skip,
+    /// Fail with an [`std::io::Error`] of kind `AlreadyExists`
+    Error,
+}
+
+/// What to do with entries whose paths differ only in case, like `Config`
+/// and `config`, which overwrite each other on a filesystem ignoring case
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CaseCollisionPolicy {
+    /// Write them all and leave it to the filesystem
+    #[default]
+    Write,
+    /// Fail before anything is written
+    Error,
+    /// Write the first one in byte order, the others and what is under them
+    /// are reported as skipped
+    KeepFirst,
+    /// Write the others with a `~1`, `~2`... suffix, they are listed in
+    /// [`ExtractionReport::renamed`]
+    Rename,
+}
+
+/// What to do with device nodes, FIFOs and sockets on extraction
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SpecialFilePolicy {
+    /// Create them, device nodes need the privilege to do so and are
+    /// skipped with a warning without it
+    #[default]
+    Create,
+    /// Only list them in [`ExtractionReport::recorded`] with their device
+    /// numbers
+    Record,
+    /// Leave them out without a word
+    Skip,
+}
+
+/// Extraction options for [`Jffs2Reader::dump_with_options`]
+#[derive(Debug, Clone, Default)]
+pub struct DumpOptions {
+    overwrite: OverwritePolicy,
+    empty_target: bool,
+    case_collisions: CaseCollisionPolicy,
+    special_files: SpecialFilePolicy,
+    symlink_fallback: SymlinkFallback,
+    // the mode is applied unless this is set
+    no_permissions: bool,
+    setid: bool,
+    preserve_owner: bool,
+    // and the times unless this is set
+    no_times: bool,
+    xattrs: bool,
+    fill_holes: bool,
+    // on Windows when not set
+    sanitize_names: Option<bool>,
+    best_effort: bool,
+    #[cfg(feature = "parallel")]
+    threads: usize,
+}
+
+impl DumpOptions {
+    pub fn new() -> Self {
+        DumpOptions::default()
+    }
+
+    /// Give the extracted files and directories the permission bits stored in
+    /// the image, on by default. Elsewhere than on unix only read-only is
+    /// kept.
+    pub fn preserve_permissions(mut self, preserve: bool) -> Self {
+        self.no_permissions = !preserve;
+        self
+    }
+
+    /// Keep the setuid and setgid bits along with the other permission bits,
+    /// off by default
+    pub fn setid(mut self, setid: bool) -> Self {
+        self.setid = setid;
+        self
+    }
+
+    /// Give the extracted files the uid and gid stored in the image. Without
+    /// the privilege to do so the files keep the current user, with a warning.
+    pub fn preserve_owner(mut self, preserve: bool) -> Self {
+        self.preserve_owner = preserve;
+        self
+    }
+
+    /// Give the extracted files and directories the access and modification
+    /// times stored in the image instead of the time of extraction, on by
+    /// default
+    pub fn preserve_times(mut self, preserve: bool) -> Self {
+        self.no_times = !preserve;
+        self
+    }
+
+    /// Set the extended attributes stored in the image, SELinux labels and
+    /// file capabilities included. Without the privilege to set them, or on
+    /// a filesystem without extended attributes, they are left out with a
+    /// warning.
+    pub fn xattrs(mut self, xattrs: bool) -> Self {
+        self.xattrs = xattrs;
+        self
+    }
+
+    /// Write the holes of sparse files as zero bytes, for filesystems
+    /// without sparse files. By default they are left unwritten.
+    pub fn fill_holes(mut self, fill_holes: bool) -> Self {
+        self.fill_holes = fill_holes;
+        self
+    }
+
+    /// Escape the characters and names Windows refuses, `a:b` is written as
+    /// `a%3Ab` and `CON` as `%43ON`, and tell apart the names that end up the
+    /// same, ignoring case, with a `~1` suffix. On by default on Windows
+    /// only, see [`ExtractionReport::renamed`] for what was renamed.
+    pub fn sanitize_names(mut self, sanitize: bool) -> Self {
+        self.sanitize_names = Some(sanitize);
+        self
+    }
+
+    /// What to do with the files already in the target directory,
+    /// [`OverwritePolicy::Overwrite`] by default
+    pub fn overwrite(mut self, policy: OverwritePolicy) -> Self {
+        self.overwrite = policy;
+        self
+    }
+
+    /// Fail with an [`std::io::Error`] of kind `AlreadyExists` unless the
+    /// target directory is empty or not there yet, so nothing already in it
+    /// is touched. Off by default.
+    pub fn empty_target(mut self, empty_target: bool) -> Self {
+        self.empty_target = empty_target;
+        self
+    }
+
+    /// What to do with the entries whose paths differ only in case,
+    /// [`CaseCollisionPolicy::Write`] by default. They are always renamed
+    /// along with the names [`DumpOptions::sanitize_names`] escapes.
+    pub fn case_collisions(mut self, policy: CaseCollisionPolicy) -> Self {
+        self.case_collisions = policy;
+        self
+    }
+
+    /// What to do with device nodes, FIFOs and sockets,
+    /// [`SpecialFilePolicy::Create`] by default
+    pub fn special_files(mut self, policy: SpecialFilePolicy) -> Self {
+        self.special_files = policy;
+        self
+    }
+
+    /// What to write for a symbolic link that cannot be created,
+    /// [`SymlinkFallback::Placeholder`] by default
+    pub fn symlink_fallback(mut self, fallback: SymlinkFallback) -> Self {
+        self.symlink_fallback = fallback;
+        self
+    }
+
+    /// Go on with the other entries when one cannot be extracted, the
+    /// failures are collected in the [`ExtractionReport`]
+    pub fn best_effort(mut self, best_effort: bool) -> Self {
+        self.best_effort = best_effort;
+        self
+    }
+
+    /// Write the regular files on this many threads, 0 or 1 keeps everything
+    /// on the calling thread
+    #[cfg(feature = "parallel")]
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+}
+
+/// A device node, FIFO or socket left out of an extraction: its path, type,
+/// and major and minor number for a device
+pub type RecordedFile = (PathBuf, FileKind, Option<(u32, u32)>);
+
+/// What an extraction wrote, see [`Jffs2Reader::dump_with_report`]
+#[derive(Debug, Default)]
+pub struct ExtractionReport {
+    files: usize,
+    dirs: usize,
+    symlinks: usize,
+    hard_links: usize,
+    special_files: usize,
+    bytes: u64,
+    written: Vec<PathBuf>,
+    renamed: Vec<(PathBuf, PathBuf)>,
+    skipped: Vec<(PathBuf, String)>,
+    recorded: Vec<RecordedFile>,
+    unsupported: Vec<(PathBuf, Compression)>,
+    failures: Vec<(PathBuf, anyhow::Error)>,
+}
+
+impl ExtractionReport {
+    /// Number of regular files written
+    pub fn files(&self) -> usize {
+        self.files
+    }
+
+    /// Number of directories created
+    pub fn dirs(&self) -> usize {
+        self.dirs
+    }
+
+    /// Number of symbolic links created
+    pub fn symlinks(&self) -> usize {
+        self.symlinks
+    }
+
+    /// Number of extra names of regular files written once, linked or
+    /// copied where hard links cannot be made
+    pub fn hard_links(&self) -> usize {
+        self.hard_links
+    }
+
+    /// Number of device nodes, FIFOs and sockets created
+    pub fn special_files(&self) -> usize {
+        self.special_files
+    }
+
+    /// Total size of the decompressed data written
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Paths of the entries written, sorted
+    pub fn written(&self) -> &[PathBuf] {
+        &self.written
+    }
+
+    /// Entries written under another name by [`DumpOptions::sanitize_names`],
+    /// the path with the name from the image first, sorted
+    pub fn renamed(&self) -> &[(PathBuf, PathBuf)] {
+        &self.renamed
+    }
+
+    /// Entries left out on purpose, with the reason
+    pub fn skipped(&self) -> &[(PathBuf, String)] {
+        &self.skipped
+    }
+
+    /// Device nodes, FIFOs and sockets left out with
+    /// [`SpecialFilePolicy::Record`], with the major and minor number of the
+    /// devices
+    pub fn recorded(&self) -> &[RecordedFile] {
+        &self.recorded
+    }
+
+    /// Files left out in best-effort mode because one of their nodes uses a
+    /// compression method that cannot be decompressed
+    pub fn unsupported(&self) -> &[(PathBuf, Compression)] {
+        &self.unsupported
+    }
+
+    /// Entries that failed in best-effort mode. The dirents that cannot be
+    /// resolved come last, by their name under the target directory as their
+    /// parents are unknown.
+    pub fn failures(&self) -> &[(PathBuf, anyhow::Error)] {
+        &self.failures
+    }
+
+    /// Path of the failures caused by a data node or a dirent, with the
+    /// offset of the node in the image
+    pub fn failed_nodes(&self) -> Vec<(&Path, u64)> {
+        self.failures
+            .iter()
+            .filter_map(|(path, e)| Some((path.as_path(), e.downcast_ref::<NodeError>()?.offset)))
+            .collect()
+    }
+
+    /// Keep the error of the entry at `path` when `best_effort`, otherwise
+    /// pass it on, as well as a cancellation. Returns true if the entry was
+    /// written.
+    fn settle(&mut self, path: &Path, result: Result<()>, best_effort: bool) -> Result<bool> {
+        match result {
+            Ok(()) => Ok(true),
+            Err(e) if best_effort && !e.is::<Cancelled>() => {
+                self.failures.push((path.to_path_buf(), e));
+                Ok(false)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Controls what [`Jffs2Reader::dump_subtree`] writes
+#[derive(Debug, Clone, Default)]
+pub struct SubtreeOptions {
+    strip_prefix: bool,
+    allow_missing: bool,
+}
+
+impl SubtreeOptions {
+    pub fn new() -> Self {
+        SubtreeOptions::default()
+    }
+
+    /// Write the content of the prefix directory, or the prefix file itself,
+    /// straight into the target directory instead of under its full path
+    pub fn strip_prefix(mut self, strip: bool) -> Self {
+        self.strip_prefix = strip;
+        self
+    }
+
+    /// Extract nothing instead of failing when the prefix is not in the image
+    pub fn allow_missing(mut self, allow: bool) -> Self {
+        self.allow_missing = allow;
+        self
+    }
+}
+
+/// Returns true if `e` reports a path missing from the image
+fn is_not_found(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<std::io::Error>()
+        .is_some_and(|e| e.kind() == std::io::ErrorKind::NotFound)
+}
+
+/// Returns true if `name` is a single normal path component, not empty, `.`,
+/// `..`, or anything holding a separator
+fn is_file_name(name: &OsStr) -> bool {
+    let mut components = Path::new(name).components();
+    matches!(components.next(), Some(Component::Normal(first)) if first == name)
+        && components.next().is_none()
+}
+
+#[cfg(feature = "serde")]
+fn serialize_path<S: serde::Serializer>(path: &Path, s: S) -> std::result::Result<S::Ok, S::Error> {
+    s.serialize_str(&path.to_string_lossy())
+}
+
+#[cfg(feature = "serde")]
+fn serialize_target<S: serde::Serializer>(
+    target: &Option<PathBuf>,
+    s: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    match target {
+        Some(target) => s.serialize_some(&target.to_string_lossy()),
+        None => s.serialize_none(),
+    }
+}
+
+/// `path` made absolute, with the symbolic links of the part of it that
+/// already exists resolved
+fn resolve_existing(path: &Path) -> std::io::Result<PathBuf> {
+    let mut existing = path;
+    let mut missing = vec![];
+    loop {
+        match existing.canonicalize() {
+            Ok(resolved) => {
+                return Ok(missing.iter().rev().fold(resolved, |path, name| path.join(name)))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let (parent, name) = match (existing.parent(), existing.file_name()) {
+                    (Some(parent), Some(name)) => (parent, name),
+                    _ => return Err(e),
+                };
+                missing.push(name);
+                existing = if parent.as_os_str().is_empty() { Path::new(".") } else { parent };
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Fail unless `path` stays inside `root` once the links already on disk are
+/// followed, `root` being resolved with [`resolve_existing`]
+fn check_inside(root: &Path, path: &Path) -> Result<()> {
+    if !resolve_existing(path)?.starts_with(root) {
+        bail!("{} leads outside of {}", path.display(), root.display());
+    }
+    Ok(())
+}
+
+/// The extended-length form of `target_path`, `root` being it resolved with
+/// [`resolve_existing`], when one of the output paths would go past
+/// MAX_PATH. The relative paths of `nodes` then have their separators
+/// turned into backslashes, the only ones understood after the prefix.
+#[cfg(windows)]
+fn long_target(target_path: &Path, root: &Path, nodes: &mut [(PathBuf, u32, u8)]) -> PathBuf {
+    use std::os::windows::ffi::OsStrExt;
+
+    const MAX_PATH: usize = 260;
+    let too_long = nodes.iter().any(|(path, _, _)| {
+        target_path.join(path).as_os_str().encode_wide().count() >= MAX_PATH
+    });
+    // canonicalize gives \\?\C:\ and \\?\UNC\ paths
+    if !too_long || !root.as_os_str().to_string_lossy().starts_with(r"\\?\") {
+        return target_path.to_path_buf();
+    }
+    for (path, _, _) in nodes {
+        *path = path.components().collect();
+    }
+    root.to_path_buf()
+}
+
+/// A name or link target stored in the image, any bytes are kept on unix and
+/// those that are not UTF-8 are replaced elsewhere
+fn os_string(bytes: Vec<u8>) -> OsString {
+    #[cfg(unix)]
+    return std::os::unix::ffi::OsStringExt::from_vec(bytes);
+    #[cfg(not(unix))]
+    return OsString::from(String::from_utf8_lossy(&bytes).into_owned());
+}
+
+/// Write `len` zero bytes to `out`
+fn write_zeros(out: &mut impl Write, mut len: u64) -> std::io::Result<()> {
+    while len > 0 {
+        let n = len.min(ZERO_PAGE.len() as u64) as usize;
+        out.write_all(&ZERO_PAGE[..n])?;
+        len -= n as u64;
+    }
+    Ok(())
+}
+
+/// Where the builder gets the image from
+#[derive(Debug)]
+enum ImageSource<'a> {
+    Path(PathBuf),
+    Bytes(Cow<'a, [u8]>),
+    Seekable(SeekableImage<'a>),
+}
+
+/// Options for opening a [`Jffs2Reader`]
+#[derive(Debug)]
+pub struct Jffs2ReaderBuilder<'a> {
+    source: ImageSource<'a>,
+    endianness: Option<Endianness>,
+    swapped: Option<bool>,
+    lenient: bool,
+    data_crc: Option<DataCrcCheck>,
+    node_crc: Option<NodeCrcCheck>,
+    name_crc: Option<NameCrcCheck>,
+    include_deleted: bool,
+    lzma: LzmaOptions,
+    summaries: bool,
+    erase_block_size: Option<usize>,
+    root_inode: u32,
+    max_depth: usize,
+    max_file_size: u64,
+    max_total_size: u64,
+}
+
+impl<'a> Jffs2ReaderBuilder<'a> {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Jffs2ReaderBuilder::with_source(ImageSource::Path(path.as_ref().to_path_buf()))
+    }
+
+    /// Read the image from memory instead of mapping a file
+    pub fn from_bytes(data: impl Into<Cow<'a, [u8]>>) -> Self {
+        Jffs2ReaderBuilder::with_source(ImageSource::Bytes(data.into()))
+    }
+
+    /// Read the image through `r` on demand, see [`Jffs2Reader::from_seekable`]
+    pub fn from_seekable(r: impl Read + Seek + Send + 'a, len: u64) -> Result<Self> {
+        Ok(Jffs2ReaderBuilder::with_source(ImageSource::Seekable(
+            SeekableImage::new(r, len)?,
+        )))
+    }
+
+    fn with_source(source: ImageSource<'a>) -> Self {
+        Jffs2ReaderBuilder {
+            source,
+            endianness: None,
+            swapped: None,
+            lenient: false,
+            data_crc: None,
+            node_crc: None,
+            name_crc: None,
+            include_deleted: false,
+            lzma: LzmaOptions::default(),
+            summaries: false,
+            erase_block_size: None,
+            root_inode: ROOT_INO,
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+            max_total_size: DEFAULT_MAX_TOTAL_SIZE,
+        }
+    }
+
+    /// Parse the image in this byte order instead of detecting it
+    pub fn endianness(mut self, endianness: Endianness) -> Self {
+        self.endianness = Some(endianness);
+        self
+    }
+
+    /// Read the image with the bytes of every 16 bit word exchanged, or
+    /// as it is, instead of detecting it
+    pub fn swapped(mut self, swapped: bool) -> Self {
+        self.swapped = Some(swapped);
+        self
+    }
+
+    /// Skip the nodes that fail to parse instead of failing the scan. They
+    /// are logged and listed by [`Jffs2Reader::skipped_nodes`], and the scan
+    /// looks for the next node from 4 bytes further, inside the broken one.
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+
+    /// Check the CRC of the compressed data of every node before
+    /// decompressing it, a mismatch fails the read
+    pub fn verify_data_crc(mut self, verify: bool) -> Self {
+        self.data_crc = verify.then_some(DataCrcCheck::Fail);
+        self
+    }
+
+    /// Check the CRC of the compressed data of every node before
+    /// decompressing it and handle a mismatch as `check` says. The
+    /// mismatches are in [`Jffs2Reader::data_crc_mismatches`].
+    pub fn data_crc_check(mut self, check: DataCrcCheck) -> Self {
+        self.data_crc = Some(check);
+        self
+    }
+
+    /// Check the CRC of the dirent and inode structures while scanning and
+    /// handle the nodes that fail as `check` says. The offsets of the
+    /// failures are in [`Jffs2Reader::node_crc_failures`].
+    pub fn verify_node_crc(mut self, check: NodeCrcCheck) -> Self {
+        self.node_crc = Some(check);
+        self
+    }
+
+    /// Check the CRC of the dirent names while scanning and handle the
+    /// dirents that fail as `check` says. The offsets of the failures are
+    /// in [`Jffs2Reader::name_crc_failures`].
+    pub fn verify_name_crc(mut self, check: NameCrcCheck) -> Self {
+        self.name_crc = Some(check);
+        self
+    }
+
+    /// Keep the files that were deleted but whose dirent is still in the
+    /// image, for recovery. By default they are left out like on the live
+    /// filesystem.
+    pub fn include_deleted(mut self, include: bool) -> Self {
+        self.include_deleted = include;
+        self
+    }
+
+    /// Decode the LZMA nodes with these parameters instead of the ones of
+    /// mkfs.jffs2
+    pub fn lzma_options(mut self, lzma: LzmaOptions) -> Self {
+        self.lzma = lzma;
+        self
+    }
+
+    /// Use the summary node at the end of each erase block to go straight
+    /// to the nodes it lists, the blocks without one are scanned as usual
+    pub fn summaries(mut self, summaries: bool) -> Self {
+        self.summaries = summaries;
+        self
+    }
+
+    /// Size of the erase blocks holding the summaries, detected from the
+    /// first block if not set
+    pub fn erase_block_size(mut self, size: u32) -> Self {
+        self.erase_block_size = Some(size as usize);
+        self
+    }
+
+    /// Treat the directory with this inode number as the root of the image,
+    /// entries outside of it are left out of listings and extraction
+    pub fn root_inode(mut self, ino: u32) -> Self {
+        self.root_inode = ino;
+        self
+    }
+
+    /// How many directories deep an entry can be, 256 by default. Deeper
+    /// entries fail to resolve.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Refuse to read or extract a file, or decompress a node, claiming more
+    /// than this many bytes, 1 GiB by default. Guards against images crafted
+    /// to exhaust memory or disk.
+    pub fn max_file_size(mut self, size: u64) -> Self {
+        self.max_file_size = size;
+        self
+    }
+
+    /// Refuse an extraction whose files add up to more than this many bytes,
+    /// 16 GiB by default
+    pub fn max_total_size(mut self, size: u64) -> Self {
+        self.max_total_size = size;
+        self
+    }
+
+    /// Load the image, the returned reader still needs a [`Jffs2Reader::scan`]
+    pub fn build(self) -> Result<Jffs2Reader<'a>> {
+        if self.lzma.lc > 8 || self.lzma.lp > 4 || self.lzma.pb > 4 {
+            bail!(
+                "invalid LZMA parameters lc={} lp={} pb={}",
+                self.lzma.lc,
+                self.lzma.lp,
+                self.lzma.pb
+            );
+        }
+        let buffer = match self.source {
+            ImageSource::Path(path) => ImageBuffer::map(path)?,
+            ImageSource::Bytes(data) => ImageBuffer::Bytes(data),
+            ImageSource::Seekable(image) => ImageBuffer::Seekable(image),
+        };
+        let mut reader = Jffs2Reader::from_buffer(buffer, self.endianness, self.swapped)?;
+        reader.lenient = self.lenient;
+        reader.data_crc = self.data_crc;
+        reader.node_crc = self.node_crc;
+        reader.name_crc = self.name_crc;
+        reader.include_deleted = self.include_deleted;
+        reader.lzma = self.lzma;
+        reader.summaries = self.summaries;
+        reader.erase_block_size = self.erase_block_size;
+        reader.root_ino = self.root_inode;
+        reader.max_depth = self.max_depth;
+        reader.max_file_size = self.max_file_size;
+        reader.max_total_size = self.max_total_size;
+        Ok(reader)
+    }
+}
+
+/// Outcome of [`Jffs2Reader::scan_node`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NodeScan {
+    /// A dirent, inode, xattr or xref
+    Parsed,
+    /// A node of another type, left alone
+    Other,
+    /// A node that failed to parse in lenient mode
+    Skipped,
+}
+
+/// A jffs2 image, scan it once and query it as often as needed
+#[derive(Debug)]
+pub struct Jffs2Reader<'a> {
+    buffer: ImageBuffer<'a>,
+    little_endian: bool,
+    detection: EndiannessDetection,
+    lenient: bool,
+    data_crc: Option<DataCrcCheck>,
+    data_crc_mismatches: Mutex<Vec<DataCrcMismatch>>,
+    node_crc: Option<NodeCrcCheck>,
+    node_crc_failures: Vec<u64>,
+    name_crc: Option<NameCrcCheck>,
+    name_crc_failures: Vec<u64>,
+    skipped_nodes: Vec<u64>,
+    stats: ScanStats,
+    include_deleted: bool,
+    lzma: LzmaOptions,
+    summaries: bool,
+    erase_block_size: Option<usize>,
+    root_ino: u32,
+    max_depth: usize,
+    max_file_size: u64,
+    max_total_size: u64,
+    scanned: bool,
+    /// The latest dirent by parent inode number and name
+    dirents: HashMap<u32, HashMap<OsString, Jffs2Dirent>>,
+    inodes: HashMap<u32, Vec<Jffs2Inode>>,
+    xattrs: HashMap<u32, Jffs2Xattr>,
+    /// Sequence number of the xrefs by inode number and xattr id
+    xrefs: HashMap<u32, HashMap<u32, u32>>,
+    /// Version of the latest deletion dirent by parent inode number and name
+    deletions: HashMap<u32, HashMap<OsString, u32>>,
+    paths: OnceLock<fs::PathIndex>,
+    progress: ProgressHook<'a>,
+    cancel: Option<Arc<AtomicBool>>,
+}
+
+// reference :
+// https://github.com/sviehb/jefferson/blob/master/src/scripts/jefferson
+
+impl<'a> Jffs2Reader<'a> {
+    /// Map the image without scanning it, see [`Jffs2Reader::scan`]
+    pub fn new(path: impl AsRef<Path>) -> Result<Self> {
+        Jffs2Reader::from_buffer(ImageBuffer::map(path)?, None, None)
+    }
+
+    /// Map and scan the image with the default options, ready for queries
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let mut reader = Jffs2Reader::builder(path).build()?;
+        reader.scan()?;
+        Ok(reader)
+    }
+
+    /// Use an image already in memory, borrowed or owned, without scanning it
+    pub fn from_bytes(data: impl Into<Cow<'a, [u8]>>) -> Result<Self> {
+        Jffs2Reader::from_buffer(ImageBuffer::Bytes(data.into()), None, None)
+    }
+
+    /// Read the whole image from `r` into memory, for streams that cannot
+    /// be mapped such as pipes or decompressors. The reader still needs a
+    /// [`Jffs2Reader::scan`].
+    pub fn from_reader(mut r: impl Read) -> Result<Self> {
+        let mut data = Vec::new();
+        r.read_to_end(&mut data)?;
+        Jffs2Reader::from_bytes(data)
+    }
+
+    /// Read the `len` bytes following the current position of `r` on demand
+    /// instead of mapping or buffering the whole image, for images inside a
+    /// larger dump or behind a custom block device. The stream is shared by
+    /// all queries, so this is slower than the file and byte slice backends.
+    pub fn from_seekable(r: impl Read + Seek + Send + 'a, len: u64) -> Result<Self> {
+        Jffs2Reader::from_buffer(
+            ImageBuffer::Seekable(SeekableImage::new(r, len)?),
+            None,
+            None,
+        )
+    }
+
+    /// Set the options of the reader before mapping the image
+    pub fn builder(path: impl AsRef<Path>) -> Jffs2ReaderBuilder<'a> {
+        Jffs2ReaderBuilder::new(path)
+    }
+
+    fn from_buffer(
+        buffer: ImageBuffer<'a>,
+        endianness: Option<Endianness>,
+        swapped: Option<bool>,
+    ) -> Result<Self> {
+        let buffer = buffer.gunzip()?;
+        if buffer.len() < 2 {
+            bail!("image size is too small");
+        }
+
+        let (buffer, mut detection) = Jffs2Reader::detect_layout(buffer, swapped)?;
+        let endianness = match endianness {
+            Some(endianness) => {
+                detection.overridden = true;
+                endianness
+            }
+            None => {
+                let (le, be) = (detection.little_endian_nodes, detection.big_endian_nodes);
+                if le > be {
+                    Endianness::Little
+                } else if be > le {
+                    Endianness::Big
+                } else {
+                    bail!(
+                        "image is not jffs2 or its byte order is ambiguous: \
+                         {} little-endian and {} big-endian node headers in the first {} bytes",
+                        le,
+                        be,
+                        ENDIANNESS_DETECTION_WINDOW.min(buffer.len())
+                    );
+                }
+            }
+        };
+
+        Ok(Jffs2Reader {
+            buffer,
+            little_endian: endianness == Endianness::Little,
+            detection,
+            lenient: false,
+            data_crc: None,
+            data_crc_mismatches: Mutex::new(Vec::new()),
+            node_crc: None,
+            node_crc_failures: Vec::new(),
+            name_crc: None,
+            name_crc_failures: Vec::new(),
+            skipped_nodes: Vec::new(),
+            stats: ScanStats::default(),
+            include_deleted: false,
+            lzma: LzmaOptions::default(),
+            summaries: false,
+            erase_block_size: None,
+            root_ino: ROOT_INO,
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+            max_total_size: DEFAULT_MAX_TOTAL_SIZE,
+            scanned: false,
+            dirents: HashMap::new(),
+            inodes: HashMap::new(),
+            xattrs: HashMap::new(),
+            xrefs: HashMap::new(),
+            deletions: HashMap::new(),
+            paths: OnceLock::new(),
+            progress: ProgressHook::default(),
+            cancel: None,
+        })
+    }
+
+    /// Read the image as it is or with its 16 bit words swapped, whichever
+    /// shows more node headers unless `swapped` says which
+    fn detect_layout(
+        buffer: ImageBuffer<'a>,
+        swapped: Option<bool>,
+    ) -> Result<(ImageBuffer<'a>, EndiannessDetection)> {
+        let nodes = |detection: EndiannessDetection| {
+            detection.little_endian_nodes + detection.big_endian_nodes
+        };
+        let swapped = match swapped {
+            Some(swapped) => swapped,
+            None => {
+                nodes(Jffs2Reader::detect_endianness(&buffer, true)?)
+                    > nodes(Jffs2Reader::detect_endianness(&buffer, false)?)
+            }
+        };
+
+        let detection = Jffs2Reader::detect_endianness(&buffer, swapped)?;
+        if swapped {
+            Ok((ImageBuffer::Swapped(Box::new(buffer)), detection))
+        } else {
+            Ok((buffer, detection))
+        }
+    }
+
+    /// Count the plausible node headers in the beginning of the image under
+    /// both byte order hypotheses. A header only counts when its magic,
+    /// header CRC and total length all check out, so stray 0x1985 values in
+    /// padding or file data don't sway the decision. With `swapped` the
+    /// bytes of every 16 bit word are exchanged first.
+    fn detect_endianness(buffer: &ImageBuffer, swapped: bool) -> Result<EndiannessDetection> {
+        let mut window = buffer.read(0, ENDIANNESS_DETECTION_WINDOW.min(buffer.len()))?;
+        if swapped {
+            for word in window.to_mut().chunks_exact_mut(2) {
+                word.swap(0, 1);
+            }
+        }
+        let mut detection = EndiannessDetection {
+            little_endian_nodes: 0,
+            big_endian_nodes: 0,
+            overridden: false,
+            swapped,
+        };
+
+        let mut idx = 0;
+        while idx + SIZE_OF_NODE_HEADER <= window.len() {
+            if Jffs2Reader::is_node_header(&window, idx, buffer.len(), true) {
+                detection.little_endian_nodes += 1;
+            } else if Jffs2Reader::is_node_header(&window, idx, buffer.len(), false) {
+                detection.big_endian_nodes += 1;
+            }
+            idx += 4;
+        }
+
+        Ok(detection)
+    }
+
+    /// Check the header at `offset` in `window`, the start of an image of
+    /// `image_len` bytes
+    fn is_node_header(window: &[u8], offset: usize, image_len: usize, little_endian: bool) -> bool {
+        match Jffs2Reader::read_uint16(window, little_endian, offset) {
+            Ok(JFFS2_MAGIC) => {}
+            _ => return false,
+        }
+
+        let totlen = match Jffs2Reader::read_uint32(window, little_endian, offset + 4) {
+            Ok(totlen) => totlen as usize,
+            Err(_) => return false,
+        };
+        if totlen < SIZE_OF_NODE_HEADER || totlen > image_len - offset {
+            return false;
+        }
+
+        match Jffs2Reader::read_uint32(window, little_endian, offset + 8) {
+            Ok(hdr_crc) => hdr_crc == jffs2_crc32(&window[offset..offset + 8]),
+            Err(_) => false,
+        }
+    }
+
+    /// The data nodes read so far whose data CRC didn't match, by reads and
+    /// extractions alike, empty unless a data CRC check was set on the
+    /// [`Jffs2ReaderBuilder`]
+    pub fn data_crc_mismatches(&self) -> Vec<DataCrcMismatch> {
+        self.data_crc_mismatches.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Offsets of the dirents whose name didn't match its CRC during the
+    /// scan, empty unless [`Jffs2ReaderBuilder::verify_name_crc`] was set
+    pub fn name_crc_failures(&self) -> &[u64] {
+        &self.name_crc_failures
+    }
+
+    /// Counts of the nodes the scan came across, all zero before the scan
+    pub fn stats(&self) -> &ScanStats {
+        &self.stats
+    }
+
+    /// Offsets of the nodes a lenient scan left out because they failed to
+    /// parse or ran past the end of the image
+    pub fn skipped_nodes(&self) -> &[u64] {
+        &self.skipped_nodes
+    }
+
+    /// Offsets of the nodes whose node CRC didn't match during the scan,
+    /// empty unless [`Jffs2ReaderBuilder::verify_node_crc`] was set
+    pub fn node_crc_failures(&self) -> &[u64] {
+        &self.node_crc_failures
+    }
+
+    /// Byte order the image is read in, detected or forced on the builder
+    pub fn endianness(&self) -> Endianness {
+        if self.little_endian {
+            Endianness::Little
+        } else {
+            Endianness::Big
+        }
+    }
+
+    /// Size and byte order of the image
+    pub fn image_info(&self) -> ImageInfo {
+        ImageInfo {
+            size: self.buffer.len() as u64,
+            endianness: self.endianness(),
+            detection: self.detection,
+        }
+    }
+
+    fn read_uint32(buffer: &[u8], little_endian: bool, offset: usize) -> Result<u32> {
+        if offset + 4 > buffer.len() {
+            bail!(
+                "offset out of bounds: {} in a buffer of {}",
+                offset,
+                buffer.len()
+            );
+        }
+        let buffer = &buffer[offset..offset + 4];
 
         Ok(if little_endian {
             u32::from_le_bytes(buffer.try_into().unwrap())
         } else {
-            u32::from_be_bytes(buffer.try_into().unwrap())
-        })
+            u32::from_be_bytes(buffer.try_into().unwrap())
+        })
+    }
+
+    fn read_uint16(buffer: &[u8], little_endian: bool, offset: usize) -> Result<u16> {
+        if offset + 2 > buffer.len() {
+            bail!(
+                "offset out of bounds: {} in a buffer of {}",
+                offset,
+                buffer.len()
+            );
+        }
+        let buffer = &buffer[offset..offset + 2];
+
+        Ok(if little_endian {
+            u16::from_le_bytes(buffer.try_into().unwrap())
+        } else {
+            u16::from_be_bytes(buffer.try_into().unwrap())
+        })
+    }
+
+    /// Read a string with at most `length` bytes, but will truncate before
+    /// that if there is a null byte.
+    fn read_str(buffer: &[u8], offset: usize, length: usize) -> Result<String> {
+        Ok(String::from_utf8(Jffs2Reader::read_bytes(buffer, offset, length)?)?)
+    }
+
+    /// Read a file name like [`Jffs2Reader::read_str`], see [`os_string`]
+    fn read_name(buffer: &[u8], offset: usize, length: usize) -> Result<OsString> {
+        Ok(os_string(Jffs2Reader::read_bytes(buffer, offset, length)?))
+    }
+
+    /// The bytes of [`Jffs2Reader::read_str`]
+    fn read_bytes(buffer: &[u8], offset: usize, length: usize) -> Result<Vec<u8>> {
+        if offset >= buffer.len() {
+            bail!(
+                "offset out of bounds: {} in a buffer of {}",
+                offset,
+                buffer.len()
+            );
+        }
+
+        let str_bytes = buffer
+            .iter()
+            .skip(offset)
+            .take(length)
+            .take_while(|b| **b != 0)
+            .copied()
+            .collect();
+        Ok(str_bytes)
+    }
+
+    /// Unpack the next fields of a node in the byte order of the image
+    fn unpack<T: UnpackFrom>(&self, cur: &mut std::io::Cursor<&[u8]>) -> Result<T> {
+        if self.little_endian {
+            Ok(T::unpack_from_le(cur)?)
+        } else {
+            Ok(T::unpack_from_be(cur)?)
+        }
+    }
+
+    /// Parse the dirent node at `idx` whose data is `mm`, none if it is
+    /// truncated
+    fn scan_dirent(&self, mm: &[u8], idx: usize) -> Result<Option<Jffs2Dirent>> {
+        if mm.len() < SIZE_OF_DIRENT {
+            return Ok(None);
+        }
+
+        let mut cur = std::io::Cursor::new(mm);
+
+        let (pino, version, ino, mctime) = self.unpack::<(u32, u32, u32, u32)>(&mut cur)?;
+        let (nsize, ntype) = self.unpack::<(u8, u8)>(&mut cur)?;
+        let (_unused, _node_crc, _name_crc) = self.unpack::<(u16, u32, u32)>(&mut cur)?;
+
+        if nsize as usize + SIZE_OF_DIRENT > mm.len() {
+            bail!("out of bounds when reading filename");
+        }
+
+        let fname = Jffs2Reader::read_name(mm, cur.position() as usize, nsize as usize)?;
+        // anything but a single path component could lead out of the
+        // extraction directory
+        if !is_file_name(&fname) {
+            bail!("{:?} is not a valid file name", fname);
+        }
+        Ok(Some(Jffs2Dirent {
+            pino,
+            version,
+            ino,
+            mctime,
+            ntype,
+            fname,
+            offset: idx as u64,
+        }))
+    }
+
+    /// Keep `dirent` unless a newer one with the same parent and name is
+    /// already known
+    fn insert_dirent(&mut self, dirent: Jffs2Dirent) {
+        let names = self.dirents.entry(dirent.pino).or_default();
+        match names.get(&dirent.fname) {
+            Some(old_dirent) if old_dirent.version > dirent.version => {}
+            _ => {
+                names.insert(dirent.fname.clone(), dirent);
+            }
+        }
+    }
+
+    /// Parse the inode node whose data starts at `idx`, `mm` only holds its
+    /// header and `len` is the size of the whole node without the common
+    /// header. None if it is truncated or superseded.
+    fn scan_inode(&self, mm: &[u8], len: usize, idx: usize) -> Result<Option<Jffs2Inode>> {
+        if mm.len() < SIZE_OF_INODE {
+            return Ok(None);
+        }
+
+        let mut cur = std::io::Cursor::new(mm);
+
+        let (ino, version, mode, uid, gid) = self.unpack::<(u32, u32, u32, u16, u16)>(&mut cur)?;
+        let (isize, atime, mtime, ctime) = self.unpack::<(u32, u32, u32, u32)>(&mut cur)?;
+        let (foffset, csize, dsize, compr, _usercompr) =
+            self.unpack::<(u32, u32, u32, u8, u8)>(&mut cur)?;
+        let (_flags, data_crc, _node_crc) = self.unpack::<(u16, u32, u32)>(&mut cur)?;
+
+        if csize as usize + SIZE_OF_INODE > len {
+            bail!("out of bounds when reading data");
+        }
+
+        // nothing of a node a newer one entirely covers is ever read
+        let (start, end) = (foffset as u64, foffset as u64 + dsize as u64);
+        if let Some(inodes) = self.inodes.get(&ino) {
+            for old_inode in inodes {
+                let old_start = old_inode.offset as u64;
+                let old_end = old_start + old_inode.dsize as u64;
+                if old_inode.version > version && old_start <= start && end <= old_end {
+                    return Ok(None);
+                }
+            }
+        }
+        let data = (idx + SIZE_OF_INODE) as u64;
+        Ok(Some(Jffs2Inode {
+            ino,
+            version,
+            mode,
+            uid,
+            gid,
+            iszie: isize,
+            atime,
+            mtime,
+            ctime,
+            offset: foffset,
+            csize,
+            dsize,
+            compr,
+            data_crc,
+            data,
+        }))
+    }
+
+    /// Parse the xattr node `mm`, none if it is truncated, superseded or
+    /// has an unknown name prefix
+    fn scan_xattr(&self, mm: &[u8]) -> Result<Option<(u32, Jffs2Xattr)>> {
+        if mm.len() < SIZE_OF_XATTR {
+            return Ok(None);
+        }
+
+        let mut cur = std::io::Cursor::new(mm);
+
+        let (xid, version) = self.unpack::<(u32, u32)>(&mut cur)?;
+        let (xprefix, name_len, value_len) = self.unpack::<(u8, u8, u16)>(&mut cur)?;
+        let (_data_crc, _node_crc) = self.unpack::<(u32, u32)>(&mut cur)?;
+
+        if let Some(old_xattr) = self.xattrs.get(&xid) {
+            if old_xattr.version > version {
+                return Ok(None);
+            }
+        }
+        if version == XATTR_DELETE_MARKER {
+            let deleted = Jffs2Xattr {
+                version,
+                name: String::new(),
+                value: vec![],
+            };
+            return Ok(Some((xid, deleted)));
+        }
+
+        // the name and the value are separated by a NUL
+        let (name_len, value_len) = (name_len as usize, value_len as usize);
+        if SIZE_OF_XATTR + name_len + 1 + value_len > mm.len() {
+            bail!("out of bounds when reading xattr");
+        }
+        let name = Jffs2Reader::read_str(mm, SIZE_OF_XATTR, name_len)?;
+        let value = &mm[SIZE_OF_XATTR + name_len + 1..][..value_len];
+        let (name, value) = match xprefix {
+            JFFS2_XPREFIX_USER => (format!("user.{}", name), value.to_vec()),
+            JFFS2_XPREFIX_SECURITY => (format!("security.{}", name), value.to_vec()),
+            JFFS2_XPREFIX_TRUSTED => (format!("trusted.{}", name), value.to_vec()),
+            JFFS2_XPREFIX_ACL_ACCESS => ("system.posix_acl_access".into(), self.posix_acl(value)?),
+            JFFS2_XPREFIX_ACL_DEFAULT => {
+                ("system.posix_acl_default".into(), self.posix_acl(value)?)
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some((xid, Jffs2Xattr { version, name, value })))
+    }
+
+    /// Convert an ACL from the jffs2 layout, in the image byte order and
+    /// without an id for the owner, group, mask and other entries, to the
+    /// layout of the system.posix_acl_* attributes
+    fn posix_acl(&self, value: &[u8]) -> Result<Vec<u8>> {
+        let le = self.little_endian;
+        let version = Jffs2Reader::read_uint32(value, le, 0)?;
+        if version != JFFS2_ACL_VERSION {
+            bail!("unsupported ACL version {}", version);
+        }
+
+        let mut acl = POSIX_ACL_XATTR_VERSION.to_le_bytes().to_vec();
+        let mut pos = 4;
+        while pos < value.len() {
+            let tag = Jffs2Reader::read_uint16(value, le, pos)?;
+            let perm = Jffs2Reader::read_uint16(value, le, pos + 2)?;
+            let id = match tag {
+                ACL_USER | ACL_GROUP => {
+                    pos += 8;
+                    Jffs2Reader::read_uint32(value, le, pos - 4)?
+                }
+                _ => {
+                    pos += 4;
+                    ACL_UNDEFINED_ID
+                }
+            };
+            acl.extend(tag.to_le_bytes());
+            acl.extend(perm.to_le_bytes());
+            acl.extend(id.to_le_bytes());
+        }
+        Ok(acl)
+    }
+
+    /// Parse the xref node `mm` into its inode number, xattr id and
+    /// sequence number, none if it is truncated
+    fn scan_xref(&self, mm: &[u8]) -> Result<Option<(u32, u32, u32)>> {
+        if mm.len() < SIZE_OF_XREF {
+            return Ok(None);
+        }
+
+        let mut cur = std::io::Cursor::new(mm);
+        Ok(Some(self.unpack::<(u32, u32, u32)>(&mut cur)?))
+    }
+
+    fn pad(x: usize) -> usize {
+        if !x.is_multiple_of(4) {
+            x + (4 - (x % 4))
+        } else {
+            x
+        }
+    }
+
+    /// Scan the image for dirents and inodes, later calls do nothing
+    pub fn scan(&mut self) -> Result<()> {
+        let mut hook = self.progress.0.get_mut().unwrap_or_else(|e| e.into_inner()).take();
+        let result = match hook.as_mut() {
+            Some(hook) => self.scan_with_progress(
+                HOOK_PROGRESS_BYTES,
+                HOOK_PROGRESS_INTERVAL,
+                |progress| hook(Progress::Scan(*progress)),
+            ),
+            None => self.scan_with_progress(u64::MAX, Duration::MAX, |_| {}),
+        };
+        *self.progress.0.get_mut().unwrap_or_else(|e| e.into_inner()) = hook;
+        result
+    }
+
+    /// Stop [`Jffs2Reader::scan`] and the extractions with a [`Cancelled`]
+    /// error once `token` is set. Extraction stops between files, and a file
+    /// being written when it does is removed.
+    pub fn set_cancel(&mut self, token: Arc<AtomicBool>) {
+        self.cancel = Some(token);
+    }
+
+    /// Fail with [`Cancelled`] if the cancellation token is set
+    fn check_cancelled(&self) -> Result<()> {
+        match &self.cancel {
+            Some(token) if token.load(Ordering::Relaxed) => Err(Cancelled.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Call `hook` with the progress of [`Jffs2Reader::scan`] and of the
+    /// extractions, it runs on the thread doing the work and should be quick
+    pub fn set_progress(&mut self, hook: impl FnMut(Progress<'_>) + Send + 'a) {
+        *self.progress.0.get_mut().unwrap_or_else(|e| e.into_inner()) = Some(Box::new(hook));
+    }
+
+    /// Scan the image, calling `progress` whenever `every_bytes` bytes were
+    /// scanned or `every` elapsed since the previous call, and once more
+    /// when the scan is over. The clock is only read every 64KB so a tight
+    /// time interval doesn't slow the scan down. Does nothing, and reports
+    /// nothing, if the image was already scanned.
+    pub fn scan_with_progress(
+        &mut self,
+        every_bytes: u64,
+        every: Duration,
+        mut progress: impl FnMut(&ScanProgress),
+    ) -> Result<()> {
+        if self.scanned {
+            return Ok(());
+        }
+
+        let mut idx = 0;
+        let maxmm = self.buffer.len();
+
+        let mut nodes = 0;
+        let mut last_report = 0;
+        let mut last_clock_check = 0;
+        let mut last_report_time = Instant::now();
+        let report = |offset: u64, nodes: u64| ScanProgress {
+            offset,
+            bytes_scanned: offset,
+            nodes,
+            image_size: maxmm as u64,
+        };
+
+        let block_size = match self.summaries {
+            true => self.erase_block_size.or_else(|| self.detect_erase_block_size()),
+            false => None,
+        };
+
+        let mut steps = 0u32;
+        while idx + SIZE_OF_NODE_HEADER < maxmm {
+            if steps.is_multiple_of(CANCEL_CHECK_STEPS) {
+                if let Err(e) = self.check_cancelled() {
+                    // a later scan starts over
+                    self.dirents.clear();
+                    self.inodes.clear();
+                    self.xattrs.clear();
+                    self.xrefs.clear();
+                    self.node_crc_failures.clear();
+                    self.name_crc_failures.clear();
+                    self.skipped_nodes.clear();
+                    self.deletions.clear();
+                    self.stats = ScanStats::default();
+                    return Err(e);
+                }
+            }
+            steps = steps.wrapping_add(1);
+
+            let offset = idx as u64;
+            if offset - last_report >= every_bytes
+                || (offset - last_clock_check >= SCAN_PROGRESS_CLOCK_CHECK && {
+                    last_clock_check = offset;
+                    last_report_time.elapsed() >= every
+                })
+            {
+                progress(&report(offset, nodes));
+                last_report = offset;
+                last_clock_check = offset;
+                last_report_time = Instant::now();
+            }
+
+            if let Some(block_size) = block_size.filter(|size| idx.is_multiple_of(*size)) {
+                if let Some(listed) = self.scan_summary(idx, block_size)? {
+                    nodes += listed;
+                    idx += block_size;
+                    continue;
+                }
+            }
+
+            let header = self.buffer.read(idx, SIZE_OF_NODE_HEADER)?;
+            let magic = Jffs2Reader::read_uint16(&header, self.little_endian, 0)?;
+            if magic != JFFS2_MAGIC {
+                let empty = header.iter().all(|b| *b == 0) || header.iter().all(|b| *b == 0xff);
+                drop(header);
+                // plus 4 here, rather than 2
+                idx += 4;
+                if empty {
+                    // stop at the next erase block, it may have a summary
+                    let end = match block_size {
+                        Some(size) => idx.next_multiple_of(size).min(maxmm),
+                        None => maxmm,
+                    };
+                    idx = self.skip_empty(idx, end)?;
+                }
+                continue;
+            }
+
+            let nodetype = Jffs2Reader::read_uint16(&header, self.little_endian, 2)?;
+            let totlen = Jffs2Reader::read_uint32(&header, self.little_endian, 4)?;
+            let hdr_crc = Jffs2Reader::read_uint32(&header, self.little_endian, 8)?;
+            if hdr_crc != jffs2_crc32(&header[..8]) {
+                // a stray magic in garbage or a damaged header, its length
+                // can't be trusted either way
+                idx += 4;
+                continue;
+            }
+            drop(header);
+
+            // totlen includes the 12 header bytes
+            let totlen = totlen as usize;
+            match NodeType::try_from(nodetype) {
+                // nothing follows the header but erased space, whatever
+                // size mkfs was told to give it
+                Ok(NodeType::CleanMarker) => {
+                    self.stats.node(nodetype);
+                    idx += SIZE_OF_NODE_HEADER;
+                    continue;
+                }
+                // may cover the rest of the erase block, but a length past
+                // the end of the image is only stepped over
+                Ok(NodeType::Padding) => {
+                    self.stats.node(nodetype);
+                    idx += if (SIZE_OF_NODE_HEADER..=maxmm - idx).contains(&totlen) {
+                        Jffs2Reader::pad(totlen)
+                    } else {
+                        SIZE_OF_NODE_HEADER
+                    };
+                    continue;
+                }
+                _ => {}
+            }
+            if totlen > maxmm - idx {
+                if !self.lenient {
+                    break;
+                }
+                // a partial node at the end of a truncated dump, or a
+                // damaged length with more nodes after it
+                let e = anyhow!("the node of {} bytes runs past the end of the image", totlen);
+                self.skip_node(idx, e)?;
+                idx += 4;
+                continue;
+            }
+            if totlen < SIZE_OF_NODE_HEADER {
+                idx += 4;
+                continue;
+            }
+
+            match self.scan_node(idx, nodetype, totlen)? {
+                NodeScan::Parsed => {
+                    self.stats.node(nodetype);
+                    nodes += 1;
+                }
+                NodeScan::Other => self.stats.node(nodetype),
+                // look for a node inside the broken one
+                NodeScan::Skipped => {
+                    idx += 4;
+                    continue;
+                }
+            }
+
+            idx += Jffs2Reader::pad(totlen);
+        }
+
+        // a deletion only hides the dirents older than itself, the name
+        // may have been reused since
+        if !self.include_deleted {
+            for (pino, deleted) in &self.deletions {
+                if let Some(names) = self.dirents.get_mut(pino) {
+                    names.retain(|name, dirent| {
+                        deleted.get(name).is_none_or(|version| *version < dirent.version)
+                    });
+                }
+            }
+        }
+
+        if let Some(NameCrcCheck::Quarantine(name)) = &self.name_crc {
+            if self.dirents.contains_key(&QUARANTINE_INO) {
+                let quarantine = Jffs2Dirent {
+                    pino: self.root_ino,
+                    version: 0,
+                    ino: QUARANTINE_INO,
+                    mctime: 0,
+                    ntype: DT_DIR,
+                    fname: name.into(),
+                    offset: 0,
+                };
+                self.dirents.entry(self.root_ino).or_default().insert(name.into(), quarantine);
+            }
+        }
+
+        self.stats.skipped = self.skipped_nodes.len() as u64;
+        self.stats.node_crc_failures = self.node_crc_failures.len() as u64;
+        self.stats.bytes_scanned = maxmm as u64;
+        self.scanned = true;
+        self.paths = OnceLock::new();
+        progress(&report(maxmm as u64, nodes));
+        Ok(())
+    }
+
+    /// Parse the dirent, inode, xattr or xref node at `idx`, of `totlen`
+    /// bytes header included
+    fn scan_node(&mut self, idx: usize, nodetype: u16, totlen: usize) -> Result<NodeScan> {
+        // the nodes are parsed in place, only what they yield is kept
+        let nodetype = NodeType::try_from(nodetype).ok();
+        if nodetype == Some(NodeType::Dirent) {
+            let node = self.buffer.read(idx, totlen)?;
+            // the node CRC is followed by the name CRC
+            let crc_ok = self.node_crc_ok(&node, 32, 32);
+            let name_ok = self.name_crc_ok(&node);
+            let dirent = self.scan_dirent(&node[12..], idx);
+            drop(node);
+            if !crc_ok && !self.node_crc_failed(idx)? {
+                return Ok(NodeScan::Parsed);
+            }
+            match dirent {
+                // an unlink, the dirents it supersedes go at the end of the scan
+                Ok(Some(dirent)) if dirent.ino == 0 => {
+                    if !name_ok {
+                        self.name_crc_failures.push(idx as u64);
+                        return Ok(NodeScan::Parsed);
+                    }
+                    self.deletions
+                        .entry(dirent.pino)
+                        .or_default()
+                        .entry(dirent.fname)
+                        .and_modify(|version| *version = dirent.version.max(*version))
+                        .or_insert(dirent.version);
+                }
+                Ok(Some(mut dirent)) => {
+                    if !name_ok {
+                        self.name_crc_failures.push(idx as u64);
+                        match &self.name_crc {
+                            Some(NameCrcCheck::Quarantine(_)) => {
+                                dirent.pino = QUARANTINE_INO;
+                                dirent.fname = format!("ino_{}", dirent.ino).into();
+                            }
+                            _ => return Ok(NodeScan::Parsed),
+                        }
+                    }
+                    self.insert_dirent(dirent);
+                }
+                Ok(None) => {}
+                Err(e) => return self.skip_node(idx, e),
+            }
+        } else if nodetype == Some(NodeType::Inode) {
+            // the compressed data is left alone until the file is read
+            let len = totlen - 12;
+            let node = self.buffer.read(idx, 12 + len.min(SIZE_OF_INODE))?;
+            // the node CRC follows the data CRC, which it doesn't cover
+            let crc_ok = self.node_crc_ok(&node, 60, 64);
+            let inode = self.scan_inode(&node[12..], len, idx + 12);
+            drop(node);
+            if !crc_ok && !self.node_crc_failed(idx)? {
+                return Ok(NodeScan::Parsed);
+            }
+            match inode {
+                Ok(Some(inode)) => self.inodes.entry(inode.ino).or_default().push(inode),
+                Ok(None) => {}
+                Err(e) => return self.skip_node(idx, e),
+            }
+        } else if nodetype == Some(NodeType::Xattr) {
+            let slice = self.buffer.read(idx + 12, totlen - 12)?;
+            match self.scan_xattr(&slice) {
+                Ok(Some((xid, xattr))) => {
+                    self.xattrs.insert(xid, xattr);
+                }
+                Ok(None) => {}
+                Err(e) => return self.skip_node(idx, e),
+            }
+        } else if nodetype == Some(NodeType::Xref) {
+            let slice = self.buffer.read(idx + 12, totlen - 12)?;
+            match self.scan_xref(&slice) {
+                Ok(Some((ino, xid, xseqno))) => {
+                    self.xrefs
+                        .entry(ino)
+                        .or_default()
+                        .entry(xid)
+                        .and_modify(|seqno| *seqno = xseqno.max(*seqno))
+                        .or_insert(xseqno);
+                }
+                Ok(None) => {}
+                Err(e) => return self.skip_node(idx, e),
+            }
+        } else {
+            return Ok(NodeScan::Other);
+        }
+        Ok(NodeScan::Parsed)
+    }
+
+    /// Fail with `e`, the error parsing the node at `idx`, or in lenient
+    /// mode log and record it and go on
+    fn skip_node(&mut self, idx: usize, e: anyhow::Error) -> Result<NodeScan> {
+        if !self.lenient {
+            return Err(e);
+        }
+        log::warn!("skipping the node at offset {:#x}: {}", idx, e);
+        self.skipped_nodes.push(idx as u64);
+        Ok(NodeScan::Skipped)
+    }
+
+    /// Whether the name of the dirent `node` matches its CRC. Always true
+    /// when not verifying.
+    fn name_crc_ok(&self, node: &[u8]) -> bool {
+        self.name_crc.is_none() || self.name_crc_matches(node)
+    }
+
+    /// Whether the name of the dirent `node` matches its CRC, true if the
+    /// node is too short to hold one
+    pub(crate) fn name_crc_matches(&self, node: &[u8]) -> bool {
+        let nsize = match node.get(SIZE_OF_NODE_HEADER + 16) {
+            Some(nsize) => *nsize as usize,
+            None => return true,
+        };
+        let start = SIZE_OF_NODE_HEADER + SIZE_OF_DIRENT;
+        match (
+            Jffs2Reader::read_uint32(node, self.little_endian, start - 4),
+            node.get(start..start + nsize),
+        ) {
+            (Ok(name_crc), Some(name)) => name_crc == jffs2_crc32(name),
+            // too short to be parsed anyway
+            _ => true,
+        }
+    }
+
+    /// Whether the node CRC stored at `at` matches the one of the `covered`
+    /// bytes starting the node. Always true when not verifying.
+    fn node_crc_ok(&self, node: &[u8], covered: usize, at: usize) -> bool {
+        self.node_crc.is_none() || self.node_crc_matches(node, covered, at)
+    }
+
+    /// Whether the node CRC stored at `at` matches the one of the `covered`
+    /// bytes starting the node, true if the node is too short to hold one
+    pub(crate) fn node_crc_matches(&self, node: &[u8], covered: usize, at: usize) -> bool {
+        match Jffs2Reader::read_uint32(node, self.little_endian, at) {
+            Ok(node_crc) => node_crc == jffs2_crc32(&node[..covered]),
+            // too short to be parsed anyway
+            Err(_) => true,
+        }
+    }
+
+    /// Record the node at `idx` whose node CRC doesn't match, returns
+    /// whether it should still be used
+    fn node_crc_failed(&mut self, idx: usize) -> Result<bool> {
+        match self.node_crc {
+            Some(NodeCrcCheck::Fail) => bail!("bad node CRC at offset {:#x}", idx),
+            Some(NodeCrcCheck::Skip) => {
+                self.node_crc_failures.push(idx as u64);
+                Ok(false)
+            }
+            _ => {
+                self.node_crc_failures.push(idx as u64);
+                Ok(true)
+            }
+        }
+    }
+
+    /// The type and length of the node at `idx`, if its header is valid
+    fn node_header(&self, idx: usize) -> Result<Option<(u16, usize)>> {
+        if idx + SIZE_OF_NODE_HEADER > self.buffer.len() {
+            return Ok(None);
+        }
+        let header = self.buffer.read(idx, SIZE_OF_NODE_HEADER)?;
+        let magic = Jffs2Reader::read_uint16(&header, self.little_endian, 0)?;
+        let nodetype = Jffs2Reader::read_uint16(&header, self.little_endian, 2)?;
+        let totlen = Jffs2Reader::read_uint32(&header, self.little_endian, 4)? as usize;
+        let hdr_crc = Jffs2Reader::read_uint32(&header, self.little_endian, 8)?;
+        if magic != JFFS2_MAGIC
+            || hdr_crc != jffs2_crc32(&header[..8])
+            || totlen < SIZE_OF_NODE_HEADER
+            || totlen > self.buffer.len() - idx
+        {
+            return Ok(None);
+        }
+        Ok(Some((nodetype, totlen)))
+    }
+
+    /// The summary node closing the erase block at `block`, as its offset
+    /// in the image and its bytes, if the block has a valid one
+    fn summary_node(&self, block: usize, block_size: usize) -> Result<Option<(usize, Vec<u8>)>> {
+        let end = block + block_size;
+        if block_size < SIZE_OF_SUMMARY + 8 || end > self.buffer.len() {
+            return Ok(None);
+        }
+        let marker = self.buffer.read(end - 8, 8)?;
+        let offset = Jffs2Reader::read_uint32(&marker, self.little_endian, 0)? as usize;
+        let magic = Jffs2Reader::read_uint32(&marker, self.little_endian, 4)?;
+        if magic != JFFS2_SUM_MAGIC || offset > block_size - SIZE_OF_SUMMARY - 8 {
+            return Ok(None);
+        }
+
+        let at = block + offset;
+        match self.node_header(at)? {
+            Some((nodetype, totlen))
+                if nodetype == NodeType::Summary as u16 && totlen >= SIZE_OF_SUMMARY =>
+            {
+                let summary = self.buffer.read(at, totlen.min(end - at))?.into_owned();
+                let node_crc = Jffs2Reader::read_uint32(&summary, self.little_endian, 28)?;
+                if node_crc != jffs2_crc32(&summary[..24]) {
+                    return Ok(None);
+                }
+                Ok(Some((at, summary)))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// The smallest erase block size whose first block ends with a summary
+    fn detect_erase_block_size(&self) -> Option<usize> {
+        ERASE_BLOCK_SIZES
+            .map(|shift| 1usize << shift)
+            .find(|size| matches!(self.summary_node(0, *size), Ok(Some(_))))
+    }
+
+    /// Parse the nodes listed by the summary of the erase block at `block`,
+    /// returns how many there were, or `None` if the block has no usable
+    /// summary and has to be scanned node by node
+    fn scan_summary(&mut self, block: usize, block_size: usize) -> Result<Option<u64>> {
+        let Some((at, summary)) = self.summary_node(block, block_size)? else {
+            return Ok(None);
+        };
+        let le = self.little_endian;
+        let count = Jffs2Reader::read_uint32(&summary, le, 12)?;
+
+        // every entry starts with the type of the node it describes
+        let mut listed = vec![];
+        let mut pos = SIZE_OF_SUMMARY;
+        for _ in 0..count {
+            let Ok(nodetype) = Jffs2Reader::read_uint16(&summary, le, pos) else {
+                return Ok(None);
+            };
+            // the offset field of the entry and the length of the entry
+            let (field, len) = match NodeType::try_from(nodetype) {
+                Ok(NodeType::Inode) => (Some(pos + 10), 18),
+                Ok(NodeType::Dirent) => match summary.get(pos + 22) {
+                    Some(nsize) => (Some(pos + 6), 24 + *nsize as usize),
+                    None => return Ok(None),
+                },
+                Ok(NodeType::Xattr) => (Some(pos + 10), 18),
+                Ok(NodeType::Xref) => (Some(pos + 2), 6),
+                _ => return Ok(None),
+            };
+            if let Some(field) = field {
+                match Jffs2Reader::read_uint32(&summary, le, field) {
+                    Ok(offset) if (offset as usize) < at - block => {
+                        listed.push(block + offset as usize)
+                    }
+                    _ => return Ok(None),
+                }
+            }
+            pos += len;
+        }
+
+        // check all the headers first, so a stale summary leaves nothing behind
+        let mut headers = Vec::with_capacity(listed.len());
+        for idx in listed {
+            match self.node_header(idx)? {
+                Some((nodetype, totlen)) if idx + totlen <= at => {
+                    headers.push((idx, nodetype, totlen))
+                }
+                _ => return Ok(None),
+            }
+        }
+        let mut nodes = 0;
+        for (idx, nodetype, totlen) in headers {
+            if self.scan_node(idx, nodetype, totlen)? == NodeScan::Parsed {
+                self.stats.node(nodetype);
+                nodes += 1;
+            }
+        }
+        self.stats.node(NodeType::Summary as u16);
+        Ok(Some(nodes))
+    }
+
+    /// Skip the erased or zeroed pages from `idx`, which hold no node, up to
+    /// [`EMPTY_SKIP_LIMIT`] bytes
+    fn skip_empty(&self, mut idx: usize, end: usize) -> Result<usize> {
+        let limit = idx + EMPTY_SKIP_LIMIT;
+        while idx + ZERO_PAGE.len() <= end.min(limit) {
+            let page = self.buffer.read(idx, ZERO_PAGE.len())?;
+            if page[..] != ZERO_PAGE[..] && page[..] != ERASED_PAGE[..] {
+                break;
+            }
+            idx += ZERO_PAGE.len();
+        }
+        Ok(idx)
+    }
+
+    /// The compressed data of an inode node, with its CRC checked if asked
+    /// to. None if it doesn't match and the node should read as zeros.
+    fn node_data(&self, inode: &Jffs2Inode) -> Result<Option<Cow<'_, [u8]>>> {
+        let input = self
+            .buffer
+            .read(inode.data as usize, inode.csize as usize)?;
+        let Some(check) = self.data_crc else {
+            return Ok(Some(input));
+        };
+        let crc = jffs2_crc32(&input);
+        if crc == inode.data_crc {
+            return Ok(Some(input));
+        }
+
+        if check == DataCrcCheck::Fail {
+            bail!(
+                "inode {} data CRC mismatch at offset {:#x}: expected {:#010x}, got {:#010x}",
+                inode.ino,
+                inode.data,
+                inode.data_crc,
+                crc
+            );
+        }
+        let path = match self.dirent_of(inode.ino).map(|dirent| self.resolve_dirent(dirent)) {
+            Some(Ok(path)) => path.as_path().jffs_fix(),
+            _ => PathBuf::new(),
+        };
+        let offset = inode.node_offset();
+        log::warn!("{}: data CRC mismatch in the node at offset {:#x}", path.display(), offset);
+        self.data_crc_mismatches
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(DataCrcMismatch {
+                path,
+                offset,
+                expected: inode.data_crc,
+                actual: crc,
+            });
+        Ok((check == DataCrcCheck::Warn).then_some(input))
+    }
+
+    /// The header lzma_decompress expects in front of a raw LZMA node
+    fn lzma_header(&self, inode: &Jffs2Inode) -> [u8; 13] {
+        // lzma_header = struct.pack("<BIQ", PROPERTIES, DICT_SIZE, outlen)
+        let mut header = [0u8; 13];
+        header[0] = self.lzma.properties();
+        header[1..5].copy_from_slice(&self.lzma.dict_size.to_le_bytes());
+        header[5..].copy_from_slice(&(inode.dsize as u64).to_le_bytes());
+        header
+    }
+
+    /// Decompress the data carried by a single inode node into `out`, which
+    /// is cleared first so a single buffer can serve all the nodes of a file
+    fn decompress_inode(&self, inode: &Jffs2Inode, out: &mut Vec<u8>) -> Result<()> {
+        out.clear();
+        if inode.dsize as u64 > self.max_file_size {
+            bail!(
+                "a node of inode {} holds {} bytes, over the limit of {}",
+                inode.ino,
+                inode.dsize,
+                self.max_file_size
+            );
+        }
+        // the kernel never writes another method, the node is damaged
+        let compr = inode.compression()?;
+        let Some(input) = self.node_data(inode)? else {
+            out.resize(inode.dsize as usize, 0);
+            return Ok(());
+        };
+        let input = &input[..];
+
+        if compr == Compression::None {
+            out.extend_from_slice(input);
+        } else if compr == Compression::Zero {
+            out.resize(inode.dsize as usize, 0);
+        } else if compr == Compression::Zlib {
+            // a small node may inflate to far more than it claims
+            let decomp = flate2::read::ZlibDecoder::new(input);
+            decomp.take(inode.dsize as u64).read_to_end(out)?;
+        } else if compr == Compression::Rtime {
+            rtime::decompress_into(input, inode.dsize as usize, out)?;
+        } else if compr == Compression::Lzo {
+            out.resize(inode.dsize as usize, 0);
+            let decompressed_size = inode.dsize as usize;
+
+            unsafe {
+                lzo1x_decompress_safe(
+                    input.as_ptr(),
+                    input.len(),
+                    out.as_mut_ptr(),
+                    &decompressed_size,
+                    std::ptr::null(),
+                );
+            }
+        } else if compr == Compression::Lzma {
+            // reconstruct the lzma header, followed by the compressed blob
+            let header = self.lzma_header(inode);
+            let mut input_reader = (&header[..]).chain(input);
+            lzma_decompress(&mut input_reader, out)?;
+        } else if compr == Compression::Dynrubin {
+            #[cfg(not(feature = "rubin"))]
+            bail!("JFFS2_COMPR_DYNRUBIN needs the rubin feature, which is not enabled");
+
+            // this is slow but it works
+            #[cfg(feature = "rubin")]
+            {
+                out.resize(inode.dsize as usize, 0);
+
+                unsafe {
+                    dynrubin_decompress(
+                        input.as_ptr(),
+                        out.as_mut_ptr(),
+                        input.len() as c_uint,
+                        inode.dsize,
+                    );
+                }
+            }
+        } else if compr == Compression::Rubinmips {
+            #[cfg(not(feature = "rubin"))]
+            bail!("JFFS2_COMPR_RUBINMIPS needs the rubin feature, which is not enabled");
+
+            // the decoder reads whole 32 bit words, keep it inside the buffer
+            #[cfg(feature = "rubin")]
+            {
+                let mut padded = input.to_vec();
+                padded.extend([0; 4]);
+                out.resize(inode.dsize as usize, 0);
+
+                unsafe {
+                    rubinmips_decompress(
+                        padded.as_ptr(),
+                        out.as_mut_ptr(),
+                        input.len() as c_uint,
+                        inode.dsize,
+                    );
+                }
+            }
+        } else if compr == Compression::Copy {
+            bail!("JFFS2_COMPR_COPY is never implemented!");
+        }
+
+        Ok(())
+    }
+
+    /// Decompress a single inode node straight into `out`, exactly its data
+    /// size, cut or zero-filled like [`Jffs2Reader::decompress_fragment`].
+    /// Stored, zero, zlib and LZMA nodes go through small fixed buffers, the
+    /// other methods need the whole node in `scratch` first.
+    fn write_inode(
+        &self,
+        inode: &Jffs2Inode,
+        out: &mut impl Write,
+        scratch: &mut Vec<u8>,
+    ) -> Result<u64> {
+        let compr = inode.compression()?;
+        let input = match compr {
+            Compression::Zero => None,
+            _ => self.node_data(inode)?,
+        };
+        let Some(input) = input else {
+            write_zeros(out, inode.dsize as u64)?;
+            return Ok(inode.dsize as u64);
+        };
+        let input = &input[..];
+        let dsize = inode.dsize as u64;
+        let written = if compr == Compression::None {
+            let input = &input[..input.len().min(dsize as usize)];
+            out.write_all(input)?;
+            input.len() as u64
+        } else if compr == Compression::Zlib {
+            let mut decomp = flate2::read::ZlibDecoder::new(input).take(dsize);
+            std::io::copy(&mut decomp, out)?
+        } else if compr == Compression::Lzma {
+            // the decoder flushes its window to `out` as it fills up
+            let header = self.lzma_header(inode);
+            lzma_decompress(&mut (&header[..]).chain(input), out)?;
+            dsize
+        } else {
+            self.decompress_inode(inode, scratch)?;
+            let data = &scratch[..scratch.len().min(dsize as usize)];
+            out.write_all(data)?;
+            data.len() as u64
+        };
+        // the next node starts at the data size, whatever came out
+        write_zeros(out, dsize - written)?;
+        Ok(dsize)
+    }
+
+    /// The data nodes of an inode number in version order, and the
+    /// fragments of the file they supply ordered by file offset. Where nodes
+    /// overlap the most recent one wins, and regular files end at the size
+    /// recorded in their most recent node.
+    fn fragments(&self, node: u32) -> (Vec<Jffs2Inode>, Vec<Fragment>) {
+        let mut nodes = match self.inodes.get(&node) {
+            Some(inodes) => inodes.clone(),
+            None => vec![],
+        };
+        // stable, so nodes of the same version keep their log order
+        nodes.sort_by_key(|inode| inode.version);
+
+        let mut map = FragmentMap::new();
+        for (i, inode) in nodes.iter().enumerate() {
+            map.insert(inode.offset as u64, inode.dsize as u64, i);
+        }
+        if let Some(len) = self.regular_file_len(node) {
+            map.truncate(len);
+        }
+        (nodes, map.into_fragments())
+    }
+
+    /// The data nodes of `node` supplying part of its content, ordered by the
+    /// offset of the first byte they supply
+    fn live_nodes(&self, node: u32) -> Vec<Jffs2Inode> {
+        let (nodes, fragments) = self.fragments(node);
+        let mut seen = vec![false; nodes.len()];
+        let mut live = vec![];
+        for frag in fragments {
+            if !std::mem::replace(&mut seen[frag.node], true) {
+                live.push(nodes[frag.node].clone());
+            }
+        }
+        live
+    }
+
+    /// Compression method, compressed and decompressed size of each data
+    /// node making up the regular file of `entry`, by file offset. Nodes
+    /// newer ones overwrote entirely are left out, those overwritten in part
+    /// count whole, and those with an unknown method are left out.
+    pub fn compression_summary(&self, entry: &Jffs2Entry) -> Vec<(Compression, u32, u32)> {
+        if !entry.is_file {
+            return vec![];
+        }
+        self.live_nodes(entry.ino)
+            .iter()
+            .filter_map(|inode| Some((inode.compression_method()?, inode.csize, inode.dsize)))
+            .collect()
+    }
+
+    /// Decompress `inode` into `out`, zero-filled up to its data size when
+    /// it comes out short
+    fn decompress_fragment(&self, inode: &Jffs2Inode, out: &mut Vec<u8>) -> Result<()> {
+        self.decompress_inode(inode, out)?;
+        if out.len() < inode.dsize as usize {
+            out.resize(inode.dsize as usize, 0);
+        }
+        Ok(())
+    }
+
+    /// The most recent node of an inode number, which carries its current metadata
+    fn latest_inode(&self, node: u32) -> Option<&Jffs2Inode> {
+        self.inodes
+            .get(&node)
+            .and_then(|inodes| inodes.iter().max_by_key(|inode| inode.version))
+    }
+
+    /// Size of the data of an inode number, the size recorded in the most
+    /// recent node for regular files
+    fn file_size(&self, node: u32) -> u64 {
+        if let Some(len) = self.regular_file_len(node) {
+            return len;
+        }
+        match self.inodes.get(&node) {
+            Some(inodes) => inodes.iter().map(|inode| inode.dsize as u64).sum(),
+            None => 0,
+        }
+    }
+
+    /// Fail when inode `node` claims more data than the file size limit
+    fn check_file_size(&self, node: u32) -> Result<()> {
+        let size = match self.regular_file_len(node) {
+            Some(len) => len,
+            None => self
+                .inodes
+                .get(&node)
+                .into_iter()
+                .flatten()
+                .map(|inode| inode.offset as u64 + inode.dsize as u64)
+                .max()
+                .unwrap_or(0),
+        };
+        if size > self.max_file_size {
+            bail!("inode {} is {} bytes, over the limit of {}", node, size, self.max_file_size);
+        }
+        Ok(())
+    }
+
+    /// Length of the regular file `node`, holes included, none for the
+    /// other kinds of files
+    fn regular_file_len(&self, node: u32) -> Option<u64> {
+        self.latest_inode(node)
+            .filter(|inode| FileKind::from_mode(inode.mode) == Some(FileKind::Regular))
+            .map(|inode| inode.iszie as u64)
+    }
+
+    /// Target of the symbolic link with inode number `node`
+    fn link_target(&self, node: u32) -> Result<PathBuf> {
+        Ok(PathBuf::from(os_string(self.read_inode_data(node)?)))
+    }
+
+    /// Type of the file with inode number `node`, taken from its mode and
+    /// from the dirent type when there is no inode
+    fn file_kind(&self, node: u32, ntype: u8) -> Option<FileKind> {
+        self.latest_inode(node)
+            .and_then(|inode| FileKind::from_mode(inode.mode))
+            .or_else(|| FileKind::from_dirent_type(ntype))
+    }
+
+    /// Major and minor number of the device node with inode number `node`
+    fn device_number(&self, node: u32) -> Result<(u32, u32)> {
+        let data = self.read_inode_data(node)?;
+        // union jffs2_device_node: the old 16 bit id or a new_encode_dev id
+        match data[..] {
+            [a, b] => {
+                let id = if self.little_endian {
+                    u16::from_le_bytes([a, b])
+                } else {
+                    u16::from_be_bytes([a, b])
+                } as u32;
+                Ok((id >> 8, id & 0xff))
+            }
+            [a, b, c, d] => {
+                let id = if self.little_endian {
+                    u32::from_le_bytes([a, b, c, d])
+                } else {
+                    u32::from_be_bytes([a, b, c, d])
+                };
+                Ok(((id & 0xfff00) >> 8, (id & 0xff) | ((id >> 12) & 0xfff00)))
+            }
+            _ => bail!("bad device number of {} bytes for node {}", data.len(), node),
+        }
+    }
+
+    /// Decompress and assemble all the data of an inode number, the holes
+    /// between the nodes are zero-filled
+    fn read_inode_data(&self, node: u32) -> Result<Vec<u8>> {
+        self.check_file_size(node)?;
+        let (nodes, fragments) = self.fragments(node);
+        let mut data = Vec::new();
+        let mut chunk = Vec::new();
+        let mut current = None;
+        for frag in fragments {
+            if current != Some(frag.node) {
+                self.decompress_fragment(&nodes[frag.node], &mut chunk)?;
+                current = Some(frag.node);
+            }
+            let (start, end) = (frag.offset as usize, (frag.offset + frag.len) as usize);
+            if end > data.len() {
+                data.resize(end, 0);
+            }
+            data[start..end].copy_from_slice(&chunk[frag.skip as usize..][..frag.len as usize]);
+        }
+        if let Some(len) = self.regular_file_len(node) {
+            data.resize(len as usize, 0);
+        }
+
+        Ok(data)
+    }
+
+    /// Directory structure used to resolve paths, built on first use
+    fn path_index(&self) -> &fs::PathIndex {
+        self.paths.get_or_init(|| fs::PathIndex::new(self))
+    }
+
+    /// Read the whole content of the regular file at `path`, symbolic links
+    /// are followed. `a/b/c` and `/a/b/c` both start from the image root and
+    /// names are compared case-sensitively.
+    pub fn read_file(&self, path: impl AsRef<Path>) -> Result<Vec<u8>> {
+        self.read_inode_data(self.resolve_file(path.as_ref())?)
+    }
+
+    /// Open the regular file at `path` for streaming, resolved like
+    /// [`Jffs2Reader::read_file`]. Only one data node is held in memory at a
+    /// time.
+    pub fn open_file(&self, path: impl AsRef<Path>) -> Result<Jffs2File<'_, 'a>> {
+        self.open_inode(self.resolve_file(path.as_ref())?)
+    }
+
+    /// [`Jffs2Reader::open_file`] of the regular file `ino`
+    fn open_inode(&self, ino: u32) -> Result<Jffs2File<'_, 'a>> {
+        self.check_file_size(ino)?;
+        let (nodes, fragments) = self.fragments(ino);
+        Ok(Jffs2File {
+            reader: self,
+            nodes,
+            fragments: fragments.into_iter(),
+            chunk: vec![],
+            current: None,
+            pos: 0,
+            end: 0,
+            offset: 0,
+            len: self.file_size(ino),
+        })
+    }
+
+    /// Write the content of the regular file at `path`, resolved like
+    /// [`Jffs2Reader::read_file`], to `out`. Holes are written as zero bytes
+    /// and only one data node is held in memory at a time.
+    pub fn write_file_to<W: Write>(&self, path: impl AsRef<Path>, out: &mut W) -> Result<()> {
+        let ino = self.resolve_file(path.as_ref())?;
+        self.check_file_size(ino)?;
+        self.write_contents(ino, out, write_zeros)?;
+        Ok(())
+    }
+
+    /// Inode number of the regular file at `path`
+    fn resolve_file(&self, path: &Path) -> Result<u32> {
+        let (ino, ntype) =
+            self.path_index()
+                .resolve(self, path, true, fs::MAX_SYMLINK_HOPS)?;
+        if ntype == DT_DIR {
+            bail!("{}: is a directory", path.display());
+        } else if ntype != DT_REG {
+            bail!("{}: not a regular file", path.display());
+        }
+
+        Ok(ino)
+    }
+
+    /// Write the regular file `node`, returns its length. Holes between the
+    /// data nodes are left unwritten so they read as zeros, unless
+    /// `fill_holes` is set.
+    fn dump_file(&self, output_path: &Path, node: u32, fill_holes: bool) -> Result<u64> {
+        if !self.inodes.contains_key(&node) {
+            return Ok(0);
+        }
+        self.check_file_size(node)?;
+
+        if let Some(dirname) = output_path.parent() {
+            if !dirname.exists() {
+                std::fs::create_dir_all(dirname)?;
+            }
+        }
+        let output_path = output_path.jffs_fix();
+        let mut file = File::create(&output_path)?;
+        let result = if fill_holes {
+            self.write_contents(node, &mut file, write_zeros)
+        } else {
+            self.write_contents(node, &mut file, |file, len| {
+                file.seek(std::io::SeekFrom::Current(len as i64)).map(drop)
+            })
+        };
+        let len = match result {
+            Err(e) if e.is::<Cancelled>() => {
+                drop(file);
+                std::fs::remove_file(&output_path)?;
+                return Err(e);
+            }
+            result => result?,
+        };
+        file.set_len(len)?;
+
+        Ok(len)
+    }
+
+    /// Write the content of the regular file `node` to `out` from its start,
+    /// returns its length. `hole` moves `out` past a hole of the given length.
+    fn write_contents<W: Write>(
+        &self,
+        node: u32,
+        out: &mut W,
+        mut hole: impl FnMut(&mut W, u64) -> std::io::Result<()>,
+    ) -> Result<u64> {
+        // one buffer for the whole file, only used by the methods that
+        // cannot stream
+        let mut scratch = Vec::new();
+        let mut end = 0;
+        let (nodes, fragments) = self.fragments(node);
+        for frag in fragments {
+            self.check_cancelled()?;
+            let inode = &nodes[frag.node];
+            if frag.offset > end {
+                hole(out, frag.offset - end)?;
+            }
+            let context = || NodeError {
+                offset: inode.node_offset(),
+            };
+            let len = if frag.skip == 0 && frag.len == inode.dsize as u64 {
+                self.write_inode(inode, out, &mut scratch).with_context(context)?
+            } else {
+                // newer nodes overwrote the rest of this one
+                self.decompress_fragment(inode, &mut scratch).with_context(context)?;
+                out.write_all(&scratch[frag.skip as usize..][..frag.len as usize])?;
+                frag.len
+            };
+            end = frag.offset + len;
+        }
+        let len = self.regular_file_len(node).unwrap_or(end);
+        if len > end {
+            hole(out, len - end)?;
+        }
+
+        Ok(len)
+    }
+
+    /// Compression method of the first data node of `node` that cannot be
+    /// decompressed
+    fn unsupported_compression(&self, node: u32) -> Option<Compression> {
+        self.inodes
+            .get(&node)
+            .into_iter()
+            .flatten()
+            .filter_map(Jffs2Inode::compression_method)
+            .find(|compr| !compr.is_supported())
+    }
+
+    /// Apply the permission bits of inode `node` as `opts` say, the sticky
+    /// bit included and setuid and setgid only when asked for
+    #[cfg(unix)]
+    fn set_permissions(&self, output_path: &Path, node: u32, opts: &DumpOptions) -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        if opts.no_permissions {
+            return Ok(());
+        }
+        if let Some(inode) = self.latest_inode(node) {
+            let mask = if opts.setid { 0o7777 } else { 0o1777 };
+            let permissions = std::fs::Permissions::from_mode(inode.mode & mask);
+            std::fs::set_permissions(output_path, permissions)?;
+        }
+        Ok(())
+    }
+
+    /// Make the file read-only when inode `node` has no write bit
+    #[cfg(not(unix))]
+    fn set_permissions(&self, output_path: &Path, node: u32, opts: &DumpOptions) -> Result<()> {
+        if opts.no_permissions {
+            return Ok(());
+        }
+        if let Some(inode) = self.latest_inode(node) {
+            let mut permissions = std::fs::metadata(output_path)?.permissions();
+            permissions.set_readonly(inode.mode & 0o222 == 0);
+            std::fs::set_permissions(output_path, permissions)?;
+        }
+        Ok(())
+    }
+
+    /// Give an extracted file the owner and group of inode `node`, returns
+    /// false when the process lacks the privilege
+    #[cfg(unix)]
+    fn set_owner(&self, output_path: &Path, node: u32) -> Result<bool> {
+        use nix::errno::Errno;
+        use nix::fcntl::AtFlags;
+        use nix::unistd::{fchownat, Gid, Uid};
+
+        let inode = match self.latest_inode(node) {
+            Some(inode) => inode,
+            None => return Ok(true),
+        };
+        let owner = Uid::from_raw(inode.uid as u32);
+        let group = Gid::from_raw(inode.gid as u32);
+        match fchownat(None, output_path, Some(owner), Some(group), AtFlags::AT_SYMLINK_NOFOLLOW) {
+            Ok(()) => Ok(true),
+            Err(Errno::EPERM) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn set_owner(&self, _output_path: &Path, _node: u32) -> Result<bool> {
+        Ok(true)
+    }
+
+    /// Set the extended attribute `name` of an extracted file, on a symbolic
+    /// link itself rather than its target. Returns false when the process
+    /// lacks the privilege or the filesystem doesn't support it.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn set_xattr(&self, output_path: &Path, name: &str, value: &[u8]) -> Result<bool> {
+        use std::ffi::CString;
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = CString::new(output_path.as_os_str().as_bytes())?;
+        let name = CString::new(name)?;
+        let ret = unsafe {
+            libc::lsetxattr(path.as_ptr(), name.as_ptr(), value.as_ptr().cast(), value.len(), 0)
+        };
+        if ret == 0 {
+            return Ok(true);
+        }
+        let e = std::io::Error::last_os_error();
+        match e.raw_os_error() {
+            Some(libc::EPERM | libc::ENOTSUP) => Ok(false),
+            // a value the kernel refuses, such as a malformed capability
+            Some(libc::EINVAL) => {
+                let name = name.to_string_lossy();
+                log::warn!("{}: invalid {} left out", output_path.display(), name);
+                Ok(true)
+            }
+            _ => Err(e.into()),
+        }
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    fn set_xattr(&self, _output_path: &Path, _name: &str, _value: &[u8]) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// Give an extracted file the access and modification times of inode
+    /// `node`, with `nofollow` a symbolic link itself rather than its target
+    fn set_times(&self, output_path: &Path, node: u32, nofollow: bool) -> Result<()> {
+        if let Some(inode) = self.latest_inode(node) {
+            let atime = filetime::FileTime::from_unix_time(inode.atime as i64, 0);
+            let mtime = filetime::FileTime::from_unix_time(inode.mtime as i64, 0);
+            if nofollow {
+                filetime::set_symlink_file_times(output_path, atime, mtime)?;
+            } else {
+                filetime::set_file_times(output_path, atime, mtime)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// [`Jffs2Reader::set_owner`] while `enabled`, which is cleared with a
+    /// single warning the first time the privilege is missing
+    fn restore_owner(&self, output_path: &Path, node: u32, enabled: &AtomicBool) -> Result<()> {
+        if enabled.load(Ordering::Relaxed)
+            && !self.set_owner(output_path, node)?
+            && enabled.swap(false, Ordering::Relaxed)
+        {
+            log::warn!("no permission to change file owners, keeping the current user");
+        }
+        Ok(())
+    }
+
+    /// Set the extended attributes of inode `node` on an extracted file while
+    /// `enabled`, which is cleared with a single warning the first time one
+    /// cannot be set. Comes after the owner, chown drops file capabilities.
+    fn restore_xattrs(&self, output_path: &Path, node: u32, enabled: &AtomicBool) -> Result<()> {
+        if !enabled.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        for (name, value) in self.inode_xattrs(node) {
+            if !self.set_xattr(output_path, &name, &value)? {
+                if enabled.swap(false, Ordering::Relaxed) {
+                    log::warn!("cannot set extended attributes here, leaving them out");
+                }
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn dump_symlink(
+        &self,
+        output_path: &Path,
+        node: u32,
+        fallback: SymlinkFallback,
+    ) -> Result<Option<String>> {
+        let target = self.link_target(node)?;
+        if let Some(dirname) = output_path.parent() {
+            if !dirname.exists() {
+                std::fs::create_dir_all(dirname)?;
+            }
+        }
+
+        // replace a link or file left by an earlier extraction
+        let output_path = output_path.jffs_fix();
+        if output_path.symlink_metadata().is_ok() {
+            std::fs::remove_file(&output_path)?;
+        }
+
+        #[cfg(unix)]
+        let created = std::os::unix::fs::symlink(&target, &output_path);
+        // no unprivileged symlinks elsewhere
+        #[cfg(not(unix))]
+        let created: std::io::Result<()> = Err(std::io::ErrorKind::Unsupported.into());
+        match created {
+            Ok(()) => Ok(None),
+            Err(e) if matches!(
+                e.kind(),
+                std::io::ErrorKind::PermissionDenied | std::io::ErrorKind::Unsupported
+            ) =>
+            {
+                self.symlink_fallback(&output_path, node, &target, fallback, e)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Stand in for the symbolic link `node` to `target` that `error` kept
+    /// from being created, returns the reason when it is skipped
+    fn symlink_fallback(
+        &self,
+        output_path: &Path,
+        node: u32,
+        target: &Path,
+        fallback: SymlinkFallback,
+        error: std::io::Error,
+    ) -> Result<Option<String>> {
+        match fallback {
+            SymlinkFallback::Placeholder => {
+                std::fs::write(output_path, target.to_string_lossy().as_bytes())?;
+                Ok(None)
+            }
+            SymlinkFallback::Skip => {
+                log::warn!("skipping symbolic link {}: {}", output_path.display(), error);
+                Ok(Some(error.to_string()))
+            }
+            SymlinkFallback::CopyTarget => {
+                let link = match self.dirent_of(node) {
+                    Some(dirent) => self.resolve_dirent(dirent)?,
+                    None => bail!("no dirent for node {}", node),
+                };
+                let resolved = self.path_index().resolve(self, &link, true, fs::MAX_SYMLINK_HOPS);
+                match resolved {
+                    Ok((ino, DT_REG)) => {
+                        self.dump_file(output_path, ino, false)?;
+                        Ok(None)
+                    }
+                    Ok(_) => Ok(Some(format!("{} is not a regular file", target.display()))),
+                    Err(e) if is_not_found(&e) => {
+                        Ok(Some(format!("{} is not in the image", target.display())))
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+        }
+    }
+
+    /// Recreate a device node, FIFO or socket, those that cannot be created
+    /// are skipped with a warning and the reason is returned
+    #[cfg(unix)]
+    fn dump_special(
+        &self,
+        output_path: &Path,
+        node: u32,
+        kind: FileKind,
+    ) -> Result<Option<String>> {
+        use nix::errno::Errno;
+        use nix::sys::stat::{mknod, Mode, SFlag};
+
+        if let Some(dirname) = output_path.parent() {
+            if !dirname.exists() {
+                std::fs::create_dir_all(dirname)?;
+            }
+        }
+
+        let output_path = output_path.jffs_fix();
+        if output_path.symlink_metadata().is_ok() {
+            std::fs::remove_file(&output_path)?;
+        }
+
+        let mode = self.latest_inode(node).map_or(0o644, |inode| inode.mode);
+        let perm = Mode::from_bits_truncate((mode & 0o7777) as nix::libc::mode_t);
+        let (sflag, dev) = match kind {
+            FileKind::CharDev | FileKind::BlockDev => {
+                let (major, minor) = self.device_number(node)?;
+                let sflag = match kind {
+                    FileKind::CharDev => SFlag::S_IFCHR,
+                    _ => SFlag::S_IFBLK,
+                };
+                (sflag, nix::libc::makedev(major as _, minor as _))
+            }
+            FileKind::Fifo => (SFlag::S_IFIFO, 0),
+            FileKind::Socket => {
+                // binding is the only way to get a socket file, the listener
+                // is closed right away and the file stays
+                if let Err(e) = std::os::unix::net::UnixListener::bind(&output_path) {
+                    log::warn!("skipping socket {}: {}", output_path.display(), e);
+                    return Ok(Some(e.to_string()));
+                }
+                return Ok(None);
+            }
+            _ => bail!("node {} is not a special file", node),
+        };
+
+        match mknod(&output_path, sflag, perm, dev) {
+            Err(Errno::EPERM) => {
+                log::warn!(
+                    "skipping {}: no permission to create device nodes",
+                    output_path.display()
+                );
+                Ok(Some("no permission to create device nodes".to_string()))
+            }
+            result => result.map(|()| None).map_err(Into::into),
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn dump_special(
+        &self,
+        output_path: &Path,
+        _node: u32,
+        kind: FileKind,
+    ) -> Result<Option<String>> {
+        log::warn!("skipping {}: cannot create {:?} here", output_path.display(), kind);
+        Ok(Some(format!("cannot create {:?} here", kind)))
+    }
+
+    /// Path of `dirent` from the root, through the dirents of its parent
+    /// directories
+    fn resolve_dirent(&self, dirent: &Jffs2Dirent) -> Result<PathBuf> {
+        let mut path = PathBuf::new();
+        let mut cnode = dirent;
+        let mut parents = std::collections::HashSet::new();
+
+        for _depth in 0..self.max_depth {
+            let name_path = Path::new(&cnode.fname);
+            if cnode.pino == self.root_ino {
+                let output_path = name_path.join(path);
+                return Ok(output_path.lexiclean().jffs_fix());
+            }
+            if !parents.insert(cnode.pino) {
+                let pino = cnode.pino;
+                bail!("directory {} is in a cycle, cannot resolve dirent {}", pino, dirent.ino);
+            }
+            path = name_path.join(path);
+            cnode = match self.dirent_of(cnode.pino) {
+                Some(parent) => parent,
+                _ => bail!("cannot find parent node {}", cnode.pino),
+            };
+        }
+
+        bail!("dirent {} is more than {} directories deep", dirent.ino, self.max_depth);
+    }
+
+    /// The dirent named `name` in directory `pino`
+    pub(crate) fn dirent(&self, pino: u32, name: &OsStr) -> Option<&Jffs2Dirent> {
+        self.dirents.get(&pino).and_then(|names| names.get(name))
+    }
+
+    /// A dirent of inode number `ino`, the only one for a directory and the
+    /// latest for a file with hard links
+    pub(crate) fn dirent_of(&self, ino: u32) -> Option<&Jffs2Dirent> {
+        let (pino, name) = self.path_index().link(ino)?;
+        self.dirent(*pino, name)
+    }
+
+    /// The dirents of directory `ino`, in no particular order
+    pub(crate) fn children(&self, ino: u32) -> impl Iterator<Item = &Jffs2Dirent> {
+        self.dirents.get(&ino).into_iter().flat_map(|names| names.values())
+    }
+
+    /// Every dirent of the image, in no particular order
+    pub(crate) fn all_dirents(&self) -> impl Iterator<Item = &Jffs2Dirent> {
+        self.dirents.values().flat_map(|names| names.values())
+    }
+
+    /// The dirents to list and extract, those outside of a custom root
+    /// directory are left out
+    fn rooted_dirents(&self) -> Vec<&Jffs2Dirent> {
+        let mut dirents: Vec<_> = self
+            .all_dirents()
+            .filter(|dirent| self.root_ino == ROOT_INO || self.is_under(dirent, self.root_ino))
+            .collect();
+        // not the order of the hash maps, which changes from run to run
+        dirents.sort_by(|a, b| (a.pino, &a.fname).cmp(&(b.pino, &b.fname)));
+        dirents
+    }
+
+    /// Returns true if `dirent` is somewhere below the directory `dir`
+    fn is_under<'r>(&'r self, mut dirent: &'r Jffs2Dirent, dir: u32) -> bool {
+        for _depth in 0..self.max_depth {
+            if dirent.pino == dir {
+                return true;
+            }
+            match self.dirent_of(dirent.pino) {
+                Some(parent) => dirent = parent,
+                None => return false,
+            }
+        }
+        false
+    }
+
+    /// Extract the files of the image under `target_path`. Device nodes need
+    /// the privilege to create them and are skipped with a warning without it.
+    pub fn dump(&self, target_path: impl AsRef<Path>) -> Result<()> {
+        self.dump_with_options(target_path, &DumpOptions::new())
+    }
+
+    /// [`Jffs2Reader::dump`] with the regular files written on one thread per
+    /// available core, see [`DumpOptions::threads`] to pick the number
+    #[cfg(feature = "parallel")]
+    pub fn dump_parallel(&self, target_path: impl AsRef<Path>) -> Result<()> {
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        self.dump_with_options(target_path, &DumpOptions::new().threads(threads))
+    }
+
+    /// [`Jffs2Reader::dump`] with the extraction options in `opts`
+    pub fn dump_with_options(
+        &self,
+        target_path: impl AsRef<Path>,
+        opts: &DumpOptions,
+    ) -> Result<()> {
+        self.dump_with_report(target_path, opts).map(drop)
+    }
+
+    /// [`Jffs2Reader::dump_with_options`], and tell what was written, skipped
+    /// and, in best-effort mode, what failed
+    pub fn dump_with_report(
+        &self,
+        target_path: impl AsRef<Path>,
+        opts: &DumpOptions,
+    ) -> Result<ExtractionReport> {
+        self.dump_all(target_path.as_ref(), opts, None)
+    }
+
+    /// [`Jffs2Reader::dump`], and return the paths written, sorted
+    pub fn dump_collecting(&self, target_path: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+        let report = self.dump_with_report(target_path, &DumpOptions::new())?;
+        Ok(report.written)
+    }
+
+    /// [`Jffs2Reader::dump`], calling `progress` after each entry with its
+    /// path, the number of entries written so far and their total
+    pub fn dump_with_progress(
+        &self,
+        target_path: impl AsRef<Path>,
+        progress: impl FnMut(&Path, u64, u64) + Send,
+    ) -> Result<()> {
+        let progress = Mutex::new(progress);
+        self.dump_all(target_path.as_ref(), &DumpOptions::new(), Some(&progress))
+            .map(drop)
+    }
+
+    /// Extract every entry under the root
+    fn dump_all(
+        &self,
+        target_path: &Path,
+        opts: &DumpOptions,
+        on_entry: Option<&EntryFn<'_>>,
+    ) -> Result<ExtractionReport> {
+        let mut nodes = vec![];
+        let mut unresolved = vec![];
+        for dirent in self.rooted_dirents() {
+            match self.resolve_dirent(dirent) {
+                Ok(output_path) => nodes.push((output_path, dirent.ino, dirent.ntype)),
+                Err(e) if opts.best_effort => {
+                    let output_path = target_path.join(&dirent.fname);
+                    let e = e.context(NodeError {
+                        offset: dirent.offset,
+                    });
+                    unresolved.push((output_path, e));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        let mut report = self.dump_nodes(target_path, nodes, opts, on_entry)?;
+        report.failures.extend(unresolved);
+        Ok(report)
+    }
+
+    /// Extract only the directory or file at `prefix` in the image, resolved
+    /// like [`Jffs2Reader::read_file`], see [`SubtreeOptions`]
+    pub fn dump_subtree(
+        &self,
+        target_path: impl AsRef<Path>,
+        prefix: impl AsRef<Path>,
+        opts: &SubtreeOptions,
+    ) -> Result<()> {
+        let target_path = target_path.as_ref();
+        let resolved = self
+            .path_index()
+            .resolve(self, prefix.as_ref(), true, fs::MAX_SYMLINK_HOPS);
+        let (top, ntype) = match resolved {
+            Err(e) if opts.allow_missing && is_not_found(&e) => return Ok(()),
+            resolved => resolved?,
+        };
+        if top == self.root_ino {
+            return self.dump(target_path);
+        }
+
+        // the path as given, another name of a file with hard links may be
+        // somewhere else entirely
+        let top_path = Path::new("/").join(prefix.as_ref()).lexiclean();
+        let top_path = top_path.strip_prefix("/")?;
+        let base = match (opts.strip_prefix, ntype) {
+            (false, _) => Path::new(""),
+            (true, DT_DIR) => top_path,
+            (true, _) => top_path.parent().unwrap_or(Path::new("")),
+        };
+
+        std::fs::create_dir_all(target_path)?;
+        let top_path = top_path.strip_prefix(base)?.to_path_buf();
+        let mut nodes = vec![];
+        // the target directory itself stands for a stripped prefix
+        if !(opts.strip_prefix && ntype == DT_DIR) {
+            nodes.push((top_path.clone(), top, ntype));
+        }
+        // walked down from the prefix, not picked by inode number
+        let mut dirs = if ntype == DT_DIR { vec![(top, top_path, 0)] } else { vec![] };
+        while let Some((dir, dir_path, depth)) = dirs.pop() {
+            if depth == self.max_depth {
+                bail!("{} is more than {} directories deep", prefix.as_ref().display(), depth);
+            }
+            for dirent in self.children(dir) {
+                let output_path = dir_path.join(&dirent.fname).lexiclean().jffs_fix();
+                if dirent.ntype == DT_DIR {
+                    dirs.push((dirent.ino, output_path.clone(), depth + 1));
+                }
+                nodes.push((output_path, dirent.ino, dirent.ntype));
+            }
+        }
+
+        self.dump_nodes(target_path, nodes, &DumpOptions::new(), None)?;
+        Ok(())
+    }
+
+    /// Write the single entry at `path` in the image to `output_path`: a
+    /// regular file with its data, a directory without its content, a
+    /// symbolic link as a link. The last component of `path` is not
+    /// followed. A path missing from the image fails with an
+    /// [`std::io::Error`] of kind `NotFound`.
+    pub fn dump_entry(&self, path: impl AsRef<Path>, output_path: impl AsRef<Path>) -> Result<()> {
+        let output_path = output_path.as_ref();
+        let (ino, ntype) =
+            self.path_index()
+                .resolve(self, path.as_ref(), false, fs::MAX_SYMLINK_HOPS)?;
+        if ino == self.root_ino {
+            std::fs::create_dir_all(output_path)?;
+            return Ok(());
+        }
+
+        let name = match output_path.file_name() {
+            Some(name) => PathBuf::from(name),
+            None => bail!("{}: not a file name", output_path.display()),
+        };
+        let target_path = output_path.parent().unwrap_or(Path::new(""));
+        self.dump_nodes(target_path, vec![(name, ino, ntype)], &DumpOptions::new(), None)?;
+        Ok(())
+    }
+
+    /// Extract only the entries matching one of the glob `patterns`, the
+    /// directories holding them are created as needed. A pattern with a `/`
+    /// is matched against the whole path within the image, `**` spanning
+    /// directories, and one without against the file name alone. Returns how
+    /// many entries each pattern matched.
+    pub fn dump_matching(
+        &self,
+        target_path: impl AsRef<Path>,
+        patterns: &[&str],
+    ) -> Result<Vec<usize>> {
+        let patterns = patterns
+            .iter()
+            .map(|pattern| glob::Pattern::new(pattern.trim_start_matches('/')))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let mut counts = vec![0; patterns.len()];
+        let nodes = self.matching_nodes(&patterns, &mut counts)?;
+
+        self.dump_nodes(target_path.as_ref(), nodes, &DumpOptions::new(), None)?;
+        Ok(counts)
+    }
+
+    /// Extract only the entries matching the glob `pattern`, matched like in
+    /// [`Jffs2Reader::dump_matching`], the directories holding them are
+    /// created as needed. Returns the paths written, sorted.
+    pub fn extract_matching(
+        &self,
+        target: impl AsRef<Path>,
+        pattern: &str,
+    ) -> Result<Vec<PathBuf>> {
+        let pattern = glob::Pattern::new(pattern.trim_start_matches('/'))?;
+        let nodes = self.matching_nodes(&[pattern], &mut [0])?;
+        let report = self.dump_nodes(target.as_ref(), nodes, &DumpOptions::new(), None)?;
+        Ok(report.written)
+    }
+
+    /// The entries matching one of `patterns`, counting the matches of each
+    /// in `counts`
+    fn matching_nodes(
+        &self,
+        patterns: &[glob::Pattern],
+        counts: &mut [usize],
+    ) -> Result<Vec<(PathBuf, u32, u8)>> {
+        let options = glob::MatchOptions {
+            require_literal_separator: true,
+            ..glob::MatchOptions::new()
+        };
+
+        let mut nodes = vec![];
+        for dirent in self.rooted_dirents() {
+            let output_path = self.resolve_dirent(dirent)?;
+            let output_path = output_path.as_path().jffs_fix();
+            let name = Path::new(output_path.file_name().unwrap_or_default());
+            let mut matched = false;
+            for (pattern, count) in patterns.iter().zip(counts.iter_mut()) {
+                let subject = if pattern.as_str().contains('/') {
+                    output_path.as_path()
+                } else {
+                    name
+                };
+                if pattern.matches_path_with(subject, options) {
+                    *count += 1;
+                    matched = true;
+                }
+            }
+            if matched {
+                nodes.push((output_path, dirent.ino, dirent.ntype));
+            }
+        }
+
+        Ok(nodes)
+    }
+
+    /// Extract the entries accepted by `f`, which sees each directory before
+    /// its content. Rejecting a directory skips everything below it without
+    /// resolving it.
+    pub fn dump_filtered(
+        &self,
+        target_path: impl AsRef<Path>,
+        mut f: impl FnMut(&Jffs2Entry) -> bool,
+    ) -> Result<()> {
+        let mut visited = std::collections::HashSet::new();
+        let mut pending: Vec<&Jffs2Dirent> = self.children(self.root_ino).collect();
+        let mut nodes = vec![];
+        while let Some(dirent) = pending.pop() {
+            if !visited.insert((dirent.pino, &dirent.fname)) {
+                continue;
+            }
+            let entry = match self.entry(dirent)? {
+                Some(entry) if f(&entry) => entry,
+                _ => continue,
+            };
+
+            if dirent.ntype == DT_DIR {
+                pending.extend(self.children(dirent.ino));
+            }
+            nodes.push((entry.path, dirent.ino, dirent.ntype));
+        }
+
+        self.dump_nodes(target_path.as_ref(), nodes, &DumpOptions::new(), None)?;
+        Ok(())
+    }
+
+    /// Extract `nodes`, given as output path, inode number and dirent type
+    fn dump_nodes(
+        &self,
+        target_path: &Path,
+        mut nodes: Vec<(PathBuf, u32, u8)>,
+        opts: &DumpOptions,
+        on_entry: Option<&EntryFn<'_>>,
+    ) -> Result<ExtractionReport> {
+        // by path, so the first of the names of a file is the same on every
+        // run, and directories come before what they hold
+        nodes.sort_by(|a, b| a.0.cmp(&b.0));
+        // checked before anything is written, hard links count once
+        let mut regular = std::collections::HashSet::new();
+        let total_size: u64 = nodes
+            .iter()
+            .filter(|(_, i, ntype)| *ntype == DT_REG && regular.insert(*i))
+            .map(|(_, i, _)| self.file_size(*i))
+            .sum();
+        if total_size > self.max_total_size {
+            bail!(
+                "the files to extract add up to {} bytes, over the limit of {}",
+                total_size,
+                self.max_total_size
+            );
+        }
+
+        if opts.empty_target {
+            let found = match std::fs::read_dir(target_path) {
+                Ok(mut entries) => entries.next().is_some(),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => false,
+                Err(e) => return Err(e.into()),
+            };
+            if found {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::AlreadyExists,
+                    format!("{} is not empty", target_path.display()),
+                )
+                .into());
+            }
+        }
+        // a link already in the target must not take an entry elsewhere
+        let root = resolve_existing(target_path)?;
+        let mut report = ExtractionReport::default();
+        let sanitize = opts.sanitize_names.unwrap_or(cfg!(windows));
+        if sanitize || opts.case_collisions == CaseCollisionPolicy::Rename {
+            let paths = nodes.iter().map(|(path, _, _)| path.as_path());
+            let renamed = names::rename_paths(paths, sanitize);
+            for (path, _, _) in &mut nodes {
+                let output = &renamed[&path.components().collect::<PathBuf>()];
+                if !path.components().eq(output.components()) {
+                    report.renamed.push((target_path.join(&*path), target_path.join(output)));
+                    *path = output.clone();
+                }
+            }
+            report.renamed.sort();
+        } else if opts.case_collisions != CaseCollisionPolicy::Write {
+            let paths = nodes.iter().map(|(path, _, _)| path.as_path());
+            let collisions = names::case_collisions(paths);
+            if let Some((kept, other)) = collisions.first() {
+                if opts.case_collisions == CaseCollisionPolicy::Error {
+                    bail!("{} and {} differ only in case", kept.display(), other.display());
+                }
+            }
+            nodes.retain(|(path, _, _)| {
+                let collision = collisions.iter().find(|(_, other)| path.starts_with(other));
+                if let Some((kept, other)) = collision {
+                    let (kept, other) = (kept.display(), other.display());
+                    let reason = format!("{} differs only in case from {}", other, kept);
+                    log::warn!("{}, left out", reason);
+                    report.skipped.push((target_path.join(path), reason));
+                }
+                collision.is_none()
+            });
+        }
+        #[cfg(windows)]
+        let long_target = long_target(target_path, &root, &mut nodes);
+        #[cfg(windows)]
+        let target_path = long_target.as_path();
+        let owners = AtomicBool::new(opts.preserve_owner);
+        let xattrs = AtomicBool::new(opts.xattrs);
+        let mut files = vec![];
+        let mut hard_links = vec![];
+        let mut written_at: HashMap<u32, PathBuf> = HashMap::new();
+        let mut symlinks = vec![];
+        let mut dirs = vec![];
+        let total = nodes.len();
+        let done = AtomicUsize::new(0);
+        let progress = |path: &Path| {
+            let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+            self.progress.report(Progress::Dump(DumpProgress { done, total, path }));
+            if let Some(on_entry) = on_entry {
+                let mut on_entry = on_entry.lock().unwrap_or_else(|e| e.into_inner());
+                on_entry(path, done as u64, total as u64);
+            }
+        };
+        for (output_path, i, ntype) in nodes {
+            self.check_cancelled()?;
+            let output_path = target_path.join(output_path).as_path().jffs_fix();
+            let special = FileKind::from_dirent_type(ntype).filter(|kind| {
+                matches!(
+                    kind,
+                    FileKind::CharDev | FileKind::BlockDev | FileKind::Fifo | FileKind::Socket
+                )
+            });
+            if let (Some(kind), SpecialFilePolicy::Record | SpecialFilePolicy::Skip) =
+                (special, opts.special_files)
+            {
+                if opts.special_files == SpecialFilePolicy::Record {
+                    let device = match kind {
+                        FileKind::CharDev | FileKind::BlockDev => self.device_number(i).map(Some),
+                        _ => Ok(None),
+                    };
+                    match device {
+                        Ok(device) => report.recorded.push((output_path.clone(), kind, device)),
+                        Err(e) => {
+                            report.settle(&output_path, Err(e), opts.best_effort)?;
+                        }
+                    }
+                }
+                progress(&output_path);
+                continue;
+            }
+            // a link is replaced rather than followed, it is checked with
+            // the other links
+            if ntype != DT_LNK {
+                if let Err(e) = check_inside(&root, &output_path) {
+                    report.settle(&output_path, Err(e), opts.best_effort)?;
+                    progress(&output_path);
+                    continue;
+                }
+            }
+            if ntype != DT_DIR && output_path.symlink_metadata().is_ok() {
+                match opts.overwrite {
+                    OverwritePolicy::Overwrite => {}
+                    OverwritePolicy::Skip => {
+                        progress(&output_path);
+                        report.skipped.push((output_path, "already exists".to_string()));
+                        continue;
+                    }
+                    OverwritePolicy::Error => {
+                        let e = std::io::Error::new(
+                            std::io::ErrorKind::AlreadyExists,
+                            format!("{} already exists", output_path.display()),
+                        );
+                        report.settle(&output_path, Err(e.into()), opts.best_effort)?;
+                        progress(&output_path);
+                        continue;
+                    }
+                }
+            }
+            let result = if ntype == DT_DIR {
+                std::fs::create_dir_all(&output_path).map_err(Into::into)
+            } else if ntype == DT_REG {
+                // written once all the directories are there, the other names
+                // of a file once its data is
+                match written_at.entry(i) {
+                    Entry::Occupied(original) => {
+                        hard_links.push((output_path, original.get().clone(), i));
+                    }
+                    Entry::Vacant(slot) => {
+                        slot.insert(output_path.clone());
+                        files.push((output_path, i));
+                    }
+                }
+                continue;
+            } else if ntype == DT_LNK {
+                symlinks.push((output_path, i));
+                continue;
+            } else if let Some(
+                kind @ (FileKind::CharDev | FileKind::BlockDev | FileKind::Fifo | FileKind::Socket),
+            ) = FileKind::from_dirent_type(ntype)
+            {
+                match self.dump_special(&output_path, i, kind) {
+                    Ok(Some(reason)) => {
+                        progress(&output_path);
+                        report.skipped.push((output_path, reason));
+                        continue;
+                    }
+                    Ok(None) => self.restore_owner(&output_path, i, &owners).and_then(|()| {
+                        self.restore_xattrs(&output_path, i, &xattrs)?;
+                        // not through an open, which fails on sockets and
+                        // blocks on FIFOs
+                        if !opts.no_times {
+                            self.set_times(&output_path, i, true)?;
+                        }
+                        Ok(())
+                    }),
+                    Err(e) => Err(e),
+                }
+            } else {
+                progress(&output_path);
+                report.skipped.push((output_path, format!("unknown file type {}", ntype)));
+                continue;
+            };
+
+            let written = report.settle(&output_path, result, opts.best_effort)?;
+            progress(&output_path);
+            if written {
+                report.written.push(output_path.clone());
+                if ntype == DT_DIR {
+                    report.dirs += 1;
+                    dirs.push((output_path, i));
+                } else {
+                    report.special_files += 1;
+                }
+            }
+        }
+
+        #[cfg(feature = "parallel")]
+        let files = if opts.threads > 1 {
+            self.dump_files_parallel(files, opts, &owners, &xattrs, &progress, &mut report)?;
+            vec![]
+        } else {
+            files
+        };
+        for (output_path, i) in files {
+            self.check_cancelled()?;
+            let result = self.dump_regular(&output_path, i, opts, &owners, &xattrs);
+            progress(&output_path);
+            self.record_regular(&mut report, output_path, i, result, opts)?;
+        }
+
+        for (output_path, original, i) in hard_links {
+            self.check_cancelled()?;
+            if original.symlink_metadata().is_err() {
+                // the data was never written, the link goes the same way
+                let result = self.dump_regular(&output_path, i, opts, &owners, &xattrs);
+                progress(&output_path);
+                self.record_regular(&mut report, output_path, i, result, opts)?;
+                continue;
+            }
+            let result = self.dump_hard_link(&original, &output_path, i, opts, &owners, &xattrs);
+            if report.settle(&output_path, result, opts.best_effort)? {
+                report.hard_links += 1;
+                report.written.push(output_path.clone());
+            }
+            progress(&output_path);
+        }
+
+        // links go last so no file is written through one of them
+        for (output_path, i) in symlinks {
+            self.check_cancelled()?;
+            // through the directory, which may be one of the links just made
+            let dir = output_path.parent().unwrap_or(target_path);
+            let dumped = check_inside(&root, dir)
+                .and_then(|()| self.dump_symlink(&output_path, i, opts.symlink_fallback));
+            let result = match dumped {
+                Ok(Some(reason)) => {
+                    progress(&output_path);
+                    report.skipped.push((output_path, reason));
+                    continue;
+                }
+                Ok(None) => self.restore_owner(&output_path, i, &owners).and_then(|()| {
+                    self.restore_xattrs(&output_path, i, &xattrs)?;
+                    if !opts.no_times {
+                        self.set_times(&output_path, i, true)?;
+                    }
+                    Ok(())
+                }),
+                Err(e) => Err(e),
+            };
+            if report.settle(&output_path, result, opts.best_effort)? {
+                report.symlinks += 1;
+                report.written.push(output_path.clone());
+            }
+            progress(&output_path);
+        }
+
+        // deepest first, a read-only directory must not block its children,
+        // and once all of them are written so their creation doesn't touch
+        // the directory times
+        dirs.sort_by_key(|(output_path, _)| std::cmp::Reverse(output_path.components().count()));
+        for (output_path, i) in dirs {
+            let result = self.restore_owner(&output_path, i, &owners).and_then(|()| {
+                self.set_permissions(&output_path, i, opts)?;
+                self.restore_xattrs(&output_path, i, &xattrs)?;
+                if !opts.no_times {
+                    self.set_times(&output_path, i, false)?;
+                }
+                Ok(())
+            });
+            report.settle(&output_path, result, opts.best_effort)?;
+        }
+        report.written.sort();
+
+        Ok(report)
+    }
+
+    /// Write the regular file `node` with its metadata, returns the number
+    /// of bytes written, or none when best-effort mode leaves it out for its
+    /// compression method
+    fn dump_regular(
+        &self,
+        output_path: &Path,
+        node: u32,
+        opts: &DumpOptions,
+        owners: &AtomicBool,
+        xattrs: &AtomicBool,
+    ) -> Result<Option<u64>> {
+        if opts.best_effort && self.unsupported_compression(node).is_some() {
+            return Ok(None);
+        }
+
+        let written = self.dump_file(output_path, node, opts.fill_holes)?;
+        self.restore_metadata(output_path, node, opts, owners, xattrs)?;
+        Ok(Some(written))
+    }
+
+    /// Owner, mode, extended attributes and times of the regular file `node`
+    fn restore_metadata(
+        &self,
+        output_path: &Path,
+        node: u32,
+        opts: &DumpOptions,
+        owners: &AtomicBool,
+        xattrs: &AtomicBool,
+    ) -> Result<()> {
+        // chown clears setuid and setgid, so the mode comes after it
+        self.restore_owner(output_path, node, owners)?;
+        self.set_permissions(output_path, node, opts)?;
+        self.restore_xattrs(output_path, node, xattrs)?;
+        if !opts.no_times {
+            self.set_times(output_path, node, false)?;
+        }
+        Ok(())
+    }
+
+    /// Make `output_path` another name of the regular file `node` already
+    /// written at `original`, a copy of it where hard links are not possible
+    fn dump_hard_link(
+        &self,
+        original: &Path,
+        output_path: &Path,
+        node: u32,
+        opts: &DumpOptions,
+        owners: &AtomicBool,
+        xattrs: &AtomicBool,
+    ) -> Result<()> {
+        if output_path.symlink_metadata().is_ok() {
+            std::fs::remove_file(output_path)?;
+        }
+        if let Err(e) = std::fs::hard_link(original, output_path) {
+            log::debug!("cannot link {}, copying it: {}", output_path.display(), e);
+            std::fs::copy(original, output_path)?;
+            self.restore_metadata(output_path, node, opts, owners, xattrs)?;
+        }
+        Ok(())
+    }
+
+    /// Add the outcome of [`Jffs2Reader::dump_regular`] to `report`
+    fn record_regular(
+        &self,
+        report: &mut ExtractionReport,
+        output_path: PathBuf,
+        node: u32,
+        result: Result<Option<u64>>,
+        opts: &DumpOptions,
+    ) -> Result<()> {
+        match result {
+            Ok(Some(written)) => {
+                report.files += 1;
+                report.bytes += written;
+                report.written.push(output_path);
+            }
+            Ok(None) => {
+                if let Some(compr) = self.unsupported_compression(node) {
+                    report.unsupported.push((output_path, compr));
+                }
+            }
+            Err(e) => {
+                report.settle(&output_path, Err(e), opts.best_effort)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the regular files on `opts.threads` threads, the outcomes are
+    /// recorded in the order of `files` once all of them are done
+    #[cfg(feature = "parallel")]
+    fn dump_files_parallel(
+        &self,
+        files: Vec<(PathBuf, u32)>,
+        opts: &DumpOptions,
+        owners: &AtomicBool,
+        xattrs: &AtomicBool,
+        progress: &(dyn Fn(&Path) + Sync),
+        report: &mut ExtractionReport,
+    ) -> Result<()> {
+        use rayon::prelude::*;
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(opts.threads)
+            .build()?;
+        let outcomes: Vec<_> = pool.install(|| {
+            files
+                .into_par_iter()
+                .map(|(output_path, i)| {
+                    let result = self
+                        .check_cancelled()
+                        .and_then(|()| self.dump_regular(&output_path, i, opts, owners, xattrs));
+                    progress(&output_path);
+                    (output_path, i, result)
+                })
+                .collect()
+        });
+
+        for (output_path, i, result) in outcomes {
+            self.record_regular(report, output_path, i, result, opts)?;
+        }
+        Ok(())
+    }
+
+    /// Extended attributes of `entry` as names and values, sorted by name.
+    /// ACLs are given in the layout of the system.posix_acl_* attributes.
+    pub fn xattrs(&self, entry: &Jffs2Entry) -> Vec<(String, Vec<u8>)> {
+        self.inode_xattrs(entry.ino)
+    }
+
+    /// Extended attributes of inode `node`, see [`Jffs2Reader::xattrs`]
+    fn inode_xattrs(&self, node: u32) -> Vec<(String, Vec<u8>)> {
+        let mut xattrs: Vec<_> = self
+            .xrefs
+            .get(&node)
+            .into_iter()
+            .flatten()
+            .filter(|(_, seqno)| *seqno & XREF_DELETE_MARKER == 0)
+            .filter_map(|(xid, _)| self.xattrs.get(xid))
+            .filter(|xattr| xattr.version != XATTR_DELETE_MARKER)
+            .map(|xattr| (xattr.name.clone(), xattr.value.clone()))
+            .collect();
+        xattrs.sort();
+        xattrs
+    }
+
+    /// List the files of the image, sorted by path so directories come
+    /// before what they hold
+    pub fn entries(&self) -> Result<Vec<Jffs2Entry>> {
+        let mut entries = self.walk().collect::<Result<Vec<_>>>()?;
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(entries)
+    }
+
+    /// List the files of the image like [`Jffs2Reader::entries`], but leave
+    /// out the dirents that cannot be resolved instead of failing, and return
+    /// them on the side
+    pub fn entries_lossy(&self) -> (Vec<Jffs2Entry>, Vec<ResolveError>) {
+        let mut entries = vec![];
+        let mut errors = vec![];
+        for dirent in self.rooted_dirents() {
+            match self.entry(dirent) {
+                Ok(Some(entry)) => entries.push(entry),
+                Ok(None) => {}
+                Err(error) => errors.push(ResolveError {
+                    pino: dirent.pino,
+                    ino: dirent.ino,
+                    name: dirent.fname.clone(),
+                    error,
+                }),
+            }
+        }
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        (entries, errors)
+    }
+
+    /// Iterate over the files of the image, each dirent is resolved when its
+    /// entry is reached. A dirent that cannot be resolved is yielded as an
+    /// error and the iteration goes on. The order is the same on every run,
+    /// by parent directory and name, but not that of the paths.
+    pub fn walk(&self) -> Walk<'_, 'a> {
+        Walk {
+            reader: self,
+            dirents: self.rooted_dirents().into_iter(),
+        }
+    }
+
+    /// Call `f` with every entry of the image and, for regular files, its
+    /// contents, decompressed one file at a time. Stops at the first error,
+    /// from `f` or from resolving an entry.
+    pub fn for_each_entry<F>(&self, mut f: F) -> Result<()>
+    where
+        F: FnMut(&Jffs2Entry, Option<&[u8]>) -> Result<()>,
+    {
+        for entry in self.walk() {
+            let entry = entry?;
+            if entry.is_file() {
+                let data = self.read_inode_data(entry.ino)?;
+                f(&entry, Some(&data))?;
+            } else {
+                f(&entry, None)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Pairs of entries whose paths differ only in case, which overwrite each
+    /// other when extracted on a filesystem ignoring case. The first of each
+    /// pair comes first in byte order.
+    pub fn case_collisions(&self) -> Result<Vec<(PathBuf, PathBuf)>> {
+        let paths: Vec<PathBuf> = self
+            .entries()?
+            .iter()
+            .map(|entry| entry.path().components().collect())
+            .collect();
+        Ok(names::case_collisions(paths.iter().map(PathBuf::as_path)))
+    }
+
+    /// Entry of `dirent`, none for unknown types
+    fn entry(&self, dirent: &Jffs2Dirent) -> Result<Option<Jffs2Entry>> {
+        let output_path = self.resolve_dirent(dirent)?;
+        let (node, ntype) = (dirent.ino, dirent.ntype);
+        let kind = match self.file_kind(node, ntype) {
+            Some(kind) => kind,
+            None => return Ok(None),
+        };
+        let latest = self.latest_inode(node);
+        let (mode, uid, gid) = latest.map_or((0, 0, 0), |inode| (inode.mode, inode.uid, inode.gid));
+        let (atime, mtime, ctime) =
+            latest.map_or((0, 0, 0), |inode| (inode.atime, inode.mtime, inode.ctime));
+        let symlink_target = match ntype {
+            DT_LNK => Some(self.link_target(node)?),
+            _ => None,
+        };
+        let device = match kind {
+            FileKind::CharDev | FileKind::BlockDev => Some(self.device_number(node)?),
+            _ => None,
+        };
+        let (compressed_size, data_size) = match ntype {
+            DT_REG => self.live_nodes(node).iter().fold((0, 0), |(csize, dsize), inode| {
+                (csize + inode.csize as u64, dsize + inode.dsize as u64)
+            }),
+            _ => (0, 0),
+        };
+        let nlink = match ntype {
+            DT_DIR => 2 + self.children(node).filter(|child| child.ntype == DT_DIR).count() as u32,
+            _ => self.path_index().names(node),
+        };
+
+        Ok(Some(Jffs2Entry {
+            size: if ntype == DT_DIR { 0 } else { self.file_size(node) },
+            is_file: ntype == DT_REG,
+            kind,
+            mode,
+            uid,
+            gid,
+            atime,
+            mtime,
+            ctime,
+            nlink,
+            compressed_size,
+            data_size,
+            path: output_path,
+            symlink_target,
+            device,
+            ino: node,
+        }))
+    }
+}
+
+/// Iterator over the entries of an image, see [`Jffs2Reader::walk`]
+#[derive(Debug)]
+pub struct Walk<'r, 'a> {
+    reader: &'r Jffs2Reader<'a>,
+    dirents: std::vec::IntoIter<&'r Jffs2Dirent>,
+}
+
+impl Iterator for Walk<'_, '_> {
+    type Item = Result<Jffs2Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for dirent in self.dirents.by_ref() {
+            match self.reader.entry(dirent) {
+                Ok(Some(entry)) => return Some(Ok(entry)),
+                Ok(None) => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        None
+    }
+}
+
+/// extract the data from a jffs2 file
+/// input : the jffs2 file
+/// output : the output path
+pub fn extract_jffs2(input: impl AsRef<Path>, output: impl AsRef<Path>) -> Result<()> {
+    Jffs2Reader::open(input)?.dump(output)
+}
+
+/// extract the entries of a jffs2 file matching any of the glob `patterns`,
+/// see [`Jffs2Reader::dump_matching`]
+pub fn extract_jffs2_matching(
+    input: impl AsRef<Path>,
+    output: impl AsRef<Path>,
+    patterns: &[&str],
+) -> Result<Vec<usize>> {
+    Jffs2Reader::open(input)?.dump_matching(output, patterns)
+}
+
+/// List all entries within the jffs2 image
+pub fn list_jffs2(input: impl AsRef<Path>) -> Result<Vec<Jffs2Entry>> {
+    Jffs2Reader::open(input)?.entries()
+}
+
+/// List the entries within the jffs2 image that can be resolved, along
+/// with those that cannot, see [`Jffs2Reader::entries_lossy`]
+pub fn list_jffs2_lossy(input: impl AsRef<Path>) -> Result<(Vec<Jffs2Entry>, Vec<ResolveError>)> {
+    Ok(Jffs2Reader::open(input)?.entries_lossy())
+}
+
+/// Scan the jffs2 image once and save the results to `index`
+#[cfg(feature = "index-cache")]
+pub fn save_jffs2_index(input: impl AsRef<Path>, index: impl AsRef<Path>) -> Result<()> {
+    Jffs2Reader::open(input)?.save_index(index)
+}
+
+/// extract the data from a jffs2 file using the scan results saved in `index`
+#[cfg(feature = "index-cache")]
+pub fn extract_jffs2_with_index(
+    input: impl AsRef<Path>,
+    index: impl AsRef<Path>,
+    output: impl AsRef<Path>,
+) -> Result<()> {
+    Jffs2Reader::open_with_index(input, index)?.dump(output)
+}
+
+/// List all entries within the jffs2 image using the scan results saved in `index`
+#[cfg(feature = "index-cache")]
+pub fn list_jffs2_with_index(
+    input: impl AsRef<Path>,
+    index: impl AsRef<Path>,
+) -> Result<Vec<Jffs2Entry>> {
+    Jffs2Reader::open_with_index(input, index)?.entries()
+}
+
+/// Inspect the jffs2 image without scanning it,
+/// `endianness` forces the byte order instead of detecting it
+pub fn image_info(input: impl AsRef<Path>, endianness: Option<Endianness>) -> Result<ImageInfo> {
+    let reader = Jffs2Reader::from_buffer(ImageBuffer::map(input)?, endianness, None)?;
+    Ok(reader.image_info())
+}
+
+/// Byte order of the first node of the image in `data`, past any erased or
+/// zeroed space, none unless its magic, length and header CRC check out in
+/// one of them
+pub fn probe(data: &[u8]) -> Option<Endianness> {
+    let start = data
+        .chunks_exact(4)
+        .position(|word| word != [0; 4] && word != [0xff; 4])?
+        * 4;
+    if Jffs2Reader::is_node_header(data, start, data.len(), true) {
+        Some(Endianness::Little)
+    } else if Jffs2Reader::is_node_header(data, start, data.len(), false) {
+        Some(Endianness::Big)
+    } else {
+        None
+    }
+}
+
+/// Describe the directory tree of the jffs2 image as a Graphviz DOT graph
+pub fn dot_jffs2(input: impl AsRef<Path>, opts: &dot::DotOptions) -> Result<String> {
+    Ok(Jffs2Reader::open(input)?.to_dot(opts))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testutil::*;
+
+    #[test]
+    #[ignore = "needs a test/test.jffs2 image, which is not in the repository"]
+    fn test_extract_jffs2() {
+        let input = Path::new("test/test.jffs2");
+        let mut reader = Jffs2Reader::new(input).expect("Failed to open file");
+        reader.scan().expect("Failed to scan");
+    }
+
+    #[test]
+    fn test_detect_endianness_after_padding() {
+        let mut builder = ImageBuilder::new();
+        builder.raw(&[0xff; 4096]);
+        builder.dir(1, 2, "etc");
+        builder.file(2, 3, "passwd", b"root:x:0:0::/root:/bin/sh\n");
+        let path = write_temp_image("le-padding", &builder.build());
+
+        let info = image_info(&path, None).expect("Failed to detect endianness");
+        assert_eq!(info.endianness(), Endianness::Little);
+        assert_eq!(info.detection().little_endian_nodes(), 4);
+        assert_eq!(info.detection().big_endian_nodes(), 0);
+        assert_eq!(info.confidence(), 1.0);
+
+        let entries = list_jffs2(&path).expect("Failed to list entries");
+        assert_eq!(entries.len(), 2);
+    }
+
+    #[test]
+    fn test_detect_big_endian_with_corrupted_first_node() {
+        let mut builder = ImageBuilder::big_endian();
+        builder.dir(1, 2, "etc");
+        builder.file(2, 3, "hosts", b"127.0.0.1 localhost\n");
+        let mut image = builder.build();
+        // break the header CRC of the first node
+        image[8] ^= 0xff;
+        let path = write_temp_image("be-corrupted", &image);
+
+        let info = image_info(&path, None).expect("Failed to detect endianness");
+        assert_eq!(info.endianness(), Endianness::Big);
+        assert_eq!(info.detection().big_endian_nodes(), 3);
+        assert!(!info.detection().overridden());
+
+        let info = image_info(&path, Some(Endianness::Little)).expect("Failed to open image");
+        assert_eq!(info.endianness(), Endianness::Little);
+        assert!(info.detection().overridden());
+    }
+
+    #[test]
+    fn test_probe() {
+        let mut builder = ImageBuilder::new();
+        builder.raw(&[0xff; 64]);
+        builder.dir(1, 2, "etc");
+        let image = builder.build();
+        assert_eq!(probe(&image), Some(Endianness::Little));
+        let reader = Jffs2Reader::from_bytes(&image[..]).unwrap();
+        assert_eq!(reader.endianness(), Endianness::Little);
+
+        let image = ImageBuilder::big_endian().dir(1, 2, "etc").build();
+        assert_eq!(probe(&image), Some(Endianness::Big));
+        let reader = Jffs2Reader::from_bytes(&image[..]).unwrap();
+        assert_eq!(reader.endianness(), Endianness::Big);
+
+        // the magic alone is not enough
+        let mut broken = image.clone();
+        broken[8] ^= 0xff;
+        assert_eq!(probe(&broken), None);
+        assert_eq!(probe(&[0xff; 4096]), None);
+        assert_eq!(probe(b"hsqs"), None);
+    }
+
+    #[test]
+    fn test_detect_endianness_without_nodes() {
+        let path = write_temp_image("no-nodes", &[0xff; 8192]);
+        let err = image_info(&path, None).unwrap_err();
+        assert!(err.to_string().contains("0 little-endian and 0 big-endian"));
+    }
+
+    #[test]
+    fn test_scan_progress() {
+        let mut builder = ImageBuilder::new();
+        builder.dir(1, 2, "etc");
+        for ino in 3..20 {
+            builder.file(2, ino, format!("file{}", ino), &[ino as u8; 100]);
+        }
+        let image = builder.build();
+        let path = write_temp_image("progress", &image);
+
+        let mut reader = Jffs2Reader::new(&path).expect("Failed to open file");
+        let mut reports = vec![];
+        reader
+            .scan_with_progress(256, Duration::MAX, |progress| reports.push(*progress))
+            .expect("Failed to scan");
+
+        assert!(reports.len() > 2);
+        assert!(reports.windows(2).all(|w| w[0].offset() < w[1].offset()));
+        assert!(reports.windows(2).all(|w| w[0].nodes() <= w[1].nodes()));
+        let last = reports.last().unwrap();
+        assert_eq!(last.offset(), image.len() as u64);
+        assert_eq!(last.image_size(), image.len() as u64);
+        assert_eq!(last.nodes(), 36);
+    }
+
+    #[test]
+    fn test_open_then_query() {
+        let image = ImageBuilder::new()
+            .dir(1, 2, "etc")
+            .file(2, 3, "passwd", b"root:x:0:0\n")
+            .build();
+        let path = write_temp_image("open", &image);
+
+        let mut reader = Jffs2Reader::open(&path).expect("Failed to open image");
+        let entries = reader.entries().expect("Failed to list entries");
+        assert_eq!(entries.len(), 2);
+
+        // scanning again must not add nodes
+        reader.scan().expect("Failed to scan");
+        assert_eq!(reader.inodes[&3].len(), 1);
+
+        let output = path.with_extension("out");
+        reader.dump(&output).expect("Failed to extract");
+        assert_eq!(
+            std::fs::read(output.join("etc/passwd")).unwrap(),
+            b"root:x:0:0\n"
+        );
+        std::fs::remove_dir_all(output).unwrap();
+    }
+
+    #[test]
+    fn test_scan_big_endian_nodes() {
+        let mut inode = InodeNode::file(2, 3, 0, b"big endian\n");
+        inode.uid = 1000;
+        inode.gid = 100;
+        inode.mtime = 0x6000_0000;
+        let image = ImageBuilder::big_endian()
+            .dirent(1, 2, 5, DT_REG, "hello.txt")
+            .inode(&inode)
+            .build();
+        let path = write_temp_image("be-nodes", &image);
+
+        let reader = Jffs2Reader::open(&path).expect("Failed to open image");
+        assert!(!reader.little_endian);
+
+        let dirent = reader.dirent_of(2).unwrap();
+        assert_eq!(dirent.pino, 1);
+        assert_eq!(dirent.version, 5);
+        assert_eq!(dirent.ntype, DT_REG);
+        assert_eq!(dirent.fname, "hello.txt");
+
+        let node = &reader.inodes[&2][0];
+        assert_eq!(node.version, 3);
+        assert_eq!(node.mode, S_IFREG | 0o644);
+        assert_eq!((node.uid, node.gid), (1000, 100));
+        assert_eq!(node.mtime, 0x6000_0000);
+        assert_eq!(node.offset, 0);
+        assert_eq!(node.csize, 11);
+        assert_eq!(node.dsize, 11);
+        assert_eq!(reader.read_inode_data(2).unwrap(), b"big endian\n");
+    }
+
+    #[test]
+    fn test_builder_options() {
+        // a dirent whose name runs past the end of its node
+        let mut bad_dirent = vec![0u8; 28];
+        bad_dirent[..4].copy_from_slice(&1u32.to_le_bytes());
+        bad_dirent[8..12].copy_from_slice(&9u32.to_le_bytes());
+        bad_dirent[16] = 200;
+        let mut builder = ImageBuilder::new();
+        builder.dir(1, 2, "etc").file(2, 3, "passwd", b"root:x:0:0\n");
+        let bad_offset = builder.build().len() as u64;
+        let image = builder
+            .node(0xE001, &bad_dirent)
+            .file(1, 4, "README", b"hello")
+            .build();
+        let path = write_temp_image("builder", &image);
+
+        assert!(Jffs2Reader::open(&path).is_err());
+
+        let mut reader = Jffs2Reader::builder(&path)
+            .endianness(Endianness::Little)
+            .lenient(true)
+            .build()
+            .expect("Failed to open image");
+        reader.scan().expect("Lenient scan failed");
+        assert!(reader.image_info().detection().overridden());
+        assert_eq!(reader.entries().unwrap().len(), 3);
+        assert_eq!(reader.skipped_nodes(), [bad_offset]);
+
+        let mut reader = Jffs2Reader::builder(&path)
+            .lenient(true)
+            .root_inode(2)
+            .build()
+            .expect("Failed to open image");
+        reader.scan().expect("Lenient scan failed");
+        let entries = reader.entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].path(), Path::new("passwd"));
+    }
+
+    #[test]
+    fn test_lenient_looks_inside_broken_nodes() {
+        // an inode whose data runs past its node, with nodes in its payload
+        let inner = ImageBuilder::new().file(1, 5, "inner", b"found\n").build();
+        let mut inode = vec![0u8; 56];
+        inode[..4].copy_from_slice(&9u32.to_le_bytes());
+        inode[36..40].copy_from_slice(&0x10000u32.to_le_bytes());
+        inode.extend(inner);
+        let image = ImageBuilder::new()
+            .file(1, 2, "motd", b"hi\n")
+            .node(0xE002, &inode)
+            .build();
+
+        let mut reader = Jffs2Reader::from_bytes(&image[..]).unwrap();
+        assert!(reader.scan().is_err());
+
+        let mut reader = Jffs2ReaderBuilder::from_bytes(&image[..])
+            .lenient(true)
+            .build()
+            .unwrap();
+        reader.scan().unwrap();
+        assert_eq!(reader.skipped_nodes().len(), 1);
+        assert_eq!(reader.read_file("/motd").unwrap(), b"hi\n");
+        assert_eq!(reader.read_file("/inner").unwrap(), b"found\n");
+    }
+
+    #[test]
+    fn test_lenient_truncated_node() {
+        let mut builder = ImageBuilder::new();
+        builder.file(1, 2, "motd", b"hi\n");
+        // a node claiming more bytes than the image has, before valid ones
+        let damaged = builder.build().len();
+        let mut header = vec![];
+        header.extend(JFFS2_MAGIC.to_le_bytes());
+        header.extend((NodeType::Inode as u16).to_le_bytes());
+        header.extend(0x10_0000u32.to_le_bytes());
+        header.extend(jffs2_crc32(&header).to_le_bytes());
+        builder.raw(&header);
+        builder.file(1, 3, "issue", b"welcome\n");
+        let truncated = builder.build().len();
+        builder.file(1, 4, "partial", &[b'x'; 256]);
+        let mut image = builder.build();
+        // cut the dump in the middle of the last inode
+        image.truncate(image.len() - 100);
+
+        let mut reader = Jffs2Reader::from_bytes(&image[..]).unwrap();
+        reader.scan().unwrap();
+        assert!(reader.read_file("/issue").is_err());
+
+        let mut reader = Jffs2ReaderBuilder::from_bytes(&image[..])
+            .lenient(true)
+            .build()
+            .unwrap();
+        reader.scan().unwrap();
+        assert_eq!(reader.read_file("/motd").unwrap(), b"hi\n");
+        assert_eq!(reader.read_file("/issue").unwrap(), b"welcome\n");
+        let skipped = reader.skipped_nodes();
+        assert_eq!(skipped.len(), 2);
+        assert_eq!(skipped[0], damaged as u64);
+        assert!(skipped[1] > truncated as u64);
+    }
+
+    #[test]
+    fn test_header_crc() {
+        let mut builder = ImageBuilder::new();
+        builder.dir(1, 2, "etc");
+        let offset = builder.build().len();
+        builder.file(2, 3, "passwd", b"root:x:0:0\n");
+        builder.file(1, 4, "README", b"hello");
+        let mut image = builder.build();
+        // totlen of the passwd dirent, its header CRC no longer matches
+        image[offset + 4] ^= 0x40;
+
+        for lenient in [false, true] {
+            let mut reader = Jffs2ReaderBuilder::from_bytes(&image[..])
+                .lenient(lenient)
+                .build()
+                .unwrap();
+            reader.scan().expect("Failed to scan");
+            assert!(reader.dirent_of(3).is_none());
+            assert!(reader.dirent_of(4).is_some());
+        }
+    }
+
+    #[test]
+    fn test_false_magic_in_leading_garbage() {
+        // a bootloader in front of the filesystem, with a node magic and a
+        // length running past the end of the image
+        let mut garbage: Vec<u8> = (0..256u32).map(|i| (i * 151 + 7) as u8).collect();
+        garbage[64..76]
+            .copy_from_slice(&[0x85, 0x19, 0x01, 0xe0, 0xff, 0xff, 0xff, 0x7f, 1, 2, 3, 4]);
+        let image = ImageBuilder::new()
+            .raw(&garbage)
+            .dir(1, 2, "etc")
+            .file(2, 3, "passwd", b"root:x:0:0\n")
+            .build();
+
+        let mut reader = Jffs2Reader::from_bytes(&image[..]).unwrap();
+        reader.scan().unwrap();
+        assert_eq!(reader.entries().unwrap().len(), 2);
+        assert_eq!(reader.read_file("/etc/passwd").unwrap(), b"root:x:0:0\n");
+    }
+
+    #[test]
+    fn test_verify_data_crc() {
+        let image = ImageBuilder::new()
+            .file(1, 2, "motd", b"hello")
+            .build();
+        let mut corrupted = image.clone();
+        // last byte of the data, after the dirent and the inode header
+        corrupted[44 + 68 + 4] ^= 0x20;
+
+        let mut reader = Jffs2ReaderBuilder::from_bytes(&corrupted[..]).build().unwrap();
+        reader.scan().unwrap();
+        assert_eq!(reader.read_file("motd").unwrap(), b"hellO");
+
+        let mut reader = Jffs2ReaderBuilder::from_bytes(&corrupted[..])
+            .verify_data_crc(true)
+            .build()
+            .unwrap();
+        reader.scan().unwrap();
+        let err = reader.read_file("motd").unwrap_err().to_string();
+        assert!(err.starts_with("inode 2 data CRC mismatch at offset 0x70: expected "), "{}", err);
+
+        let mut reader = Jffs2ReaderBuilder::from_bytes(&image[..])
+            .verify_data_crc(true)
+            .build()
+            .unwrap();
+        reader.scan().unwrap();
+        assert_eq!(reader.read_file("motd").unwrap(), b"hello");
+    }
+
+    #[test]
+    fn test_data_crc_check() {
+        let image = ImageBuilder::new()
+            .dir(1, 2, "etc")
+            .file(2, 3, "motd", b"hello")
+            .file(1, 4, "issue", b"welcome")
+            .build();
+        let mut corrupted = image.clone();
+        // the motd inode follows the etc dirent and inode and its own dirent
+        let node = 44 + 68 + 44;
+        corrupted[node + 68] ^= 0x20;
+        let output = std::env::temp_dir().join(format!("jffs2-rs-data-crc-{}", std::process::id()));
+
+        let mut reader = Jffs2ReaderBuilder::from_bytes(&corrupted[..])
+            .data_crc_check(DataCrcCheck::ZeroFill)
+            .build()
+            .unwrap();
+        reader.scan().unwrap();
+        assert_eq!(reader.read_file("/etc/motd").unwrap(), [0; 5]);
+        assert_eq!(reader.read_file("/issue").unwrap(), b"welcome");
+        reader.dump(&output).unwrap();
+        assert_eq!(std::fs::read(output.join("etc/motd")).unwrap(), [0; 5]);
+        let mismatches = reader.data_crc_mismatches();
+        assert_eq!(mismatches.len(), 2);
+        assert_eq!(mismatches[0].path(), Path::new("etc/motd"));
+        assert_eq!(mismatches[0].offset(), node as u64);
+        assert_eq!(mismatches[0].expected(), jffs2_crc32(b"hello"));
+        assert_eq!(mismatches[0].actual(), jffs2_crc32(b"Hello"));
+        std::fs::remove_dir_all(&output).unwrap();
+
+        let mut reader = Jffs2ReaderBuilder::from_bytes(&corrupted[..])
+            .data_crc_check(DataCrcCheck::Warn)
+            .build()
+            .unwrap();
+        reader.scan().unwrap();
+        assert_eq!(reader.read_file("/etc/motd").unwrap(), b"Hello");
+        assert_eq!(reader.data_crc_mismatches().len(), 1);
+
+        let mut reader = Jffs2ReaderBuilder::from_bytes(&image[..])
+            .data_crc_check(DataCrcCheck::ZeroFill)
+            .build()
+            .unwrap();
+        reader.scan().unwrap();
+        reader.dump(&output).unwrap();
+        assert!(reader.data_crc_mismatches().is_empty());
+        std::fs::remove_dir_all(&output).unwrap();
+    }
+
+    #[test]
+    fn test_verify_node_crc() {
+        let mut builder = ImageBuilder::new();
+        builder.dir(1, 2, "etc");
+        let inode = builder.build().len() + 44;
+        builder.file(2, 3, "motd", b"hello");
+        let image = builder.build();
+        let mut corrupted = image.clone();
+        // mctime of the etc dirent and mtime of the motd inode
+        corrupted[24] ^= 0x01;
+        corrupted[inode + 12 + 20] ^= 0x01;
+
+        let mut reader = Jffs2ReaderBuilder::from_bytes(&image[..])
+            .verify_node_crc(NodeCrcCheck::Fail)
+            .build()
+            .unwrap();
+        reader.scan().unwrap();
+        assert!(reader.node_crc_failures().is_empty());
+
+        let mut reader = Jffs2Reader::from_bytes(&corrupted[..]).unwrap();
+        reader.scan().unwrap();
+        assert!(reader.node_crc_failures().is_empty());
+
+        let mut reader = Jffs2ReaderBuilder::from_bytes(&corrupted[..])
+            .verify_node_crc(NodeCrcCheck::Keep)
+            .build()
+            .unwrap();
+        reader.scan().unwrap();
+        assert_eq!(reader.node_crc_failures(), [0, inode as u64]);
+        assert_eq!(reader.read_file("/etc/motd").unwrap(), b"hello");
+
+        let mut reader = Jffs2ReaderBuilder::from_bytes(&corrupted[..])
+            .verify_node_crc(NodeCrcCheck::Skip)
+            .build()
+            .unwrap();
+        reader.scan().unwrap();
+        assert_eq!(reader.node_crc_failures(), [0, inode as u64]);
+        assert!(reader.dirent_of(2).is_none());
+        assert!(!reader.inodes.contains_key(&3));
+
+        let mut reader = Jffs2ReaderBuilder::from_bytes(&corrupted[..])
+            .verify_node_crc(NodeCrcCheck::Fail)
+            .build()
+            .unwrap();
+        let err = reader.scan().unwrap_err();
+        assert_eq!(err.to_string(), "bad node CRC at offset 0x0");
+    }
+
+    #[test]
+    fn test_cleanmarker_and_padding() {
+        let header = |nodetype: u16, totlen: u32| {
+            let mut header = vec![];
+            header.extend(0x1985u16.to_le_bytes());
+            header.extend(nodetype.to_le_bytes());
+            header.extend(totlen.to_le_bytes());
+            header.extend(jffs2_crc32(&header).to_le_bytes());
+            header
+        };
+        let image = ImageBuilder::new()
+            // a cleanmarker claiming more than the image holds
+            .raw(&header(0x2003, 0xffff_fff0))
+            .dir(1, 2, "etc")
+            // padding up to the end of a 4KB block
+            .node(0x2004, &[0xff; 4096 - 12 - 44 - 68 - 12])
+            .file(2, 3, "passwd", b"root:x:0:0\n")
+            // padding whose length runs past the end
+            .raw(&header(0x2004, 0x10_0000))
+            .file(1, 4, "motd", b"hi\n")
+            .build();
+
+        let mut reader = Jffs2Reader::from_bytes(&image[..]).unwrap();
+        reader.scan().unwrap();
+        assert_eq!(reader.entries().unwrap().len(), 3);
+        assert_eq!(reader.read_file("/etc/passwd").unwrap(), b"root:x:0:0\n");
+        assert_eq!(reader.read_file("/motd").unwrap(), b"hi\n");
+    }
+
+    #[test]
+    fn test_scan_stats() {
+        let mut broken = vec![0u8; 56];
+        broken[..4].copy_from_slice(&9u32.to_le_bytes());
+        // csize past the end of the node
+        broken[36..40].copy_from_slice(&0x10000u32.to_le_bytes());
+        let image = ImageBuilder::new()
+            .node(0x2003, &[])
+            .dir(1, 2, "etc")
+            .file(2, 3, "passwd", b"root:x:0:0\n")
+            .node(0x2004, &[0xff; 20])
+            .node(0xE00A, &[0; 8])
+            .node(0xE002, &broken)
+            .build();
+
+        let mut reader = Jffs2ReaderBuilder::from_bytes(&image[..])
+            .lenient(true)
+            .build()
+            .unwrap();
+        assert_eq!(reader.stats(), &ScanStats::default());
+        reader.scan().unwrap();
+        let stats = reader.stats();
+        assert_eq!((stats.dirents(), stats.inodes(), stats.xattrs()), (2, 2, 0));
+        assert_eq!((stats.cleanmarkers(), stats.padding(), stats.unknown()), (1, 1, 1));
+        assert_eq!((stats.skipped(), stats.node_crc_failures()), (1, 0));
+        assert_eq!(stats.bytes_scanned(), image.len() as u64);
+    }
+
+    #[test]
+    fn test_xattrs() {
+        // owner rw, user 1000 r, group r, mask r, others nothing
+        let mut acl = vec![];
+        acl.extend(1u32.to_le_bytes());
+        for (tag, perm, id) in [(0x01u16, 6u16, None), (0x02, 4, Some(1000u32)), (0x04, 4, None)] {
+            acl.extend(tag.to_le_bytes());
+            acl.extend(perm.to_le_bytes());
+            acl.extend(id.into_iter().flat_map(u32::to_le_bytes));
+        }
+        for (tag, perm) in [(0x10u16, 4u16), (0x20, 0)] {
+            acl.extend(tag.to_le_bytes());
+            acl.extend(perm.to_le_bytes());
+        }
+        // cap_net_raw, effective and permitted
+        let capability: Vec<u8> = [0x0200_0001u32, 1 << 13, 0, 0, 0]
+            .into_iter()
+            .flat_map(u32::to_le_bytes)
+            .collect();
+        let image = ImageBuilder::new()
+            .dir(1, 2, "bin")
+            .file(2, 3, "ping", b"\x7fELF")
+            .xattr(1, 1, 2, "selinux", b"u:object_r:system_file:s0\0")
+            .xattr(2, 1, 2, "capability", &capability)
+            .xattr(3, 1, 1, "comment", b"old")
+            .xattr(3, 2, 1, "comment", b"new")
+            .xattr(4, 1, 3, "", &acl)
+            .xattr(5, 1, 1, "gone", b"")
+            .xref(3, 1, 0)
+            .xref(3, 2, 0)
+            .xref(3, 3, 0)
+            .xref(3, 4, 0)
+            .xref(2, 1, 0)
+            .xref(2, 5, 0)
+            // detached later
+            .xref(2, 5, 1)
+            .build();
+
+        let mut reader = Jffs2Reader::from_bytes(&image[..]).unwrap();
+        reader.scan().unwrap();
+        let entries = reader.entries().unwrap();
+        let entry = |name: &str| entries.iter().find(|e| e.path().ends_with(name)).unwrap();
+
+        let mut posix_acl = vec![];
+        posix_acl.extend(2u32.to_le_bytes());
+        for (tag, perm, id) in [(1u16, 6u16, u32::MAX), (2, 4, 1000), (4, 4, u32::MAX)]
+            .into_iter()
+            .chain([(0x10, 4, u32::MAX), (0x20, 0, u32::MAX)])
+        {
+            posix_acl.extend(tag.to_le_bytes());
+            posix_acl.extend(perm.to_le_bytes());
+            posix_acl.extend(id.to_le_bytes());
+        }
+        assert_eq!(
+            reader.xattrs(entry("ping")),
+            [
+                ("security.capability".to_string(), capability.clone()),
+                ("security.selinux".to_string(), b"u:object_r:system_file:s0\0".to_vec()),
+                ("system.posix_acl_access".to_string(), posix_acl),
+                ("user.comment".to_string(), b"new".to_vec()),
+            ]
+        );
+        assert_eq!(
+            reader.xattrs(entry("bin")),
+            [("security.selinux".to_string(), b"u:object_r:system_file:s0\0".to_vec())]
+        );
+
+        let output = std::env::temp_dir().join(format!("jffs2-rs-xattrs-{}", std::process::id()));
+        reader
+            .dump_with_options(&output, &DumpOptions::new().xattrs(true))
+            .unwrap();
+        assert_eq!(std::fs::read(output.join("bin/ping")).unwrap(), b"\x7fELF");
+        #[cfg(target_os = "linux")]
+        {
+            let path = std::ffi::CString::new(format!("{}/bin/ping", output.display())).unwrap();
+            let mut value = [0u8; 16];
+            let len = unsafe {
+                libc::lgetxattr(
+                    path.as_ptr(),
+                    c"user.comment".as_ptr(),
+                    value.as_mut_ptr().cast(),
+                    value.len(),
+                )
+            };
+            // some filesystems have no user attributes
+            if len >= 0 {
+                assert_eq!(&value[..len as usize], b"new");
+            }
+        }
+        std::fs::remove_dir_all(&output).unwrap();
+    }
+
+    #[test]
+    fn test_deleted_files() {
+        let image = ImageBuilder::new()
+            .dir(1, 2, "etc")
+            .file(2, 3, "passwd", b"root:x:0:0\n")
+            .dirent(2, 0, 2, DT_REG, "passwd")
+            // deleted, then created again under the same name
+            .file(1, 4, "motd", b"old\n")
+            .dirent(1, 0, 2, DT_REG, "motd")
+            .dirent(1, 5, 3, DT_REG, "motd")
+            .inode(&InodeNode::file(5, 1, 0, b"new\n"))
+            // the deletion moved ahead of the dirent by garbage collection
+            .dirent(1, 0, 5, DT_REG, "issue")
+            .file(1, 6, "issue", b"welcome\n")
+            .build();
+        let names = |reader: &Jffs2Reader| {
+            let mut names: Vec<_> = reader
+                .entries()
+                .unwrap()
+                .into_iter()
+                .map(|entry| entry.path().to_string_lossy().trim_end_matches('/').to_string())
+                .collect();
+            names.sort();
+            names
+        };
+
+        let mut reader = Jffs2Reader::from_bytes(&image[..]).unwrap();
+        reader.scan().unwrap();
+        assert_eq!(names(&reader), ["etc", "motd"]);
+        assert_eq!(reader.read_file("motd").unwrap(), b"new\n");
+        assert!(reader.read_file("etc/passwd").is_err());
+
+        let mut reader = Jffs2ReaderBuilder::from_bytes(&image[..])
+            .include_deleted(true)
+            .build()
+            .unwrap();
+        reader.scan().unwrap();
+        // a newer file under the same name still replaces the old motd
+        assert_eq!(names(&reader), ["etc", "etc/passwd", "issue", "motd"]);
+        assert_eq!(reader.read_file("motd").unwrap(), b"new\n");
+        assert_eq!(reader.read_file("etc/passwd").unwrap(), b"root:x:0:0\n");
+    }
+
+    #[test]
+    fn test_renames_and_hard_links() {
+        let image = ImageBuilder::new()
+            .dir(1, 2, "etc")
+            // moved into etc, the old name unlinked afterwards
+            .file(1, 3, "old.conf", b"conf\n")
+            .dirent(2, 3, 2, DT_REG, "new.conf")
+            .dirent(1, 0, 3, DT_REG, "old.conf")
+            // two names for one inode
+            .file(1, 4, "busybox", b"ELF")
+            .dirent(2, 4, 1, DT_REG, "sh")
+            .build();
+        let mut reader = Jffs2Reader::from_bytes(&image[..]).unwrap();
+        reader.scan().unwrap();
+
+        let mut names: Vec<_> = reader
+            .entries()
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.path().to_string_lossy().trim_end_matches('/').to_string())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["busybox", "etc", "etc/new.conf", "etc/sh"]);
+        assert_eq!(reader.read_file("etc/new.conf").unwrap(), b"conf\n");
+        assert_eq!(reader.read_file("etc/sh").unwrap(), b"ELF");
+
+        let entries = reader.entries().unwrap();
+        let ino = |name: &str| {
+            let entry = entries.iter().find(|entry| entry.path().ends_with(name)).unwrap();
+            entry.ino()
+        };
+        assert_eq!(ino("busybox"), ino("sh"));
+        assert_ne!(ino("busybox"), ino("new.conf"));
+        let nlink = |name: &str| {
+            let entry = entries.iter().find(|entry| entry.path().ends_with(name)).unwrap();
+            entry.nlink()
+        };
+        assert_eq!((nlink("sh"), nlink("new.conf"), nlink("etc")), (2, 1, 2));
+
+        let output = std::env::temp_dir().join(format!("jffs2-rs-links-{}", std::process::id()));
+        let report = reader.dump_with_report(&output, &DumpOptions::new()).unwrap();
+        assert_eq!((report.files(), report.hard_links()), (2, 1));
+        assert_eq!(std::fs::read(output.join("busybox")).unwrap(), b"ELF");
+        assert_eq!(std::fs::read(output.join("etc/sh")).unwrap(), b"ELF");
+        assert!(!output.join("old.conf").exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            let busybox = std::fs::metadata(output.join("busybox")).unwrap();
+            let sh = std::fs::metadata(output.join("etc/sh")).unwrap();
+            assert_eq!(busybox.ino(), sh.ino());
+            assert_eq!(busybox.nlink(), 2);
+        }
+        std::fs::remove_dir_all(output).unwrap();
+    }
+
+    #[test]
+    fn test_verify_name_crc() {
+        let mut builder = ImageBuilder::new();
+        builder.dir(1, 2, "etc");
+        let dirent = builder.build().len();
+        builder.file(2, 3, "passwd", b"root:x:0:0\n");
+        builder.file(1, 4, "motd", b"hi\n");
+        let mut image = builder.build();
+        // passwd becomes pbsswd
+        image[dirent + 40 + 1] ^= 0x03;
+
+        let mut reader = Jffs2Reader::from_bytes(&image[..]).unwrap();
+        reader.scan().unwrap();
+        assert_eq!(reader.read_file("/etc/pbsswd").unwrap(), b"root:x:0:0\n");
+
+        let mut reader = Jffs2ReaderBuilder::from_bytes(&image[..])
+            .verify_name_crc(NameCrcCheck::Drop)
+            .build()
+            .unwrap();
+        reader.scan().unwrap();
+        assert_eq!(reader.name_crc_failures(), [dirent as u64]);
+        assert_eq!(reader.entries().unwrap().len(), 2);
+        assert!(reader.read_file("/etc/pbsswd").is_err());
+
+        let mut reader = Jffs2ReaderBuilder::from_bytes(&image[..])
+            .verify_name_crc(NameCrcCheck::Quarantine("__corrupt__".into()))
+            .build()
+            .unwrap();
+        reader.scan().unwrap();
+        assert_eq!(reader.name_crc_failures(), [dirent as u64]);
+        assert_eq!(reader.entries().unwrap().len(), 4);
+        assert_eq!(reader.read_file("/__corrupt__/ino_3").unwrap(), b"root:x:0:0\n");
+        assert_eq!(reader.read_file("/motd").unwrap(), b"hi\n");
+
+        let output = std::env::temp_dir().join(format!("jffs2-rs-name-crc-{}", std::process::id()));
+        reader.dump(&output).unwrap();
+        assert_eq!(
+            std::fs::read(output.join("__corrupt__/ino_3")).unwrap(),
+            b"root:x:0:0\n"
+        );
+        std::fs::remove_dir_all(&output).unwrap();
+    }
+
+    #[test]
+    fn test_compression_methods() {
+        for compr in 0..=8 {
+            let method = Compression::try_from(compr).unwrap();
+            assert_eq!(method as u8, compr);
+        }
+        assert_eq!(Compression::Lzma.name(), "lzma");
+        assert!(!Compression::Copy.is_supported());
+        assert!(Compression::try_from(9).is_err());
+        assert_eq!(NodeType::try_from(0xE002).unwrap(), NodeType::Inode);
+        assert!(NodeType::try_from(0xE003).is_err());
+
+        let mut inode = InodeNode::file(2, 1, 0, b"??");
+        inode.compr = 9;
+        let image = ImageBuilder::new()
+            .dirent(1, 2, 1, DT_REG, "odd")
+            .inode(&inode)
+            .file(1, 3, "motd", b"hi\n")
+            .build();
+        // only reading the damaged file fails
+        let mut reader = Jffs2Reader::from_bytes(&image[..]).unwrap();
+        reader.scan().unwrap();
+        assert_eq!(reader.entries().unwrap().len(), 2);
+        assert_eq!(reader.inodes[&2][0].compression_method(), None);
+        let err = reader.read_file("odd").unwrap_err();
+        assert!(format!("{:#}", err).contains("unknown compression type 0x09"));
+        assert_eq!(reader.read_file("motd").unwrap(), b"hi\n");
+    }
+
+    #[test]
+    fn test_compression_summary() {
+        let data = vec![b'a'; 4096];
+        let mut encoder = flate2::write::ZlibEncoder::new(vec![], flate2::Compression::fast());
+        encoder.write_all(&data).unwrap();
+        let mut zlib = InodeNode::file(2, 2, 0, &encoder.finish().unwrap());
+        zlib.compr = Compression::Zlib as u8;
+        zlib.dsize = data.len() as u32;
+        let csize = zlib.data.len() as u32;
+        let image = ImageBuilder::new()
+            .dirent(1, 2, 1, DT_REG, "log")
+            // overwritten entirely by the zlib node
+            .inode(&InodeNode::file(2, 1, 0, b"old!"))
+            .inode(&zlib)
+            .inode(&InodeNode::file(2, 3, 4096, b"tail"))
+            .dir(1, 3, "etc")
+            .build();
+        let mut reader = Jffs2Reader::from_bytes(&image[..]).unwrap();
+        reader.scan().unwrap();
+
+        let entries = reader.entries().unwrap();
+        let log = entries.iter().find(|entry| entry.ino() == 2).unwrap();
+        assert_eq!(
+            reader.compression_summary(log),
+            [(Compression::Zlib, csize, 4096), (Compression::None, 4, 4)]
+        );
+        assert_eq!(log.total_compressed_size(), csize as u64 + 4);
+        assert_eq!(log.compression_ratio(), (csize + 4) as f64 / 4100.0);
+        assert!(log.compression_ratio() < 0.1);
+
+        let etc = entries.iter().find(|entry| entry.ino() == 3).unwrap();
+        assert!(reader.compression_summary(etc).is_empty());
+        assert_eq!(etc.compression_ratio(), 1.0);
+    }
+
+    #[cfg(feature = "rubin")]
+    #[test]
+    fn test_rubinmips() {
+        let data = b"#!/bin/sh\n# rcS for old mips boards\nmount -a\nmount -a\n\x00\xff".repeat(9);
+        let mut inode = InodeNode::file(2, 1, 0, &rubinmips_compress(&data));
+        inode.compr = Compression::Rubinmips as u8;
+        inode.dsize = data.len() as u32;
+        inode.isize = data.len() as u32;
+        let image = ImageBuilder::new()
+            .dirent(1, 2, 1, DT_REG, "rcS")
+            .inode(&inode)
+            .build();
+        let mut reader = Jffs2Reader::from_bytes(&image[..]).unwrap();
+        reader.scan().unwrap();
+        assert_eq!(reader.read_file("rcS").unwrap(), data);
+    }
+
+    #[test]
+    fn test_lzma_options() {
+        let data = b"console::respawn:/sbin/getty -L ttyS0 115200 vt100\n".repeat(7);
+        let mut inode = InodeNode::file(2, 1, 0, &lzma_compress(&data));
+        inode.compr = Compression::Lzma as u8;
+        inode.dsize = data.len() as u32;
+        inode.isize = data.len() as u32;
+        let image = ImageBuilder::new()
+            .dirent(1, 2, 1, DT_REG, "inittab")
+            .inode(&inode)
+            .build();
+
+        let lzma = LzmaOptions::new().lc(3).pb(2).dict_size(0x80_0000);
+        let mut reader = Jffs2ReaderBuilder::from_bytes(&image[..])
+            .lzma_options(lzma)
+            .build()
+            .unwrap();
+        reader.scan().unwrap();
+        assert_eq!(reader.read_file("inittab").unwrap(), data);
+
+        // the mkfs.jffs2 defaults do not match this stream
+        let mut reader = Jffs2Reader::from_bytes(&image[..]).unwrap();
+        reader.scan().unwrap();
+        assert!(reader.read_file("inittab").map_or(true, |read| read != data));
+
+        assert!(Jffs2ReaderBuilder::from_bytes(&image[..])
+            .lzma_options(LzmaOptions::new().pb(5))
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn test_from_bytes() {
+        let image = ImageBuilder::big_endian()
+            .dir(1, 2, "etc")
+            .file(2, 3, "passwd", b"root:x:0:0\n")
+            .build();
+
+        let mut reader = Jffs2Reader::from_bytes(&image[..]).expect("Failed to open image");
+        reader.scan().expect("Failed to scan");
+        assert_eq!(reader.entries().unwrap().len(), 2);
+        assert_eq!(reader.read_inode_data(3).unwrap(), b"root:x:0:0\n");
+
+        let mut reader = Jffs2ReaderBuilder::from_bytes(image.clone())
+            .endianness(Endianness::Big)
+            .build()
+            .expect("Failed to open image");
+        reader.scan().expect("Failed to scan");
+        let output = std::env::temp_dir().join(format!("jffs2-rs-{}-bytes", std::process::id()));
+        reader.dump(&output).expect("Failed to extract");
+        assert_eq!(
+            std::fs::read(output.join("etc/passwd")).unwrap(),
+            b"root:x:0:0\n"
+        );
+        std::fs::remove_dir_all(output).unwrap();
+
+        assert!(Jffs2Reader::from_bytes(vec![0xffu8; 4096]).is_err());
+    }
+
+    #[test]
+    fn test_truncated_images() {
+        let image = ImageBuilder::new()
+            .dir(1, 2, "etc")
+            .file(2, 3, "passwd", b"root:x:0:0\n")
+            .symlink(1, 4, "passwd", "etc/passwd")
+            .special(1, 5, "null", DT_CHR, S_IFCHR | 0o666, &[3, 1])
+            .build();
+        for cut in 0..image.len() {
+            for lenient in [false, true] {
+                let reader = Jffs2ReaderBuilder::from_bytes(&image[..cut])
+                    .lenient(lenient)
+                    .build();
+                let mut reader = match reader {
+                    Ok(reader) => reader,
+                    Err(_) => continue,
+                };
+                if reader.scan().is_err() {
+                    continue;
+                }
+                // whatever survived the cut reads back, or fails cleanly
+                for entry in reader.walk().flatten() {
+                    if entry.is_file() {
+                        let _ = reader.read_file(entry.path());
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_summaries() {
+        // a dirent the summaries don't list, only a linear scan finds it
+        let ghost = ImageBuilder::new().dirent(1, 9, 1, DT_REG, "ghost").build();
+        let image = ImageBuilder::new()
+            .dir(1, 2, "etc")
+            .file(2, 3, "hostname", b"router\n")
+            .summary(8192)
+            .raw(&ghost)
+            .file(1, 4, "motd", b"hi\n")
+            .summary(8192)
+            .file(1, 5, "issue", b"welcome\n")
+            .build();
+        let names = |reader: &Jffs2Reader| {
+            let mut names: Vec<_> = reader
+                .entries()
+                .unwrap()
+                .into_iter()
+                .map(|entry| entry.path().to_string_lossy().trim_end_matches('/').to_string())
+                .collect();
+            names.sort();
+            names
+        };
+
+        let mut reader = Jffs2ReaderBuilder::from_bytes(&image[..])
+            .summaries(true)
+            .build()
+            .unwrap();
+        reader.scan().unwrap();
+        let expected = ["etc", "etc/hostname", "issue", "motd"];
+        assert_eq!(names(&reader), expected);
+        assert_eq!(reader.read_file("/etc/hostname").unwrap(), b"router\n");
+        assert_eq!(reader.read_file("/issue").unwrap(), b"welcome\n");
+        assert_eq!(reader.stats().summaries(), 2);
+
+        let mut reader = Jffs2ReaderBuilder::from_bytes(&image[..])
+            .summaries(true)
+            .erase_block_size(8192)
+            .build()
+            .unwrap();
+        reader.scan().unwrap();
+        assert_eq!(names(&reader), expected);
+
+        let mut reader = Jffs2Reader::from_bytes(&image[..]).unwrap();
+        reader.scan().unwrap();
+        assert_eq!(names(&reader), ["etc", "etc/hostname", "ghost", "issue", "motd"]);
+        // the summary nodes are stepped over
+        assert_eq!(reader.stats().summaries(), 2);
+        assert_eq!(reader.stats().unknown(), 0);
+
+        // a summary pointing at no node is ignored and its block scanned
+        let mut stale = image.clone();
+        let at = 8192 - 8;
+        let offset = u32::from_le_bytes(stale[at..at + 4].try_into().unwrap()) as usize;
+        stale[offset + 32 + 6] ^= 0x04;
+        let mut reader = Jffs2ReaderBuilder::from_bytes(&stale[..])
+            .summaries(true)
+            .erase_block_size(8192)
+            .build()
+            .unwrap();
+        reader.scan().unwrap();
+        assert_eq!(names(&reader), expected);
+    }
+
+    #[test]
+    fn test_from_reader() {
+        let image = ImageBuilder::new()
+            .dir(1, 2, "etc")
+            .file(2, 3, "hostname", b"router\n")
+            .build();
+
+        let mut reader = Jffs2Reader::from_reader(std::io::Cursor::new(image)).unwrap();
+        reader.scan().unwrap();
+        assert_eq!(reader.entries().unwrap().len(), 2);
+        assert_eq!(reader.read_file("/etc/hostname").unwrap(), b"router\n");
+
+        assert!(Jffs2Reader::from_reader(&[0xffu8; 4096][..]).is_err());
+    }
+
+    #[test]
+    fn test_gzip_image() {
+        let image = ImageBuilder::new()
+            .dir(1, 2, "etc")
+            .file(2, 3, "hostname", b"router\n")
+            .build();
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::fast());
+        encoder.write_all(&image).unwrap();
+        let gzipped = encoder.finish().unwrap();
+
+        let path = write_temp_image("gzip", &gzipped);
+        let reader = Jffs2Reader::open(&path).unwrap();
+        assert_eq!(reader.read_file("/etc/hostname").unwrap(), b"router\n");
+        let mut reader = Jffs2Reader::from_reader(&gzipped[..]).unwrap();
+        reader.scan().unwrap();
+        assert_eq!(reader.entries().unwrap().len(), 2);
+
+        let err = Jffs2Reader::from_bytes(&gzipped[..gzipped.len() / 2]).unwrap_err();
+        assert!(err.to_string().contains("gzip"), "{}", err);
+    }
+
+    #[test]
+    fn test_swapped_words() {
+        let swap = |image: &[u8]| -> Vec<u8> {
+            image.chunks(2).flat_map(|word| word.iter().rev().copied()).collect()
+        };
+        for builder in [ImageBuilder::big_endian(), ImageBuilder::new()] {
+            let mut builder = builder;
+            builder.dir(1, 2, "etc");
+            builder.file(2, 3, "passwd", b"root:x:0:0\n");
+            let image = builder.build();
+            // with an odd trailing byte
+            let mut swapped = swap(&image);
+            swapped.push(0xff);
+
+            let mut reader = Jffs2Reader::from_bytes(&swapped[..]).unwrap();
+            assert!(reader.image_info().detection().swapped());
+            assert_eq!(reader.image_info().confidence(), 1.0);
+            reader.scan().unwrap();
+            assert_eq!(reader.read_file("etc/passwd").unwrap(), b"root:x:0:0\n");
+
+            let reader = Jffs2Reader::from_bytes(&image[..]).unwrap();
+            assert!(!reader.image_info().detection().swapped());
+            assert!(Jffs2ReaderBuilder::from_bytes(&image[..])
+                .swapped(true)
+                .build()
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn test_big_endian_resync() {
+        let garbage: Vec<u8> = (0..1000u32).map(|i| (i * 37 % 256) as u8).collect();
+        let image = ImageBuilder::big_endian()
+            .raw(&[0xff; 64])
+            .dir(1, 2, "etc")
+            .raw(&garbage)
+            // the magic in the other byte order is garbage too
+            .raw(&[0x85, 0x19, 0xe0, 0x01, 0, 0, 0, 0])
+            .file(2, 3, "passwd", b"root:x:0:0\n")
+            .raw(&[0; 12])
+            .symlink(1, 4, "passwd", "etc/passwd")
+            .build();
+        let mut reader = Jffs2Reader::from_bytes(&image[..]).unwrap();
+        reader.scan().unwrap();
+        assert_eq!(reader.entries().unwrap().len(), 3);
+        assert_eq!(reader.read_file("passwd").unwrap(), b"root:x:0:0\n");
+
+        // a node magic with a broken header is stepped over
+        let mut broken = image.clone();
+        broken.splice(64..64, [0x19, 0x85, 0xe0, 0x01, 0, 0, 0, 0x2c, 0, 0, 0, 0]);
+        let mut reader = Jffs2Reader::from_bytes(&broken[..]).unwrap();
+        reader.scan().unwrap();
+        assert_eq!(reader.entries().unwrap().len(), 3);
+    }
+
+    // a 32-bit target cannot map an image this large
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn test_image_past_4gb() {
+        let image = ImageBuilder::new()
+            .dir(1, 2, "etc")
+            .file(2, 3, "passwd", b"root:x:0:0\n")
+            .build();
+        // a sparse file, the filesystem starts right after the 4GB mark
+        let path = write_temp_image("past-4gb", &[]);
+        let mut file = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+        file.seek(std::io::SeekFrom::Start((4 << 30) + 4096)).unwrap();
+        file.write_all(&image).unwrap();
+        drop(file);
+
+        // nothing to detect the byte order from in the first bytes
+        let mut reader = Jffs2ReaderBuilder::new(&path)
+            .endianness(Endianness::Little)
+            .build()
+            .unwrap();
+        reader.scan().unwrap();
+        assert!(reader.inodes[&3][0].data_offset() > u32::MAX as u64);
+        assert_eq!(reader.read_file("etc/passwd").unwrap(), b"root:x:0:0\n");
+    }
+
+    #[test]
+    fn test_from_seekable() {
+        let big: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        let image = ImageBuilder::new()
+            .dir(1, 2, "etc")
+            .file(2, 3, "passwd", b"root:x:0:0\n")
+            .raw(&[0xff; 70_000])
+            .file(1, 4, "big", &big)
+            .build();
+
+        // the image sits in the middle of a larger dump
+        let mut dump = vec![0xaa; 4096];
+        dump.extend_from_slice(&image);
+        dump.extend_from_slice(&[0x55; 4096]);
+        let mut cursor = std::io::Cursor::new(dump);
+        cursor.set_position(4096);
+
+        let mut reader = Jffs2Reader::from_seekable(cursor, image.len() as u64)
+            .expect("Failed to open image");
+        reader.scan().expect("Failed to scan");
+        let mut paths: Vec<_> = reader
+            .entries()
+            .unwrap()
+            .iter()
+            .map(|entry| entry.path().clone())
+            .collect();
+        paths.sort();
+        assert_eq!(paths, ["big", "etc", "etc/passwd"].map(PathBuf::from));
+        assert_eq!(reader.read_inode_data(3).unwrap(), b"root:x:0:0\n");
+        assert_eq!(reader.read_inode_data(4).unwrap(), big);
+        assert_eq!(reader.image_info().size(), image.len() as u64);
+    }
+
+    #[test]
+    fn test_read_file() {
+        let image = ImageBuilder::new()
+            .dir(1, 2, "etc")
+            .dirent(2, 3, 1, DT_REG, "motd")
+            .inode(&InodeNode::file(3, 2, 6, b"world\n"))
+            .inode(&InodeNode::file(3, 1, 0, b"hello "))
+            .symlink(1, 4, "motd", "etc/motd")
+            .build();
+        let mut reader = Jffs2Reader::from_bytes(&image[..]).expect("Failed to open image");
+        reader.scan().expect("Failed to scan");
+
+        assert_eq!(reader.read_file("/etc/motd").unwrap(), b"hello world\n");
+        assert_eq!(reader.read_file("etc/motd").unwrap(), b"hello world\n");
+        assert_eq!(reader.read_file(Path::new("motd")).unwrap(), b"hello world\n");
+
+        let err = reader.read_file("/etc").unwrap_err();
+        assert_eq!(err.to_string(), "/etc: is a directory");
+        let err = reader.read_file("etc/missing").unwrap_err();
+        assert_eq!(err.to_string(), "etc/missing: no such file or directory");
+        assert!(reader.read_file("/ETC/motd").is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_dump_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mut script = InodeNode::file(3, 1, 0, b"#!/bin/sh\n");
+        script.mode = S_IFREG | 0o4755;
+        let mut secret = InodeNode::file(4, 1, 0, b"hunter2");
+        secret.mode = S_IFREG | 0o600;
+        let mut bin = InodeNode::file(2, 1, 0, &[]);
+        bin.mode = S_IFDIR | 0o555;
+        let image = ImageBuilder::new()
+            .dirent(1, 2, 1, DT_DIR, "bin")
+            .inode(&bin)
+            .dirent(2, 3, 1, DT_REG, "su")
+            .inode(&script)
+            .dirent(1, 4, 1, DT_REG, "shadow")
+            .inode(&secret)
+            .build();
+        let path = write_temp_image("permissions", &image);
+        let reader = Jffs2Reader::open(&path).unwrap();
+
+        let output = std::env::temp_dir().join(format!("jffs2-rs-perms-{}", std::process::id()));
+        reader.dump(&output).expect("Failed to extract");
+        let mode = |path: &str| {
+            std::fs::metadata(output.join(path)).unwrap().permissions().mode() & 0o7777
+        };
+        assert_eq!(mode("bin"), 0o555);
+        // setuid only on request
+        assert_eq!(mode("bin/su"), 0o755);
+        assert_eq!(mode("shadow"), 0o600);
+
+        std::fs::set_permissions(output.join("bin"), std::fs::Permissions::from_mode(0o755))
+            .unwrap();
+        reader.dump_with_options(&output, &DumpOptions::new().setid(true)).unwrap();
+        assert_eq!(mode("bin/su"), 0o4755);
+
+        std::fs::set_permissions(output.join("bin"), std::fs::Permissions::from_mode(0o755))
+            .unwrap();
+        std::fs::remove_dir_all(&output).unwrap();
+        let opts = DumpOptions::new().preserve_permissions(false);
+        reader.dump_with_options(&output, &opts).unwrap();
+        assert_ne!(mode("shadow"), 0o600);
+
+        std::fs::set_permissions(output.join("bin"), std::fs::Permissions::from_mode(0o755))
+            .unwrap();
+        std::fs::remove_dir_all(output).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_dump_preserve_owner() {
+        use std::os::unix::fs::MetadataExt;
+
+        let mut passwd = InodeNode::file(3, 1, 0, b"root:x:0:0\n");
+        passwd.uid = 1000;
+        passwd.gid = 100;
+        let image = ImageBuilder::new()
+            .dir(1, 2, "etc")
+            .dirent(2, 3, 1, DT_REG, "passwd")
+            .inode(&passwd)
+            .symlink(1, 4, "passwd", "etc/passwd")
+            .build();
+        let path = write_temp_image("owner", &image);
+        let reader = Jffs2Reader::open(&path).unwrap();
+        let inode = reader.latest_inode(3).unwrap();
+        assert_eq!((inode.uid(), inode.gid()), (1000, 100));
+
+        let output = std::env::temp_dir().join(format!("jffs2-rs-owner-{}", std::process::id()));
+        let opts = DumpOptions::new().preserve_owner(true);
+        reader
+            .dump_with_options(&output, &opts)
+            .expect("Missing privilege must not fail the extraction");
+        let metadata = std::fs::metadata(output.join("etc/passwd")).unwrap();
+        if nix::unistd::geteuid().is_root() {
+            assert_eq!((metadata.uid(), metadata.gid()), (1000, 100));
+        } else {
+            assert_eq!(metadata.uid(), nix::unistd::geteuid().as_raw());
+        }
+        std::fs::remove_dir_all(output).unwrap();
+    }
+
+    #[test]
+    fn test_dump_entry() {
+        let image = ImageBuilder::new()
+            .dir(1, 2, "etc")
+            .file(2, 3, "passwd", b"root:x:0:0\n")
+            .symlink(1, 4, "passwd", "etc/passwd")
+            .build();
+        let mut reader = Jffs2Reader::from_bytes(&image[..]).unwrap();
+        reader.scan().unwrap();
+
+        let output = std::env::temp_dir().join(format!("jffs2-rs-entry-{}", std::process::id()));
+        reader.dump_entry("/etc/passwd", output.join("a/users")).unwrap();
+        assert_eq!(std::fs::read(output.join("a/users")).unwrap(), b"root:x:0:0\n");
+
+        reader.dump_entry("etc", output.join("config")).unwrap();
+        assert!(output.join("config").is_dir());
+        assert_eq!(std::fs::read_dir(output.join("config")).unwrap().count(), 0);
+
+        reader.dump_entry("passwd", output.join("link")).unwrap();
+        assert_eq!(std::fs::read_link(output.join("link")).unwrap(), Path::new("etc/passwd"));
+
+        let err = reader.dump_entry("etc/shadow", output.join("shadow")).unwrap_err();
+        assert!(is_not_found(&err));
+        assert!(!output.join("shadow").exists());
+        std::fs::remove_dir_all(output).unwrap();
+    }
+
+    #[test]
+    fn test_dump_matching() {
+        let image = ImageBuilder::new()
+            .dir(1, 2, "etc")
+            .dir(2, 3, "init.d")
+            .file(3, 4, "rcS", b"mount -a\n")
+            .file(2, 5, "hosts.conf", b"order hosts\n")
+            .dir(1, 6, "lib")
+            .dir(6, 7, "modules")
+            .file(7, 8, "mtd.so", b"ELF")
+            .file(6, 9, "libc.so", b"ELF")
+            .file(1, 10, "motd", b"hi\n")
+            .build();
+        let path = write_temp_image("matching", &image);
+        let output = std::env::temp_dir().join(format!("jffs2-rs-match-{}", std::process::id()));
+
+        let patterns = ["*.conf", "lib/**/*.so", "/etc/init.d/*", "*.ko"];
+        let counts = extract_jffs2_matching(&path, &output, &patterns).unwrap();
+        assert_eq!(counts, [1, 2, 1, 0]);
+        assert!(output.join("etc/hosts.conf").exists());
+        assert!(output.join("etc/init.d/rcS").exists());
+        assert!(output.join("lib/modules/mtd.so").exists());
+        assert!(output.join("lib/libc.so").exists());
+        assert!(!output.join("motd").exists());
+        std::fs::remove_dir_all(&output).unwrap();
+
+        assert!(extract_jffs2_matching(&path, &output, &["[etc"]).is_err());
+    }
+
+    #[test]
+    fn test_extract_matching() {
+        let image = ImageBuilder::new()
+            .dir(1, 2, "etc")
+            .dir(2, 3, "init.d")
+            .file(3, 4, "rcS", b"mount -a\n")
+            .file(2, 5, "hosts.conf", b"order hosts\n")
+            .file(1, 6, "motd", b"hi\n")
+            .build();
+        let path = write_temp_image("extract-matching", &image);
+        let reader = Jffs2Reader::open(&path).unwrap();
+        let output = std::env::temp_dir().join(format!("jffs2-rs-extract-{}", std::process::id()));
+
+        let written = reader.extract_matching(&output, "/etc/init.d/*").unwrap();
+        assert_eq!(written, [output.join("etc/init.d/rcS")]);
+        assert!(output.join("etc/init.d").is_dir());
+        assert!(!output.join("etc/hosts.conf").exists());
+        std::fs::remove_dir_all(&output).unwrap();
+
+        let written = reader.extract_matching(&output, "etc/**").unwrap();
+        // `**` takes in the directory itself too
+        let expected = ["etc", "etc/hosts.conf", "etc/init.d", "etc/init.d/rcS"];
+        let expected = expected.map(|p| output.join(p));
+        assert_eq!(written, expected);
+        assert!(!output.join("motd").exists());
+        std::fs::remove_dir_all(&output).unwrap();
+
+        let written = reader.extract_matching(&output, "*.conf").unwrap();
+        assert_eq!(written, [output.join("etc/hosts.conf")]);
+        std::fs::remove_dir_all(&output).unwrap();
+
+        assert!(reader.extract_matching(&output, "[etc").is_err());
     }
 
-    fn read_uint16(buffer: &[u8], little_endian: bool, offset: usize) -> Result<u16> {
-        if offset + 2 > buffer.len() {
-            bail!(
-                "offset out of bounds: {} in a buffer of {}",
-                offset,
-                buffer.len()
-            );
-        }
-        let buffer = &buffer[offset..offset + 2];
+    #[test]
+    fn test_dump_preserve_times() {
+        let mut motd = InodeNode::file(3, 1, 0, b"hi\n");
+        motd.atime = 1_600_000_100;
+        motd.mtime = 1_600_000_000;
+        motd.ctime = 1_600_000_200;
+        let mut etc = InodeNode::file(2, 1, 0, &[]);
+        etc.mode = S_IFDIR | 0o755;
+        etc.mtime = 1_500_000_000;
+        let image = ImageBuilder::new()
+            .dirent(1, 2, 1, DT_DIR, "etc")
+            .inode(&etc)
+            .dirent(2, 3, 1, DT_REG, "motd")
+            .inode(&motd)
+            .symlink(1, 4, "motd", "etc/motd")
+            .build();
+        let path = write_temp_image("times", &image);
+        let reader = Jffs2Reader::open(&path).unwrap();
+
+        let entries = reader.entries().unwrap();
+        let entry = entries
+            .iter()
+            .find(|entry| entry.path() == Path::new("etc/motd"))
+            .unwrap();
+        assert_eq!(entry.atime(), UNIX_EPOCH + Duration::from_secs(1_600_000_100));
+        assert_eq!(entry.mtime(), UNIX_EPOCH + Duration::from_secs(1_600_000_000));
+        assert_eq!(entry.ctime(), UNIX_EPOCH + Duration::from_secs(1_600_000_200));
+
+        let output = std::env::temp_dir().join(format!("jffs2-rs-times-{}", std::process::id()));
+        reader.dump(&output).expect("Failed to extract");
+        let modified = |path: &str| {
+            std::fs::metadata(output.join(path)).unwrap().modified().unwrap()
+        };
+        assert_eq!(modified("etc/motd"), UNIX_EPOCH + Duration::from_secs(1_600_000_000));
+        assert_eq!(modified("etc"), UNIX_EPOCH + Duration::from_secs(1_500_000_000));
+        let accessed = std::fs::metadata(output.join("etc/motd")).unwrap().accessed().unwrap();
+        assert_eq!(accessed, UNIX_EPOCH + Duration::from_secs(1_600_000_100));
+        std::fs::remove_dir_all(&output).unwrap();
 
-        Ok(if little_endian {
-            u16::from_le_bytes(buffer.try_into().unwrap())
-        } else {
-            u16::from_be_bytes(buffer.try_into().unwrap())
-        })
+        let opts = DumpOptions::new().preserve_times(false);
+        reader.dump_with_options(&output, &opts).expect("Failed to extract");
+        assert!(modified("etc/motd") > UNIX_EPOCH + Duration::from_secs(1_600_000_000));
+        assert!(modified("etc") > UNIX_EPOCH + Duration::from_secs(1_600_000_000));
+        std::fs::remove_dir_all(output).unwrap();
     }
 
-    /// Read a string with at most `length` bytes, but will truncate before
-    /// that if there is a null byte.
-    fn read_str(buffer: &[u8], offset: usize, length: usize) -> Result<String> {
-        if offset >= buffer.len() {
-            bail!(
-                "offset out of bounds: {} in a buffer of {}",
-                offset,
-                buffer.len()
-            );
+    #[test]
+    fn test_dump_empty_dirs() {
+        let mut empty = InodeNode::file(3, 1, 0, &[]);
+        empty.mode = S_IFDIR | 0o700;
+        empty.mtime = 1_500_000_000;
+        let image = ImageBuilder::new()
+            .dir(1, 2, "var")
+            .dirent(2, 3, 1, DT_DIR, "empty")
+            .inode(&empty)
+            .dir(2, 4, "log")
+            .file(4, 5, "messages", b"boot\n")
+            .dir(1, 6, "tmp")
+            .build();
+        let path = write_temp_image("empty-dirs", &image);
+        let reader = Jffs2Reader::open(&path).unwrap();
+        let output = std::env::temp_dir().join(format!("jffs2-rs-empty-{}", std::process::id()));
+        let report = reader.dump_with_report(&output, &DumpOptions::new()).unwrap();
+        assert_eq!((report.dirs(), report.files()), (4, 1));
+        for dir in ["var/empty", "var/log", "tmp"] {
+            assert!(output.join(dir).is_dir(), "{}", dir);
         }
+        assert_eq!(std::fs::read_dir(output.join("var/empty")).unwrap().count(), 0);
+        let empty = std::fs::metadata(output.join("var/empty")).unwrap();
+        assert_eq!(empty.modified().unwrap(), UNIX_EPOCH + Duration::from_secs(1_500_000_000));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(empty.permissions().mode() & 0o7777, 0o700);
+        }
+        std::fs::remove_dir_all(&output).unwrap();
 
-        let str_bytes = buffer
+        let written = reader.dump_collecting(&output).unwrap();
+        let expected: Vec<_> = ["tmp", "var", "var/empty", "var/log", "var/log/messages"]
             .iter()
-            .skip(offset)
-            .take(length)
-            .take_while(|b| **b != 0)
-            .copied()
+            .map(|path| output.join(path))
             .collect();
+        assert_eq!(written, expected);
+        std::fs::remove_dir_all(output).unwrap();
+    }
 
-        let s = String::from_utf8(str_bytes)?;
-        Ok(s)
+    #[test]
+    fn test_dump_sanitize_names() {
+        let image = ImageBuilder::new()
+            .dir(1, 2, "aux")
+            .file(2, 3, "12:00.log", b"noon\n")
+            .file(1, 4, "Notes", b"upper\n")
+            .file(1, 5, "notes", b"lower\n")
+            .file(1, 6, "motd", b"hi\n")
+            .build();
+        let path = write_temp_image("sanitize", &image);
+        let reader = Jffs2Reader::open(&path).unwrap();
+        let output =
+            std::env::temp_dir().join(format!("jffs2-rs-sanitize-{}", std::process::id()));
+        let opts = DumpOptions::new().sanitize_names(true);
+        let report = reader.dump_with_report(&output, &opts).unwrap();
+        let renamed: Vec<_> = report
+            .renamed()
+            .iter()
+            .map(|(from, to)| {
+                let from = from.strip_prefix(&output).unwrap().to_str().unwrap().to_string();
+                (from, to.strip_prefix(&output).unwrap().to_str().unwrap().to_string())
+            })
+            .collect();
+        let expected = [
+            ("aux", "%61ux"),
+            ("aux/12:00.log", "%61ux/12%3A00.log"),
+            ("notes", "notes~1"),
+        ];
+        let expected: Vec<_> =
+            expected.iter().map(|(from, to)| (from.to_string(), to.to_string())).collect();
+        assert_eq!(renamed, expected);
+        assert_eq!(std::fs::read(output.join("%61ux/12%3A00.log")).unwrap(), b"noon\n");
+        assert_eq!(std::fs::read(output.join("Notes")).unwrap(), b"upper\n");
+        assert_eq!(std::fs::read(output.join("notes~1")).unwrap(), b"lower\n");
+        assert_eq!(std::fs::read(output.join("motd")).unwrap(), b"hi\n");
+        std::fs::remove_dir_all(output).unwrap();
     }
 
-    fn scan_dirent(&mut self, mm: &[u8]) -> Result<bool> {
-        if mm.len() < SIZE_OF_DIRENT {
-            return Ok(false);
+    #[test]
+    fn test_dump_long_paths() {
+        // past MAX_PATH on Windows
+        let mut builder = ImageBuilder::new();
+        for ino in 2..22 {
+            builder.dir(ino - 1, ino, format!("{:0>20}", ino));
         }
+        let image = builder.file(21, 22, "deep.txt", b"down\n").build();
+        let path = write_temp_image("long-paths", &image);
+        let reader = Jffs2Reader::open(&path).unwrap();
+        let output = std::env::temp_dir().join(format!("jffs2-rs-long-{}", std::process::id()));
+        let report = reader.dump_with_report(&output, &DumpOptions::new()).unwrap();
+        assert_eq!((report.dirs(), report.files()), (20, 1));
+        let deep = report.written().last().unwrap();
+        assert!(deep.as_os_str().len() > 400);
+        assert!(deep.ends_with("00000000000000000021/deep.txt"));
+        assert_eq!(std::fs::read(deep).unwrap(), b"down\n");
+        std::fs::remove_dir_all(output).unwrap();
+    }
 
-        let mut cur = std::io::Cursor::new(mm);
-
-        let (pino, version, ino, mctime) = <(u32, u32, u32, u32)>::unpack_from_le(&mut cur)?;
-        let (nsize, ntype) = <(u8, u8)>::unpack_from_le(&mut cur)?;
-        let (_unused, _node_crc, _name_crc) = <(u16, u32, u32)>::unpack_from_le(&mut cur)?;
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serialize_entries() {
+        let mut motd = InodeNode::file(3, 1, 0, b"hi\n");
+        motd.mtime = 1_600_000_000;
+        let image = ImageBuilder::new()
+            .dir(1, 2, "etc")
+            .dirent(2, 3, 1, DT_REG, "motd")
+            .inode(&motd)
+            .symlink(1, 4, "motd", "etc/motd")
+            .build();
+        let path = write_temp_image("serde", &image);
+        let reader = Jffs2Reader::open(&path).unwrap();
+        let mut entries = reader.entries().unwrap();
+        entries.sort_by_key(|entry| entry.ino());
 
-        if nsize as usize + SIZE_OF_DIRENT > mm.len() {
-            bail!("out of bounds when reading filename");
-        }
+        let json = serde_json::to_value(&entries).unwrap();
+        assert_eq!(json[1]["path"], "etc/motd");
+        assert_eq!(json[1]["kind"], "Regular");
+        assert_eq!(json[1]["mtime"], 1_600_000_000);
+        assert_eq!(json[1]["symlink_target"], serde_json::Value::Null);
+        assert_eq!(json[2]["symlink_target"], "etc/motd");
 
-        if let Some(old_dirent) = self.dirents.get(&ino) {
-            if old_dirent.version > version {
-                return Ok(true);
-            }
-        }
+        let back: Vec<Jffs2Entry> = serde_json::from_value(json).unwrap();
+        assert_eq!(back[1].path(), entries[1].path());
+        assert_eq!(back[1].mtime(), entries[1].mtime());
+        assert_eq!(back[2].symlink_target(), Some(Path::new("etc/motd")));
+    }
 
-        let fname = Jffs2Reader::read_str(mm, cur.position() as usize, nsize as usize)?;
-        self.dirents.insert(
-            ino,
-            Jffs2Dirent {
-                pino,
-                version,
-                mctime,
-                ntype,
-                fname,
-            },
+    #[test]
+    fn test_case_collisions() {
+        let image = ImageBuilder::new()
+            .dir(1, 2, "etc")
+            .file(2, 3, "Config", b"upper\n")
+            .file(2, 4, "config", b"lower\n")
+            .dir(1, 5, "Etc")
+            .file(5, 6, "hosts", b"127.0.0.1\n")
+            .file(1, 7, "motd", b"hi\n")
+            .build();
+        let path = write_temp_image("case", &image);
+        let reader = Jffs2Reader::open(&path).unwrap();
+        let pair = |kept: &str, other: &str| (PathBuf::from(kept), PathBuf::from(other));
+        assert_eq!(
+            reader.case_collisions().unwrap(),
+            [pair("Etc", "etc"), pair("etc/Config", "etc/config")]
         );
 
-        Ok(true)
+        let output = std::env::temp_dir().join(format!("jffs2-rs-case-{}", std::process::id()));
+        let opts = DumpOptions::new().case_collisions(CaseCollisionPolicy::Error);
+        let err = reader.dump_with_report(&output, &opts).unwrap_err();
+        assert!(err.to_string().contains("differ only in case"), "{}", err);
+        assert!(!output.exists());
+
+        let opts = DumpOptions::new().case_collisions(CaseCollisionPolicy::KeepFirst);
+        let report = reader.dump_with_report(&output, &opts).unwrap();
+        let mut skipped: Vec<_> = report.skipped().iter().map(|(path, _)| path.clone()).collect();
+        skipped.sort();
+        let expected: Vec<_> = ["etc", "etc/Config", "etc/config"]
+            .iter()
+            .map(|path| output.join(path))
+            .collect();
+        assert_eq!(skipped, expected);
+        assert_eq!(std::fs::read(output.join("Etc/hosts")).unwrap(), b"127.0.0.1\n");
+        std::fs::remove_dir_all(&output).unwrap();
+
+        let opts = DumpOptions::new().case_collisions(CaseCollisionPolicy::Rename);
+        let report = reader.dump_with_report(&output, &opts).unwrap();
+        assert_eq!(
+            report.renamed(),
+            [
+                (output.join("etc"), output.join("etc~1")),
+                (output.join("etc/Config"), output.join("etc~1/Config")),
+                (output.join("etc/config"), output.join("etc~1/config~1")),
+            ]
+        );
+        assert_eq!(std::fs::read(output.join("etc~1/Config")).unwrap(), b"upper\n");
+        assert_eq!(std::fs::read(output.join("etc~1/config~1")).unwrap(), b"lower\n");
+        std::fs::remove_dir_all(output).unwrap();
     }
 
-    fn scan_inode(&mut self, mm: &[u8], idx: u32) -> Result<bool> {
-        if mm.len() < SIZE_OF_INODE {
-            return Ok(false);
-        }
+    #[test]
+    fn test_dump_filtered() {
+        let image = ImageBuilder::new()
+            .dir(1, 2, "dev")
+            .file(2, 3, "console", b"")
+            .dir(1, 4, "etc")
+            .file(4, 5, "passwd", b"root:x:0:0\n")
+            .file(4, 6, "firmware.bin", &[0; 64])
+            .build();
+        let path = write_temp_image("filtered", &image);
+        let reader = Jffs2Reader::open(&path).unwrap();
+        let output = std::env::temp_dir().join(format!("jffs2-rs-filter-{}", std::process::id()));
 
-        let mut cur = std::io::Cursor::new(mm);
+        let mut seen = vec![];
+        reader
+            .dump_filtered(&output, |entry| {
+                seen.push(entry.path().clone());
+                entry.path() != Path::new("dev") && entry.size() < 32
+            })
+            .expect("Failed to extract");
+        assert!(output.join("etc/passwd").exists());
+        assert!(!output.join("etc/firmware.bin").exists());
+        assert!(!output.join("dev").exists());
+        // the content of a rejected directory is never looked at
+        assert!(!seen.contains(&PathBuf::from("dev/console")));
+        assert_eq!(seen.len(), 4);
+        std::fs::remove_dir_all(output).unwrap();
+    }
 
-        let (ino, version, _mode, _uid, _gid) =
-            <(u32, u32, u32, u16, u16)>::unpack_from_le(&mut cur)?;
-        let (isize, _atime, mtime, _ctime) = <(u32, u32, u32, u32)>::unpack_from_le(&mut cur)?;
-        let (foffset, csize, dsize, compr, _usercompr) =
-            <(u32, u32, u32, u8, u8)>::unpack_from_le(&mut cur)?;
-        let (_flags, _data_crc, _node_crc) = <(u16, u32, u32)>::unpack_from_le(&mut cur)?;
+    #[test]
+    fn test_dump_with_report() {
+        let mut copy = InodeNode::file(5, 1, 0, b"data");
+        copy.compr = Compression::Copy as u8;
+        let mut bad_zlib = InodeNode::file(6, 1, 0, b"not zlib");
+        bad_zlib.compr = Compression::Zlib as u8;
+        let mut unknown = InodeNode::file(8, 1, 0, b"????");
+        unknown.compr = 9;
+        let image = ImageBuilder::new()
+            .dir(1, 2, "etc")
+            .file(2, 3, "passwd", b"root:x:0:0\n")
+            .symlink(1, 4, "passwd", "etc/passwd")
+            .dirent(1, 5, 1, DT_REG, "copied")
+            .inode(&copy)
+            .dirent(1, 6, 1, DT_REG, "broken")
+            .inode(&bad_zlib)
+            .file(40, 7, "stray", b"lost")
+            .dirent(1, 8, 1, DT_REG, "odd")
+            .inode(&unknown)
+            .build();
+        let path = write_temp_image("report", &image);
+        let reader = Jffs2Reader::open(&path).unwrap();
+        let output = std::env::temp_dir().join(format!("jffs2-rs-report-{}", std::process::id()));
 
-        if csize as usize + SIZE_OF_INODE > mm.len() {
-            bail!("out of bounds when reading data");
-        }
+        // the stray dirent stops the extraction before anything is written
+        assert!(reader.dump_with_report(&output, &DumpOptions::new()).is_err());
+        assert!(!output.exists());
 
-        if let Some(inodes) = self.inodes.get(&ino) {
-            for old_inode in inodes {
-                if old_inode.version > version && foffset == old_inode.offset {
-                    return Ok(true);
+        let report = reader
+            .dump_with_report(&output, &DumpOptions::new().best_effort(true))
+            .expect("Best effort extraction failed");
+        assert_eq!(report.files(), 1);
+        assert_eq!(report.dirs(), 1);
+        assert_eq!(report.symlinks(), 1);
+        assert_eq!(report.bytes(), 11);
+        assert_eq!(report.unsupported(), [(output.join("copied"), Compression::Copy)]);
+        // every failure has the node behind it
+        let broken = reader.inodes[&6][0].node_offset();
+        let odd = reader.inodes[&8][0].node_offset();
+        let stray = image.windows(5).position(|w| w == b"stray").unwrap() as u64 - 40;
+        let (broken_path, odd_path, stray_path) =
+            (output.join("broken"), output.join("odd"), output.join("stray"));
+        let expected = [(broken_path, broken), (odd_path, odd), (stray_path, stray)];
+        let failed: Vec<_> =
+            report.failed_nodes().iter().map(|(path, at)| (path.to_path_buf(), *at)).collect();
+        assert_eq!(failed, expected);
+        let message = format!("{:#}", report.failures()[0].1);
+        assert!(message.starts_with(&format!("reading the node at offset {:#x}: ", broken)));
+        let message = format!("{:#}", report.failures()[1].1);
+        assert!(message.ends_with("unknown compression type 0x09"), "{}", message);
+        let message = format!("{:#}", report.failures()[2].1);
+        assert!(message.ends_with(": cannot find parent node 40"), "{}", message);
+        assert!(output.join("etc/passwd").exists());
+        assert!(!output.join("copied").exists());
+        std::fs::remove_dir_all(&output).unwrap();
+    }
+
+    #[test]
+    fn test_progress_hook() {
+        let image = ImageBuilder::new()
+            .dir(1, 2, "etc")
+            .file(2, 3, "passwd", b"root:x:0:0\n")
+            .symlink(1, 4, "passwd", "etc/passwd")
+            .build();
+        let events = std::sync::Arc::new(Mutex::new(vec![]));
+        let path = write_temp_image("progress", &image);
+        let mut reader = Jffs2Reader::new(&path).unwrap();
+        let sink = events.clone();
+        reader.set_progress(move |progress| {
+            let event = match progress {
+                Progress::Scan(scan) => format!("scan {}/{}", scan.offset(), scan.image_size()),
+                Progress::Dump(dump) => {
+                    format!("dump {}/{} {}", dump.done(), dump.total(), dump.path().display())
                 }
+            };
+            sink.lock().unwrap().push(event);
+        });
+        reader.scan().unwrap();
+
+        let output = std::env::temp_dir().join(format!("jffs2-rs-progress-{}", std::process::id()));
+        reader.dump(&output).unwrap();
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[0], format!("scan {0}/{0}", image.len()));
+        assert!(events[1].starts_with("dump 1/3 "));
+        // the symbolic link is created last
+        assert_eq!(events[3], format!("dump 3/3 {}", output.join("passwd").display()));
+        std::fs::remove_dir_all(output).unwrap();
+    }
+
+    #[test]
+    fn test_dump_with_progress() {
+        let image = ImageBuilder::new()
+            .dir(1, 2, "etc")
+            .file(2, 3, "passwd", b"root:x:0:0\n")
+            .symlink(1, 4, "passwd", "etc/passwd")
+            .build();
+        let mut reader = Jffs2Reader::from_bytes(&image[..]).unwrap();
+        reader.scan().unwrap();
+
+        let output = std::env::temp_dir().join(format!("jffs2-rs-dump-cb-{}", std::process::id()));
+        let mut calls = vec![];
+        reader
+            .dump_with_progress(&output, |path, done, total| {
+                calls.push((path.to_path_buf(), done, total))
+            })
+            .unwrap();
+        assert_eq!(
+            calls,
+            [
+                (output.join("etc"), 1, 3),
+                (output.join("etc/passwd"), 2, 3),
+                (output.join("passwd"), 3, 3),
+            ]
+        );
+        std::fs::remove_dir_all(output).unwrap();
+    }
+
+    #[test]
+    fn test_cancel() {
+        let image = ImageBuilder::new()
+            .dir(1, 2, "etc")
+            .file(2, 3, "passwd", b"root:x:0:0\n")
+            .file(2, 4, "group", b"root:x:0:\n")
+            .file(2, 5, "shadow", b"root:*:0:0\n")
+            .build();
+        let cancel = Arc::new(AtomicBool::new(true));
+        let path = write_temp_image("cancel", &image);
+        let mut reader = Jffs2Reader::new(&path).unwrap();
+        reader.set_cancel(cancel.clone());
+        assert!(reader.scan().unwrap_err().is::<Cancelled>());
+
+        cancel.store(false, Ordering::Relaxed);
+        reader.scan().unwrap();
+        assert_eq!(reader.entries().unwrap().len(), 4);
+
+        // cancel as soon as the first entry is out
+        let token = cancel.clone();
+        reader.set_progress(move |_| token.store(true, Ordering::Relaxed));
+        let output = std::env::temp_dir().join(format!("jffs2-rs-cancel-{}", std::process::id()));
+        let opts = DumpOptions::new().best_effort(true);
+        let err = reader.dump_with_report(&output, &opts).unwrap_err();
+        assert!(err.is::<Cancelled>());
+        // one entry, plus the directory holding it
+        let written = walk_dir(&output);
+        assert!(written.iter().filter(|path| path.is_file()).count() <= 1);
+        assert!(written.len() <= 2, "{:?}", written);
+        std::fs::remove_dir_all(output).unwrap();
+    }
+
+    fn walk_dir(dir: &Path) -> Vec<PathBuf> {
+        let mut paths = vec![];
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                paths.extend(walk_dir(&path));
             }
+            paths.push(path);
         }
+        paths
+    }
 
-        let data = idx + SIZE_OF_INODE as u32;
-        let new_node = Jffs2Inode {
-            version,
-            iszie: isize,
-            mtime,
-            offset: foffset,
-            csize,
-            dsize,
-            compr,
-            data,
-        };
+    #[test]
+    #[cfg(feature = "parallel")]
+    fn test_dump_parallel() {
+        let mut builder = ImageBuilder::new();
+        builder.dir(1, 2, "lib");
+        for i in 0..64 {
+            let data = format!("module {}\n", i).repeat(i as usize + 1);
+            builder.file(2, 10 + i, format!("mod{}.ko", i), data.as_bytes());
+        }
+        builder.symlink(1, 9, "modules", "lib");
+        let path = write_temp_image("parallel", &builder.build());
+        let reader = Jffs2Reader::open(&path).unwrap();
 
-        match self.inodes.get_mut(&ino) {
-            Some(inodes) => {
-                inodes.push(new_node);
-            }
-            _ => {
-                let inodes = vec![new_node];
-                self.inodes.insert(ino, inodes);
-            }
+        let output = std::env::temp_dir().join(format!("jffs2-rs-parallel-{}", std::process::id()));
+        let report = reader
+            .dump_with_report(&output, &DumpOptions::new().threads(4))
+            .expect("Parallel extraction failed");
+        assert_eq!(report.files(), 64);
+        assert_eq!(report.symlinks(), 1);
+        for i in 0..64 {
+            let data = std::fs::read(output.join(format!("modules/mod{}.ko", i))).unwrap();
+            assert_eq!(data, format!("module {}\n", i).repeat(i + 1).as_bytes());
         }
+        std::fs::remove_dir_all(&output).unwrap();
 
-        Ok(true)
+        reader.dump_parallel(&output).unwrap();
+        assert_eq!(std::fs::read(output.join("lib/mod0.ko")).unwrap(), b"module 0\n");
+        assert_eq!(std::fs::read(output.join("modules/mod63.ko")).unwrap().len(), 64 * 10);
+        std::fs::remove_dir_all(output).unwrap();
     }
 
-    fn pad(x: u32) -> u32 {
-        if x % 4 != 0 {
-            x + (4 - (x % 4))
-        } else {
-            x
-        }
+    #[test]
+    fn test_dump_subtree() {
+        let image = ImageBuilder::new()
+            .dir(1, 2, "etc")
+            .dir(2, 3, "init.d")
+            .file(3, 4, "rcS", b"mount -a\n")
+            .file(2, 5, "passwd", b"root:x:0:0\n")
+            .file(1, 6, "motd", b"hi\n")
+            .dir(1, 7, "bin")
+            .file(7, 8, "busybox", b"\x7fELF")
+            .dir(1, 9, "sbin")
+            // a later name of busybox
+            .dirent(9, 8, 2, DT_REG, "init")
+            .build();
+        let path = write_temp_image("subtree", &image);
+        let reader = Jffs2Reader::open(&path).unwrap();
+        let output = std::env::temp_dir().join(format!("jffs2-rs-subtree-{}", std::process::id()));
+
+        reader
+            .dump_subtree(&output, "/etc", &SubtreeOptions::new())
+            .expect("Failed to extract /etc");
+        assert_eq!(std::fs::read(output.join("etc/init.d/rcS")).unwrap(), b"mount -a\n");
+        assert!(output.join("etc/passwd").exists());
+        assert!(!output.join("motd").exists());
+        std::fs::remove_dir_all(&output).unwrap();
+
+        let stripped = SubtreeOptions::new().strip_prefix(true);
+        reader.dump_subtree(&output, "etc", &stripped).unwrap();
+        assert!(output.join("init.d/rcS").exists());
+        assert!(output.join("passwd").exists());
+        assert!(!output.join("etc").exists());
+        std::fs::remove_dir_all(&output).unwrap();
+
+        reader.dump_subtree(&output, "/etc/passwd", &stripped).unwrap();
+        assert_eq!(std::fs::read(output.join("passwd")).unwrap(), b"root:x:0:0\n");
+        assert!(!output.join("init.d").exists());
+        std::fs::remove_dir_all(&output).unwrap();
+
+        // only the name asked for, not the other names of the file
+        reader.dump_subtree(&output, "bin/busybox", &stripped).unwrap();
+        assert_eq!(std::fs::read(output.join("busybox")).unwrap(), b"\x7fELF");
+        assert!(!output.join("init").exists());
+        std::fs::remove_dir_all(&output).unwrap();
+
+        reader.dump_subtree(&output, "/bin", &SubtreeOptions::new()).unwrap();
+        assert!(output.join("bin/busybox").exists());
+        assert!(!output.join("sbin").exists());
+        std::fs::remove_dir_all(&output).unwrap();
+
+        let err = reader
+            .dump_subtree(&output, "/usr/lib", &SubtreeOptions::new())
+            .unwrap_err();
+        assert_eq!(err.to_string(), "/usr/lib: no such file or directory");
+        reader
+            .dump_subtree(&output, "/usr/lib", &SubtreeOptions::new().allow_missing(true))
+            .expect("Missing prefix should be ignored");
+        assert!(!output.exists());
     }
 
-    pub fn scan(&mut self) -> Result<()> {
-        let mut idx = 0;
-        let maxmm = self.buffer.len() as u32;
+    #[test]
+    fn test_walk() {
+        let image = ImageBuilder::new()
+            .dir(1, 2, "etc")
+            .file(2, 3, "passwd", b"root:x:0:0\n")
+            .file(1, 4, "motd", b"hi\n")
+            // parent 9 does not exist
+            .file(9, 5, "orphan", b"lost")
+            .build();
+        let mut reader = Jffs2Reader::from_bytes(&image[..]).expect("Failed to open image");
+        reader.scan().expect("Failed to scan");
 
-        while idx < maxmm - 12 {
-            let magic = Jffs2Reader::read_uint16(&self.buffer, self.little_endian, idx as usize)?;
-            if magic != 0x1985 {
-                // plus 4 here, rather than 2
-                idx += 4;
-                continue;
-            }
+        let passwd = reader
+            .walk()
+            .filter_map(Result::ok)
+            .find(|entry| entry.path() == Path::new("etc/passwd"))
+            .expect("File not found");
+        assert!(passwd.is_file());
+        assert_eq!(passwd.size(), 11);
 
-            idx += 2;
+        let results: Vec<_> = reader.walk().collect();
+        assert_eq!(results.len(), 4);
+        assert_eq!(results.iter().filter(|result| result.is_err()).count(), 1);
+        assert!(reader.entries().is_err());
+    }
 
-            let nodetype =
-                Jffs2Reader::read_uint16(&self.buffer, self.little_endian, idx as usize)?;
-            idx += 2;
+    #[test]
+    fn test_for_each_entry() {
+        let image = ImageBuilder::new()
+            .dir(1, 2, "etc")
+            .file(2, 3, "passwd", b"root:x:0:0\n")
+            .file(1, 4, "motd", b"hi\n")
+            .symlink(1, 5, "issue", "motd")
+            .build();
+        let mut reader = Jffs2Reader::from_bytes(&image[..]).expect("Failed to open image");
+        reader.scan().expect("Failed to scan");
 
-            let totlen = Jffs2Reader::read_uint32(&self.buffer, self.little_endian, idx as usize)?;
-            idx += 4;
+        let mut seen = vec![];
+        reader
+            .for_each_entry(|entry, data| {
+                let path = entry.path().to_string_lossy().trim_end_matches('/').to_string();
+                seen.push((path, data.map(<[u8]>::to_vec)));
+                Ok(())
+            })
+            .unwrap();
+        seen.sort();
+        assert_eq!(
+            seen,
+            [
+                ("etc".to_string(), None),
+                ("etc/passwd".to_string(), Some(b"root:x:0:0\n".to_vec())),
+                ("issue".to_string(), None),
+                ("motd".to_string(), Some(b"hi\n".to_vec())),
+            ]
+        );
 
-            let _hdh_crc =
-                Jffs2Reader::read_uint32(&self.buffer, self.little_endian, idx as usize)?;
-            idx += 4;
+        let mut calls = 0;
+        let err = reader
+            .for_each_entry(|_, _| {
+                calls += 1;
+                bail!("enough")
+            })
+            .unwrap_err();
+        assert_eq!((err.to_string(), calls), ("enough".to_string(), 1));
+    }
 
-            if totlen > maxmm - idx || totlen == 0 {
-                break;
-            }
+    #[test]
+    fn test_sorted_entries() {
+        let image = ImageBuilder::new()
+            .file(1, 2, "zImage", b"kernel")
+            .dir(1, 3, "usr")
+            .file(3, 4, "b", b"b")
+            .dir(3, 5, "a")
+            .file(5, 6, "z", b"z")
+            .file(1, 7, "README", b"readme")
+            .dirent(1, 6, 2, DT_REG, "also-z")
+            .build();
+        let path = write_temp_image("sorted", &image);
+        let reader = Jffs2Reader::open(&path).unwrap();
+        let paths: Vec<_> = reader
+            .entries()
+            .unwrap()
+            .iter()
+            .map(|entry| entry.path().to_string_lossy().trim_end_matches('/').to_string())
+            .collect();
+        assert_eq!(paths, ["README", "also-z", "usr", "usr/a", "usr/a/z", "usr/b", "zImage"]);
 
-            if nodetype == JFFS2_NODETYPE_DIRENT {
-                idx -= 12;
-                let slice =
-                    self.buffer[idx as usize + 12..idx as usize + totlen as usize].to_owned();
-                self.scan_dirent(&slice)?;
-            } else if nodetype == JFFS2_NODETYPE_INODE {
-                idx -= 12;
-                let slice =
-                    self.buffer[idx as usize + 12..idx as usize + totlen as usize].to_owned();
-                self.scan_inode(&slice, idx + 12)?;
-            }
+        // the data goes to the first name by path, the other one is the link
+        let output = std::env::temp_dir().join(format!("jffs2-rs-sorted-{}", std::process::id()));
+        let mut order = vec![];
+        reader
+            .dump_with_progress(&output, |path, _, _| order.push(path.to_path_buf()))
+            .unwrap();
+        let relative = |path: &PathBuf| path.strip_prefix(&output).unwrap().to_path_buf();
+        let order: Vec<_> = order.iter().map(relative).collect();
+        let expected = ["usr", "usr/a", "README", "also-z", "usr/b", "zImage", "usr/a/z"];
+        assert_eq!(order, expected.iter().map(PathBuf::from).collect::<Vec<_>>());
+        std::fs::remove_dir_all(output).unwrap();
+    }
 
-            idx += Jffs2Reader::pad(totlen);
-        }
+    #[test]
+    fn test_entries_lossy() {
+        let image = ImageBuilder::new()
+            .dir(1, 2, "etc")
+            .file(2, 3, "passwd", b"root:x:0:0\n")
+            .file(40, 4, "orphan", b"lost\n")
+            .file(1, 5, "motd", b"hello\n")
+            .build();
+        let path = write_temp_image("lossy", &image);
+        let reader = Jffs2Reader::open(&path).unwrap();
+        assert!(reader.entries().is_err());
 
-        Ok(())
+        let (entries, errors) = list_jffs2_lossy(&path).unwrap();
+        let paths: Vec<_> = entries.iter().map(|entry| entry.path().to_path_buf()).collect();
+        assert_eq!(paths.len(), 3);
+        assert!(paths.contains(&PathBuf::from("etc/passwd")));
+        assert_eq!(errors.len(), 1);
+        assert_eq!((errors[0].pino(), errors[0].ino()), (40, 4));
+        assert_eq!(errors[0].name(), "orphan");
+        assert_eq!(errors[0].error().to_string(), "cannot find parent node 40");
     }
 
-    fn rtime_decompress(compressed_buffer: &[u8], dstlen: usize) -> Vec<u8> {
-        let mut dst = vec![];
-        let mut pos = 0;
-        let mut position = Vec::new();
-        position.resize(256, 0);
-
-        while dst.len() < dstlen {
-            let val = &compressed_buffer[pos..pos + 1];
-            pos += 1;
-            let val = val[0];
-            dst.push(val);
-
-            let repeat = &compressed_buffer[pos..pos + 1];
-            let mut repeat = repeat[0];
-            pos += 1;
-            let mut backoffs = position[val as usize];
-
-            position[val as usize] = dst.len();
-            if repeat != 0 {
-                if backoffs + repeat as usize >= dst.len() {
-                    while repeat != 0 {
-                        dst.push(dst[backoffs]);
-                        backoffs += 1;
-                        repeat -= 1;
-                    }
-                } else {
-                    let slice = &dst[backoffs..backoffs + repeat as usize].to_owned();
-                    dst.extend(slice);
-                }
+    #[test]
+    fn test_open_file() {
+        let image = ImageBuilder::new()
+            .dirent(1, 2, 1, DT_REG, "blob")
+            .inode(&InodeNode::file(2, 3, 8, b"89abcdef"))
+            .inode(&InodeNode::file(2, 1, 0, b"0123"))
+            .inode(&InodeNode::file(2, 2, 4, b"4567"))
+            .dir(1, 3, "etc")
+            .build();
+        let mut reader = Jffs2Reader::from_bytes(&image[..]).expect("Failed to open image");
+        reader.scan().expect("Failed to scan");
+
+        let mut file = reader.open_file("/blob").unwrap();
+        assert_eq!(file.len(), 16);
+        let mut buf = [0; 3];
+        let mut data = vec![];
+        loop {
+            match file.read(&mut buf).unwrap() {
+                0 => break,
+                n => data.extend_from_slice(&buf[..n]),
             }
         }
+        assert_eq!(data, b"0123456789abcdef");
+        assert_eq!(file.read(&mut buf).unwrap(), 0);
 
-        dst
+        let err = reader.open_file("etc").unwrap_err();
+        assert_eq!(err.to_string(), "etc: is a directory");
     }
 
-    fn dump_file(&self, output_path: &PathBuf, node: u32) -> Result<()> {
-        let inodes = match self.inodes.get(&node) {
-            Some(inodes) => inodes,
-            None => return Ok(()),
-        };
-
-        let mut sorted_inodes = inodes.clone();
-        sorted_inodes.sort_by_key(|k| k.offset);
-        if let Some(dirname) = output_path.parent() {
-            if !dirname.exists() {
-                std::fs::create_dir_all(dirname)?;
-            }
+    #[test]
+    fn test_truncated_file() {
+        let mut builder = ImageBuilder::new();
+        builder.dirent(1, 2, 1, DT_REG, "log");
+        for page in 0..25 {
+            let data = vec![b'a' + page as u8; 4096];
+            builder.inode(&InodeNode::file(2, page + 1, page * 4096, &data));
         }
-        let mut file = File::create(output_path.jffs_fix())?;
-        for inode in sorted_inodes {
-            if inode.compr == JFFS2_COMPR_NONE {
-                file.write_all(
-                    &self.buffer[inode.data as usize..(inode.data + inode.csize) as usize],
-                )?;
-            } else if inode.compr == JFFS2_COMPR_ZERO {
-                let cycle = inode.dsize / 0x1000;
-                let reminder = inode.dsize % 0x1000;
-                for _ in 0..cycle {
-                    file.write_all(&vec![0; 0x1000])?;
-                }
-                if reminder != 0 {
-                    file.write_all(&vec![0; reminder as usize])?;
-                }
-            } else if inode.compr == JFFS2_COMPR_ZLIB {
-                let mut decomp = flate2::read::ZlibDecoder::new(
-                    &self.buffer[inode.data as usize..(inode.data + inode.csize) as usize],
-                );
-                let mut buf = Vec::new();
-                decomp.read_to_end(&mut buf)?;
-                file.write_all(&buf)?;
-            } else if inode.compr == JFFS2_COMPR_RTIME {
-                let buf = Jffs2Reader::rtime_decompress(
-                    &self.buffer[inode.data as usize..(inode.data + inode.csize) as usize],
-                    inode.dsize as usize,
-                );
+        // truncated from 100KB down to 10 bytes
+        let mut truncate = InodeNode::file(2, 26, 10, b"");
+        truncate.isize = 10;
+        builder.inode(&truncate);
+        let image = builder.build();
+        let mut reader = Jffs2Reader::from_bytes(&image[..]).unwrap();
+        reader.scan().unwrap();
 
-                file.write_all(&buf)?;
-            } else if inode.compr == JFFS2_COMPR_LZO {
-                let mut decomp: Vec<u8> = Vec::new();
-                let decompressed_size = inode.dsize as usize;
-                decomp.resize(inode.dsize as usize, 0);
+        let entries = reader.entries().unwrap();
+        assert_eq!(entries[0].size(), 10);
+        assert_eq!(reader.read_file("log").unwrap(), b"aaaaaaaaaa");
+        let mut data = vec![];
+        reader.open_file("log").unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"aaaaaaaaaa");
 
-                let input = &self.buffer[inode.data as usize..(inode.data + inode.csize) as usize];
+        let output =
+            std::env::temp_dir().join(format!("jffs2-rs-truncated-{}", std::process::id()));
+        reader.dump(&output).unwrap();
+        assert_eq!(std::fs::read(output.join("log")).unwrap(), b"aaaaaaaaaa");
+        std::fs::remove_dir_all(output).unwrap();
+    }
 
-                unsafe {
-                    lzo1x_decompress_safe(
-                        input.as_ptr(),
-                        input.len(),
-                        decomp.as_mut_ptr(),
-                        &decompressed_size,
-                        std::ptr::null(),
-                    );
-                }
+    #[test]
+    fn test_entry_metadata() {
+        let mut tail = InodeNode::file(2, 2, 4, b"bbbbbbbb");
+        tail.mode = S_IFREG | 0o600;
+        tail.uid = 1000;
+        tail.gid = 100;
+        tail.mtime = 1_700_000_000;
+        let image = ImageBuilder::new()
+            .dirent(1, 2, 1, DT_REG, "overlap")
+            .inode(&InodeNode::file(2, 1, 0, b"aaaaaaaa"))
+            .inode(&tail)
+            .dir(1, 3, "etc")
+            .build();
+        let mut reader = Jffs2Reader::from_bytes(&image[..]).unwrap();
+        reader.scan().unwrap();
+
+        let entries = reader.entries().unwrap();
+        let file = entries.iter().find(|e| e.path() == Path::new("overlap")).unwrap();
+        // the fragments sum up to 16 bytes but overlap
+        assert_eq!(file.size(), 12);
+        assert_eq!(reader.read_file("overlap").unwrap(), b"aaaabbbbbbbb");
+        assert_eq!(file.mode(), S_IFREG | 0o600);
+        assert_eq!((file.uid(), file.gid()), (1000, 100));
+        assert_eq!(file.mtime(), UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+
+        let dir = entries.iter().find(|e| e.path() == Path::new("etc")).unwrap();
+        assert_eq!(dir.mode(), S_IFDIR | 0o755);
+        assert_eq!(dir.size(), 0);
+    }
 
-                file.write_all(&decomp)?;
-            } else if inode.compr == JFFS2_COMPR_LZMA {
-                let pb = LZMA_BEST_PB;
-                let lp = LZMA_BEST_LP;
-                let lc = LZMA_BEST_LC;
+    #[test]
+    fn test_rewritten_file() {
+        let mut rewrite = InodeNode::file(2, 4, 0, &[b'c'; 4096]);
+        rewrite.isize = 8192;
+        let mut older = InodeNode::file(2, 3, 2048, &[b'd'; 4096]);
+        older.isize = 8192;
+        // the newest node is written before an older one, as after garbage
+        // collection
+        let image = ImageBuilder::new()
+            .dirent(1, 2, 1, DT_REG, "config")
+            .inode(&InodeNode::file(2, 1, 0, &[b'a'; 8192]))
+            .inode(&InodeNode::file(2, 2, 4096, &[b'b'; 4096]))
+            .inode(&rewrite)
+            .inode(&older)
+            .build();
+        let mut reader = Jffs2Reader::from_bytes(&image[..]).unwrap();
+        reader.scan().unwrap();
 
-                // reconstruct the lzma header
-                // lzma_header = struct.pack("<BIQ", PROPERTIES, DICT_SIZE, outlen)
-                let mut input: Vec<u8> = Vec::new();
+        let mut expected = vec![b'c'; 4096];
+        expected.extend([b'd'; 2048]);
+        expected.extend([b'b'; 2048]);
+        assert_eq!(reader.read_file("config").unwrap(), expected);
 
-                let properties = (pb * 5 + lp) * 9 + lc;
-                input.push(properties);
+        let mut data = vec![];
+        reader.open_file("config").unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, expected);
 
-                let dict_size = DICT_SIZE.to_le_bytes();
-                input.extend(dict_size);
+        let output =
+            std::env::temp_dir().join(format!("jffs2-rs-rewritten-{}", std::process::id()));
+        reader.dump(&output).unwrap();
+        assert_eq!(std::fs::read(output.join("config")).unwrap(), expected);
+        std::fs::remove_dir_all(output).unwrap();
+    }
 
-                let out_len = (inode.dsize as u64).to_le_bytes();
-                input.extend(out_len);
+    #[test]
+    fn test_size_limits() {
+        let mut bomb = InodeNode::file(2, 1, 0, b"boom");
+        // a few bytes claiming 3 GiB
+        bomb.isize = 3 << 30;
+        let image = ImageBuilder::new()
+            .dirent(1, 2, 1, DT_REG, "bomb")
+            .file(1, 3, "a", &[b'a'; 60])
+            .file(1, 4, "b", &[b'b'; 60])
+            .inode(&bomb)
+            .build();
+        let mut reader = Jffs2Reader::from_bytes(&image[..]).unwrap();
+        reader.scan().unwrap();
+        let err = reader.read_file("bomb").unwrap_err();
+        assert!(err.to_string().contains("inode 2"), "{}", err);
+        assert!(reader.open_file("bomb").is_err());
 
-                // append the compressed blob
-                input
-                    .extend(&self.buffer[inode.data as usize..(inode.data + inode.csize) as usize]);
+        // 64 MiB of zeros in a node claiming 10 bytes, partly overwritten so
+        // it is decompressed whole
+        let mut encoder = flate2::write::ZlibEncoder::new(vec![], flate2::Compression::best());
+        encoder.write_all(&vec![0; 64 << 20]).unwrap();
+        let mut zlib = InodeNode::file(5, 1, 0, &encoder.finish().unwrap());
+        zlib.compr = Compression::Zlib as u8;
+        zlib.dsize = 10;
+        zlib.isize = 10;
+        let mut patch = InodeNode::file(5, 2, 4, b"xy");
+        patch.isize = 10;
+        let zbomb = ImageBuilder::new()
+            .dirent(1, 5, 1, DT_REG, "zbomb")
+            .inode(&zlib)
+            .inode(&patch)
+            .build();
+        let mut reader = Jffs2Reader::from_bytes(&zbomb[..]).unwrap();
+        reader.scan().unwrap();
+        assert_eq!(reader.read_file("zbomb").unwrap(), b"\0\0\0\0xy\0\0\0\0");
+        let mut out = vec![];
+        reader.decompress_inode(&reader.inodes[&5][0], &mut out).unwrap();
+        assert_eq!(out.len(), 10);
 
-                let mut decomp: Vec<u8> = Vec::new();
-                let mut input_reader = std::io::Cursor::new(&input);
-                lzma_decompress(&mut input_reader, &mut decomp)?;
+        let mut reader = Jffs2ReaderBuilder::from_bytes(&image[..])
+            .max_file_size(50)
+            .build()
+            .unwrap();
+        reader.scan().unwrap();
+        assert!(reader.read_file("a").is_err());
 
-                file.write_all(&decomp)?;
-            } else if inode.compr == JFFS2_COMPR_DYNRUBIN {
-                // this is slow but it works
-                let mut decomp: Vec<u8> = Vec::new();
-                decomp.resize(inode.dsize as usize, 0);
-                let input = &self.buffer[inode.data as usize..(inode.data + inode.csize) as usize];
+        let mut reader = Jffs2ReaderBuilder::from_bytes(&image[..])
+            .max_total_size(100)
+            .build()
+            .unwrap();
+        reader.scan().unwrap();
+        assert_eq!(reader.read_file("a").unwrap(), [b'a'; 60]);
+        let output = std::env::temp_dir().join(format!("jffs2-rs-limits-{}", std::process::id()));
+        assert!(reader.dump(&output).is_err());
+        assert!(!output.exists());
+    }
 
-                unsafe {
-                    dynrubin_decompress(
-                        input.as_ptr() as *const u8,
-                        decomp.as_mut_ptr() as *mut u8,
-                        input.len() as c_uint,
-                        inode.dsize as u32,
-                    );
-                }
+    #[test]
+    fn test_sparse_file() {
+        let mut tail = InodeNode::file(2, 2, 8192, b"tail");
+        // truncated past the last node
+        tail.isize = 8192 + 4 + 100;
+        let image = ImageBuilder::new()
+            .dirent(1, 2, 1, DT_REG, "sparse")
+            .inode(&InodeNode::file(2, 1, 0, b"head"))
+            .inode(&tail)
+            .build();
+        let mut reader = Jffs2Reader::from_bytes(&image[..]).unwrap();
+        reader.scan().unwrap();
 
-                file.write_all(&decomp)?;
-            } else if inode.compr == JFFS2_COMPR_RUBINMIPS {
-                bail!("JFFS2_COMPR_RUBINMIPS is deprecated!!");
-            } else if inode.compr == JFFS2_COMPR_COPY {
-                bail!("JFFS2_COMPR_COPY is never implemented!");
-            } else {
-                bail!("unknown compression type");
-            }
-        }
+        let mut expected = vec![0; 8192 + 4 + 100];
+        expected[..4].copy_from_slice(b"head");
+        expected[8192..8196].copy_from_slice(b"tail");
+        assert_eq!(reader.read_file("sparse").unwrap(), expected);
 
-        Ok(())
+        let mut file = reader.open_file("sparse").unwrap();
+        assert_eq!(file.len(), expected.len() as u64);
+        let mut data = vec![];
+        file.read_to_end(&mut data).unwrap();
+        assert_eq!(data, expected);
+
+        let output = std::env::temp_dir().join(format!("jffs2-rs-sparse-{}", std::process::id()));
+        let report = reader.dump_with_report(&output, &DumpOptions::new()).unwrap();
+        assert_eq!(report.bytes(), expected.len() as u64);
+        assert_eq!(std::fs::read(output.join("sparse")).unwrap(), expected);
+        std::fs::remove_dir_all(&output).unwrap();
+
+        reader.dump_with_options(&output, &DumpOptions::new().fill_holes(true)).unwrap();
+        assert_eq!(std::fs::read(output.join("sparse")).unwrap(), expected);
+
+        let mut data = vec![];
+        reader.write_file_to("sparse", &mut data).unwrap();
+        assert_eq!(data, expected);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            // every byte is allocated
+            let blocks = std::fs::metadata(output.join("sparse")).unwrap().blocks();
+            assert!(blocks * 512 >= expected.len() as u64);
+        }
+        std::fs::remove_dir_all(output).unwrap();
     }
 
-    fn resolve_dirent(&self, node: u32) -> Result<(PathBuf, u8)> {
-        let mut path = PathBuf::new();
-        let (ntype, mut cnode) = match self.dirents.get(&node) {
-            Some(dirent) => (dirent.ntype, dirent.clone()),
-            _ => bail!("no dirent for node {}", node),
-        };
-
-        for _i in 0..32 {
-            if cnode.pino == 1 {
-                let fname = cnode.fname;
-                let name_path = Path::new(&fname);
-                let mut output_path = name_path.join(path);
-                output_path = output_path.lexiclean().jffs_fix();
-                return Ok((output_path, ntype));
-            } else {
-                let name_path = Path::new(&cnode.fname);
-                path = name_path.join(path);
-                cnode = match self.dirents.get(&cnode.pino) {
-                    Some(dirent) => dirent.clone(),
-                    _ => bail!("cannot find parent node {}", cnode.pino),
-                };
-            }
+    #[test]
+    fn test_dump_large_nodes() {
+        let data: Vec<u8> = (0..1 << 20).map(|i: u32| ((i % 251) ^ (i >> 12)) as u8).collect();
+        let mut encoder = flate2::write::ZlibEncoder::new(vec![], flate2::Compression::fast());
+        encoder.write_all(&data).unwrap();
+        let mut zlib = InodeNode::file(2, 1, 0, &encoder.finish().unwrap());
+        zlib.compr = Compression::Zlib as u8;
+        let mut lzma = InodeNode::file(3, 1, 0, &lzma_compress(&data));
+        lzma.compr = Compression::Lzma as u8;
+        for inode in [&mut zlib, &mut lzma] {
+            inode.dsize = data.len() as u32;
+            inode.isize = data.len() as u32;
         }
+        let image = ImageBuilder::new()
+            .dirent(1, 2, 1, DT_REG, "zlib")
+            .inode(&zlib)
+            .dirent(1, 3, 1, DT_REG, "lzma")
+            .inode(&lzma)
+            .build();
+        let lzma_options = LzmaOptions::new().lc(3).pb(2).dict_size(0x80_0000);
+        let mut reader = Jffs2ReaderBuilder::from_bytes(&image[..])
+            .lzma_options(lzma_options)
+            .build()
+            .unwrap();
+        reader.scan().unwrap();
 
-        bail!("cannot resolve dirent {}", node);
+        let output = std::env::temp_dir().join(format!("jffs2-rs-large-{}", std::process::id()));
+        let report = reader.dump_with_report(&output, &DumpOptions::new()).unwrap();
+        assert_eq!(report.bytes(), 2 * data.len() as u64);
+        assert!(std::fs::read(output.join("zlib")).unwrap() == data);
+        assert!(std::fs::read(output.join("lzma")).unwrap() == data);
+        std::fs::remove_dir_all(output).unwrap();
     }
 
-    pub fn dump(&self, target_path: impl AsRef<Path>) -> Result<()> {
-        for i in self.dirents.keys() {
-            let (output_path, ntype) = self.resolve_dirent(*i)?;
-            if ntype == DT_DIR {
-                std::fs::create_dir_all(target_path.as_ref().join(output_path))?;
-            } else if ntype == DT_REG {
-                self.dump_file(&target_path.as_ref().join(output_path), *i)?;
-            }
+    #[test]
+    fn test_node_data_size() {
+        // nodes whose data comes out longer than their data size
+        let mut encoder = flate2::write::ZlibEncoder::new(vec![], flate2::Compression::fast());
+        encoder.write_all(&[b'z'; 4096]).unwrap();
+        let mut zlib = InodeNode::file(2, 1, 0, &encoder.finish().unwrap());
+        zlib.compr = Compression::Zlib as u8;
+        zlib.dsize = 4;
+        let mut stored = InodeNode::file(2, 2, 4, b"sssssss");
+        stored.dsize = 4;
+        // and shorter
+        let mut short = InodeNode::file(2, 3, 8, b"ab");
+        short.dsize = 4;
+        let mut tail = InodeNode::file(2, 4, 12, b"tail");
+        tail.isize = 16;
+        let image = ImageBuilder::new()
+            .dirent(1, 2, 1, DT_REG, "log")
+            .inode(&zlib)
+            .inode(&stored)
+            .inode(&short)
+            .inode(&tail)
+            .build();
+        let mut reader = Jffs2Reader::from_bytes(&image[..]).unwrap();
+        reader.scan().unwrap();
+        assert_eq!(reader.read_file("log").unwrap(), b"zzzzssssab\0\0tail");
+        let mut out = vec![];
+        reader.write_file_to("log", &mut out).unwrap();
+        assert_eq!(out, b"zzzzssssab\0\0tail");
+
+        let output = std::env::temp_dir().join(format!("jffs2-rs-dsize-{}", std::process::id()));
+        let report = reader.dump_with_report(&output, &DumpOptions::new()).unwrap();
+        assert_eq!(report.bytes(), 16);
+        assert_eq!(std::fs::read(output.join("log")).unwrap(), b"zzzzssssab\0\0tail");
+        std::fs::remove_dir_all(output).unwrap();
+    }
+
+    #[test]
+    fn test_symlinks() {
+        let image = ImageBuilder::new()
+            .dir(1, 2, "bin")
+            .file(2, 3, "busybox", b"ELF")
+            .symlink(2, 4, "sh", "busybox")
+            .symlink(1, 5, "init", "/bin/busybox")
+            .build();
+        let path = write_temp_image("symlinks", &image);
+        let reader = Jffs2Reader::open(&path).expect("Failed to open image");
+
+        let entries = reader.entries().unwrap();
+        let sh = entries
+            .iter()
+            .find(|entry| entry.path() == Path::new("bin/sh"))
+            .expect("Symbolic link not listed");
+        assert!(sh.is_symlink());
+        assert!(!sh.is_file());
+        assert_eq!(sh.symlink_target(), Some(Path::new("busybox")));
+        assert_eq!(sh.link_target(), Some(PathBuf::from("busybox")));
+        let others = entries.iter().filter(|entry| !entry.is_symlink());
+        assert!(others.clone().count() > 0);
+        assert!(others.clone().all(|entry| entry.link_target().is_none()));
+        assert_eq!(sh.size(), 7);
+
+        let output = path.with_extension("out");
+        reader.dump(&output).expect("Failed to extract");
+        #[cfg(unix)]
+        {
+            let link = std::fs::read_link(output.join("init")).unwrap();
+            assert_eq!(link, Path::new("/bin/busybox"));
+            assert_eq!(std::fs::read(output.join("bin/sh")).unwrap(), b"ELF");
         }
 
-        Ok(())
+        // extracting again replaces the links
+        reader.dump(&output).expect("Failed to extract twice");
+        std::fs::remove_dir_all(output).unwrap();
     }
 
-    pub fn entries(&self) -> Result<Vec<Jffs2Entry>> {
-        let mut jffs2_entries = vec![];
-        for i in self.dirents.keys() {
-            let (output_path, ntype) = self.resolve_dirent(*i)?;
-            if ntype == DT_DIR {
-                let entry = Jffs2Entry {
-                    inodes: vec![],
-                    is_file: false,
-                    path: output_path.clone(),
-                };
-                jffs2_entries.push(entry);
-            } else if ntype == DT_REG {
-                let inodes = match self.inodes.get(i) {
-                    Some(sorted_inodes) => sorted_inodes.to_owned(),
-                    _ => vec![],
-                };
+    #[test]
+    fn test_symlink_fallback() {
+        let image = ImageBuilder::new()
+            .dir(1, 2, "bin")
+            .file(2, 3, "busybox", b"ELF")
+            .symlink(2, 4, "sh", "busybox")
+            .symlink(1, 5, "rc", "/etc/rc")
+            .build();
+        let mut reader = Jffs2Reader::from_bytes(&image[..]).unwrap();
+        reader.scan().unwrap();
 
-                let entry = Jffs2Entry {
-                    inodes,
-                    is_file: true,
-                    path: output_path.clone(),
-                };
-                jffs2_entries.push(entry);
-            }
+        let output = std::env::temp_dir().join(format!("jffs2-rs-fallback-{}", std::process::id()));
+        std::fs::create_dir_all(&output).unwrap();
+        let fallback = |name: &str, node, target: &str, fallback| {
+            let error = std::io::Error::from(std::io::ErrorKind::Unsupported);
+            let output_path = output.join(name);
+            reader.symlink_fallback(&output_path, node, Path::new(target), fallback, error)
+        };
+        assert_eq!(fallback("sh", 4, "busybox", SymlinkFallback::Placeholder).unwrap(), None);
+        assert_eq!(std::fs::read(output.join("sh")).unwrap(), b"busybox");
+        assert_eq!(fallback("sh", 4, "busybox", SymlinkFallback::CopyTarget).unwrap(), None);
+        assert_eq!(std::fs::read(output.join("sh")).unwrap(), b"ELF");
+        assert!(fallback("rc", 5, "/etc/rc", SymlinkFallback::CopyTarget).unwrap().is_some());
+        assert!(fallback("rc", 5, "/etc/rc", SymlinkFallback::Skip).unwrap().is_some());
+        assert!(!output.join("rc").exists());
+        std::fs::remove_dir_all(output).unwrap();
+    }
+
+    #[test]
+    fn test_non_utf8_names() {
+        let image = ImageBuilder::new()
+            .dir(1, 2, b"caf\xe9")
+            .file(2, 3, b"men\xfc.txt", b"latin-1\n")
+            .file(1, 4, "motd", b"hi\n")
+            .build();
+        let mut reader = Jffs2Reader::from_bytes(&image[..]).unwrap();
+        reader.scan().unwrap();
+
+        let mut names: Vec<_> = reader
+            .entries()
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.path().to_string_lossy().trim_end_matches('/').to_string())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["caf\u{fffd}", "caf\u{fffd}/men\u{fffd}.txt", "motd"]);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+
+            let path = Path::new(OsStr::from_bytes(b"caf\xe9/men\xfc.txt"));
+            assert_eq!(reader.read_file(path).unwrap(), b"latin-1\n");
+            let output =
+                std::env::temp_dir().join(format!("jffs2-rs-names-{}", std::process::id()));
+            reader.dump(&output).unwrap();
+            assert_eq!(std::fs::read(output.join(path)).unwrap(), b"latin-1\n");
+            std::fs::remove_dir_all(output).unwrap();
         }
+    }
 
-        Ok(jffs2_entries)
+    #[test]
+    fn test_path_traversal() {
+        let image = ImageBuilder::new()
+            .file(1, 2, "../../etc/passwd", b"root::0:0\n")
+            .file(1, 3, "/etc/shadow", b"root::0\n")
+            .file(1, 4, "..", b"up\n")
+            .file(1, 5, "motd", b"hi\n")
+            .build();
+        let mut reader = Jffs2Reader::from_bytes(&image[..]).unwrap();
+        let err = reader.scan().unwrap_err();
+        assert!(err.to_string().contains("not a valid file name"), "{}", err);
+
+        let mut reader = Jffs2ReaderBuilder::from_bytes(&image[..]).lenient(true).build().unwrap();
+        reader.scan().unwrap();
+        assert_eq!(reader.skipped_nodes().len(), 3);
+
+        let sandbox =
+            std::env::temp_dir().join(format!("jffs2-rs-traversal-{}", std::process::id()));
+        let output = sandbox.join("a/b/out");
+        reader.dump(&output).unwrap();
+        assert_eq!(std::fs::read(output.join("motd")).unwrap(), b"hi\n");
+        assert!(!sandbox.join("a/etc/passwd").exists());
+        assert_eq!(std::fs::read_dir(&output).unwrap().count(), 1);
+        assert_eq!(std::fs::read_dir(sandbox.join("a")).unwrap().count(), 1);
+        std::fs::remove_dir_all(sandbox).unwrap();
     }
-}
 
-/// extract the data from a jffs2 file
-/// input : the jffs2 file
-/// output : the output path
-pub fn extract_jffs2(input: impl AsRef<Path>, output: impl AsRef<Path>) -> Result<()> {
-    let mut reader = Jffs2Reader::new(input)?;
-    reader.scan()?;
-    reader.dump(output)
-}
+    #[test]
+    fn test_max_depth() {
+        let mut builder = ImageBuilder::new();
+        for ino in 2..42 {
+            builder.dir(ino - 1, ino, format!("d{}", ino));
+        }
+        let image = builder.file(41, 42, "deep", b"down\n").build();
+        let mut reader = Jffs2Reader::from_bytes(&image[..]).unwrap();
+        reader.scan().unwrap();
+        let deep = reader.entries().unwrap().into_iter().find(|e| e.ino() == 42).unwrap();
+        assert_eq!(deep.path().components().count(), 41);
 
-/// List all entries within the jffs2 image
-pub fn list_jffs2(input: impl AsRef<Path>) -> Result<Vec<Jffs2Entry>> {
-    let mut reader = Jffs2Reader::new(input)?;
-    reader.scan()?;
-    reader.entries()
-}
+        let mut reader = Jffs2ReaderBuilder::from_bytes(&image[..]).max_depth(10).build().unwrap();
+        reader.scan().unwrap();
+        let err = reader.entries().unwrap_err();
+        assert!(err.to_string().contains("more than 10 directories deep"), "{}", err);
 
-#[cfg(test)]
-mod test {
-    use super::*;
+        let image = ImageBuilder::new()
+            .dirent(2, 2, 1, DT_DIR, "loop")
+            .file(2, 3, "lost", b"?\n")
+            .build();
+        let mut reader = Jffs2Reader::from_bytes(&image[..]).unwrap();
+        reader.scan().unwrap();
+        let err = reader.entries().unwrap_err();
+        assert!(err.to_string().contains("cycle"), "{}", err);
+    }
 
+    #[cfg(unix)]
     #[test]
-    fn test_extract_jffs2() {
-        let input = Path::new("test/test.jffs2");
-        let mut reader = Jffs2Reader::new(input).expect("Failed to open file");
-        reader.scan().expect("Failed to scan");
+    fn test_dump_through_links() {
+        let sandbox = std::env::temp_dir().join(format!("jffs2-rs-escape-{}", std::process::id()));
+        let outside = sandbox.join("outside");
+        let output = sandbox.join("out");
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::create_dir_all(&output).unwrap();
+
+        // a link left in the target directory
+        std::os::unix::fs::symlink(&outside, output.join("etc")).unwrap();
+        let image = ImageBuilder::new()
+            .dir(1, 2, "etc")
+            .file(2, 3, "passwd", b"root::0:0\n")
+            .file(1, 4, "motd", b"hi\n")
+            .build();
+        let path = write_temp_image("escape", &image);
+        let reader = Jffs2Reader::open(&path).unwrap();
+        let err = reader.dump(&output).unwrap_err();
+        assert!(err.to_string().contains("leads outside"), "{}", err);
+        let opts = DumpOptions::new().best_effort(true);
+        let report = reader.dump_with_report(&output, &opts).unwrap();
+        assert_eq!(report.failures().len(), 2);
+        assert_eq!(std::fs::read(output.join("motd")).unwrap(), b"hi\n");
+        assert_eq!(std::fs::read_dir(&outside).unwrap().count(), 0);
+        std::fs::remove_dir_all(&output).unwrap();
+
+        // a link of the image with another one under it
+        let image = ImageBuilder::new()
+            .symlink(1, 2, "tmp", outside.to_str().unwrap())
+            .symlink(2, 3, "payload", "x")
+            .build();
+        let path = write_temp_image("escape-links", &image);
+        let reader = Jffs2Reader::open(&path).unwrap();
+        reader.dump_with_report(&output, &opts).unwrap();
+        assert_eq!(std::fs::read_dir(&outside).unwrap().count(), 0);
+        std::fs::remove_dir_all(sandbox).unwrap();
+    }
+
+    #[test]
+    fn test_overwrite_policy() {
+        let image = ImageBuilder::new()
+            .dir(1, 2, "etc")
+            .file(2, 3, "passwd", b"root:x:0:0\n")
+            .file(1, 4, "motd", b"hi\n")
+            .build();
+        let mut reader = Jffs2Reader::from_bytes(&image[..]).unwrap();
+        reader.scan().unwrap();
+
+        let output =
+            std::env::temp_dir().join(format!("jffs2-rs-overwrite-{}", std::process::id()));
+        std::fs::create_dir_all(output.join("etc")).unwrap();
+        std::fs::write(output.join("etc/passwd"), b"mine\n").unwrap();
+
+        let opts = DumpOptions::new().overwrite(OverwritePolicy::Error);
+        let err = reader.dump_with_report(&output, &opts).unwrap_err();
+        let err = err.downcast::<std::io::Error>().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+        assert_eq!(std::fs::read(output.join("etc/passwd")).unwrap(), b"mine\n");
+
+        let opts = DumpOptions::new().overwrite(OverwritePolicy::Skip);
+        let report = reader.dump_with_report(&output, &opts).unwrap();
+        assert_eq!(report.files(), 1);
+        assert_eq!(report.skipped().len(), 1);
+        assert_eq!(std::fs::read(output.join("etc/passwd")).unwrap(), b"mine\n");
+        assert_eq!(std::fs::read(output.join("motd")).unwrap(), b"hi\n");
+
+        reader.dump(&output).unwrap();
+        assert_eq!(std::fs::read(output.join("etc/passwd")).unwrap(), b"root:x:0:0\n");
+
+        let opts = DumpOptions::new().empty_target(true);
+        let err = reader.dump_with_report(&output, &opts).unwrap_err();
+        let err = err.downcast::<std::io::Error>().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+        std::fs::remove_dir_all(&output).unwrap();
+        std::fs::create_dir(&output).unwrap();
+        assert_eq!(reader.dump_with_report(&output, &opts).unwrap().files(), 2);
+        std::fs::remove_dir_all(&output).unwrap();
+        assert_eq!(reader.dump_with_report(&output, &opts).unwrap().files(), 2);
+        std::fs::remove_dir_all(output).unwrap();
+    }
+
+    #[test]
+    fn test_special_files() {
+        let image = ImageBuilder::new()
+            .dir(1, 2, "dev")
+            .special(2, 3, "null", DT_CHR, S_IFCHR | 0o666, &0x103u32.to_le_bytes())
+            .special(2, 4, "mtdblock0", DT_BLK, S_IFBLK | 0o600, &0x1f00u16.to_le_bytes())
+            .special(2, 5, "initctl", DT_FIFO, S_IFIFO | 0o600, &[])
+            .special(2, 6, "log", DT_SOCK, S_IFSOCK | 0o666, &[])
+            .file(1, 7, "motd", b"hi\n")
+            .build();
+        let path = write_temp_image("special", &image);
+        let reader = Jffs2Reader::open(&path).unwrap();
+        assert_eq!(reader.device_number(3).unwrap(), (1, 3));
+        assert_eq!(reader.device_number(4).unwrap(), (31, 0));
+        assert_eq!(reader.latest_inode(5).unwrap().mode(), S_IFIFO | 0o600);
+
+        let kinds: HashMap<_, _> = reader
+            .entries()
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.path().clone(), entry.kind()))
+            .collect();
+        assert_eq!(kinds[Path::new("dev")], FileKind::Dir);
+        assert_eq!(kinds[Path::new("dev/null")], FileKind::CharDev);
+        assert_eq!(kinds[Path::new("dev/mtdblock0")], FileKind::BlockDev);
+        assert_eq!(kinds[Path::new("dev/initctl")], FileKind::Fifo);
+        assert_eq!(kinds[Path::new("dev/log")], FileKind::Socket);
+        assert_eq!(kinds[Path::new("motd")], FileKind::Regular);
+        let devices: HashMap<_, _> = reader
+            .entries()
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.path().clone(), entry.device()))
+            .collect();
+        assert_eq!(devices[Path::new("dev/mtdblock0")], Some((31, 0)));
+        assert_eq!(devices[Path::new("dev/initctl")], None);
+
+        let output = std::env::temp_dir().join(format!("jffs2-rs-special-{}", std::process::id()));
+        let opts = DumpOptions::new().special_files(SpecialFilePolicy::Record);
+        let report = reader.dump_with_report(&output, &opts).unwrap();
+        let mut recorded = report.recorded().to_vec();
+        recorded.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            recorded,
+            [
+                (output.join("dev/initctl"), FileKind::Fifo, None),
+                (output.join("dev/log"), FileKind::Socket, None),
+                (output.join("dev/mtdblock0"), FileKind::BlockDev, Some((31, 0))),
+                (output.join("dev/null"), FileKind::CharDev, Some((1, 3))),
+            ]
+        );
+        assert!(!output.join("dev/initctl").exists());
+        let opts = DumpOptions::new().special_files(SpecialFilePolicy::Skip);
+        let report = reader.dump_with_report(&output, &opts).unwrap();
+        assert!(report.recorded().is_empty() && report.skipped().is_empty());
+        std::fs::remove_dir_all(&output).unwrap();
+
+        reader.dump(&output).expect("Failed to extract");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{FileTypeExt, MetadataExt};
+
+            let fifo = std::fs::symlink_metadata(output.join("dev/initctl")).unwrap();
+            assert!(fifo.file_type().is_fifo());
+            // device nodes are only there when running with the privilege
+            if let Ok(null) = std::fs::symlink_metadata(output.join("dev/null")) {
+                assert!(null.file_type().is_char_device());
+                assert_eq!(null.rdev(), nix::libc::makedev(1, 3));
+            }
+        }
+        assert_eq!(std::fs::read(output.join("motd")).unwrap(), b"hi\n");
+        std::fs::remove_dir_all(output).unwrap();
     }
 }
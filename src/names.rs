@@ -0,0 +1,177 @@
+// Output names for filesystems that refuse some of the names JFFS2 allows,
+// Windows ones first of all, or that ignore case. The characters they refuse
+// are escaped as %XX, and the names that end up the same, ignoring case, get
+// a ~N suffix.
+
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Device names Windows reserves, with any extension
+const RESERVED: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// `name` with the control characters and `<>:"/\|?*` escaped, as well as a
+/// trailing dot or space and the first letter of a reserved device name
+pub(crate) fn sanitize_name(name: &OsStr) -> OsString {
+    let bytes = name_bytes(name);
+    let stem = bytes.split(|b| *b == b'.').next().unwrap_or_default();
+    let reserved = RESERVED.iter().any(|device| device.as_bytes().eq_ignore_ascii_case(stem));
+    let mut sanitized = Vec::with_capacity(bytes.len());
+    for (i, &b) in bytes.iter().enumerate() {
+        let escape = b < 0x20
+            || b"<>:\"/\\|?*".contains(&b)
+            || (i == 0 && reserved)
+            || (i == bytes.len() - 1 && (b == b'.' || b == b' '));
+        if escape {
+            write!(sanitized, "%{:02X}", b).unwrap();
+        } else {
+            sanitized.push(b);
+        }
+    }
+    crate::os_string(sanitized)
+}
+
+/// Output path of each of `paths` and of their parent directories, keyed by
+/// the path normalized through its components, with the names escaped by
+/// [`sanitize_name`] if `escape` is set. Of the names that collide, the
+/// unchanged ones come first and then the others in byte order.
+pub(crate) fn rename_paths<'p>(
+    paths: impl IntoIterator<Item = &'p Path>,
+    escape: bool,
+) -> HashMap<PathBuf, PathBuf> {
+    let mut sanitized = HashMap::new();
+    sanitized.insert(PathBuf::new(), PathBuf::new());
+    // a parent comes before its children in path order
+    for (parent, names) in children(paths) {
+        let output_parent = sanitized[&parent].clone();
+        let mut names: Vec<_> = names
+            .into_iter()
+            .map(|name| {
+                let output = if escape { sanitize_name(&name) } else { name.clone() };
+                (output != name, name, output)
+            })
+            .collect();
+        names.sort();
+        let mut taken = HashSet::new();
+        for (_, name, output) in names {
+            let mut candidate = output.clone();
+            let mut n = 1;
+            while !taken.insert(candidate.to_string_lossy().to_lowercase()) {
+                candidate = output.clone();
+                candidate.push(format!("~{}", n));
+                n += 1;
+            }
+            sanitized.insert(parent.join(&name), output_parent.join(candidate));
+        }
+    }
+    sanitized
+}
+
+/// Pairs of paths among `paths` and their parent directories that differ
+/// only in case, the one coming first in byte order and one of the others
+pub(crate) fn case_collisions<'p>(
+    paths: impl IntoIterator<Item = &'p Path>,
+) -> Vec<(PathBuf, PathBuf)> {
+    let mut collisions = vec![];
+    for (parent, names) in children(paths) {
+        let mut first: HashMap<String, &OsString> = HashMap::new();
+        for name in &names {
+            match first.entry(name.to_string_lossy().to_lowercase()) {
+                Entry::Occupied(kept) => {
+                    collisions.push((parent.join(kept.get()), parent.join(name)));
+                }
+                Entry::Vacant(slot) => {
+                    slot.insert(name);
+                }
+            }
+        }
+    }
+    collisions
+}
+
+/// Names in each directory of `paths`, parents first
+fn children<'p>(
+    paths: impl IntoIterator<Item = &'p Path>,
+) -> BTreeMap<PathBuf, BTreeSet<OsString>> {
+    let mut children: BTreeMap<PathBuf, BTreeSet<OsString>> = BTreeMap::new();
+    for path in paths {
+        let mut parent = PathBuf::new();
+        for component in path.components() {
+            let name = component.as_os_str();
+            children.entry(parent.clone()).or_default().insert(name.to_owned());
+            parent.push(name);
+        }
+    }
+    children
+}
+
+#[cfg(unix)]
+fn name_bytes(name: &OsStr) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    name.as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn name_bytes(name: &OsStr) -> Vec<u8> {
+    name.to_string_lossy().into_owned().into_bytes()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_name() {
+        let sanitize = |name: &str| sanitize_name(OsStr::new(name)).into_string().unwrap();
+        assert_eq!(sanitize("busybox"), "busybox");
+        assert_eq!(sanitize("100%"), "100%");
+        assert_eq!(sanitize("a:b*?"), "a%3Ab%2A%3F");
+        assert_eq!(sanitize("tab\there"), "tab%09here");
+        assert_eq!(sanitize("dots..."), "dots..%2E");
+        assert_eq!(sanitize("space "), "space%20");
+        assert_eq!(sanitize("con"), "%63on");
+        assert_eq!(sanitize("NUL.txt"), "%4EUL.txt");
+        assert_eq!(sanitize("console"), "console");
+        assert_eq!(sanitize("COM10"), "COM10");
+    }
+
+    #[test]
+    fn test_sanitize_paths() {
+        let paths = ["etc/a:b", "etc/a%3Ab", "etc/A%3AB", "etc/readme", "etc/README", "aux/x?"];
+        let sanitized = rename_paths(paths.iter().map(Path::new), true);
+        let output = |path: &str| sanitized[Path::new(path)].to_str().unwrap().to_string();
+        assert_eq!(output("etc"), "etc");
+        assert_eq!(output("etc/A%3AB"), "etc/A%3AB");
+        assert_eq!(output("etc/a%3Ab"), "etc/a%3Ab~1");
+        assert_eq!(output("etc/a:b"), "etc/a%3Ab~2");
+        assert_eq!(output("etc/README"), "etc/README");
+        assert_eq!(output("etc/readme"), "etc/readme~1");
+        assert_eq!(output("aux"), "%61ux");
+        assert_eq!(output("aux/x?"), "%61ux/x%3F");
+
+        let renamed = rename_paths(paths.iter().map(Path::new), false);
+        assert_eq!(renamed[Path::new("aux/x?")], Path::new("aux/x?"));
+        assert_eq!(renamed[Path::new("etc/a:b")], Path::new("etc/a:b"));
+        assert_eq!(renamed[Path::new("etc/a%3Ab")], Path::new("etc/a%3Ab~1"));
+    }
+
+    #[test]
+    fn test_case_collisions() {
+        let paths = ["Etc/passwd", "etc/passwd", "etc/Passwd", "etc/PASSWD", "bin/sh"];
+        let collisions = case_collisions(paths.iter().map(Path::new));
+        let pair = |kept: &str, other: &str| (PathBuf::from(kept), PathBuf::from(other));
+        assert_eq!(
+            collisions,
+            [
+                pair("Etc", "etc"),
+                pair("etc/PASSWD", "etc/Passwd"),
+                pair("etc/PASSWD", "etc/passwd"),
+            ]
+        );
+    }
+}
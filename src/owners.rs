@@ -0,0 +1,163 @@
+//! Ownership manifests.
+//!
+//! Extracting with the owners of the image needs the privilege to chown. A
+//! manifest records the uid and gid of every entry instead, one
+//! `uid:gid<TAB>path` line per entry with the path relative to the
+//! extraction directory, so they can be applied later, as root, with
+//! [`apply_owner_manifest`].
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+
+use crate::Jffs2Reader;
+
+impl Jffs2Reader<'_> {
+    /// Write the owner and group of every entry to `out` as a manifest.
+    /// Entries with a line break in their path are left out with a warning.
+    pub fn write_owner_manifest(&self, mut out: impl Write) -> Result<()> {
+        for entry in self.entries()? {
+            // without the trailing slash some entry paths have
+            let path: PathBuf = entry.path().components().collect();
+            let path = path_bytes(&path);
+            if path.contains(&b'\n') {
+                log::warn!("{}: line break in the path, left out", entry.path().display());
+                continue;
+            }
+            write!(out, "{}:{}\t", entry.uid(), entry.gid())?;
+            out.write_all(&path)?;
+            out.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+}
+
+/// Give the entries extracted under `target` the owners listed in
+/// `manifest`, returns how many were changed. Entries that are not there are
+/// skipped, and without the privilege to chown nothing is changed and a
+/// warning is logged.
+pub fn apply_owner_manifest(manifest: impl Read, target: impl AsRef<Path>) -> Result<usize> {
+    let target = target.as_ref();
+    let mut changed = 0;
+    for line in BufReader::new(manifest).split(b'\n') {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        let (owner, path) = parse_line(&line)?;
+        let path = target.join(path);
+        if path.symlink_metadata().is_err() {
+            continue;
+        }
+        if !chown(&path, owner)? {
+            log::warn!("no permission to change file owners, manifest not applied");
+            break;
+        }
+        changed += 1;
+    }
+    Ok(changed)
+}
+
+/// Owner, group and path of a manifest line
+fn parse_line(line: &[u8]) -> Result<((u32, u32), PathBuf)> {
+    let malformed = || anyhow!("malformed manifest line {:?}", String::from_utf8_lossy(line));
+    let tab = line.iter().position(|b| *b == b'\t').ok_or_else(malformed)?;
+    let owner = std::str::from_utf8(&line[..tab]).map_err(|_| malformed())?;
+    let (uid, gid) = owner.split_once(':').ok_or_else(malformed)?;
+    let uid = uid.parse().map_err(|_| malformed())?;
+    let gid = gid.parse().map_err(|_| malformed())?;
+    Ok(((uid, gid), path_from_bytes(&line[tab + 1..])))
+}
+
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().into_owned().into_bytes()
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// Change the owner of `path` without following a link, returns false when
+/// the process lacks the privilege
+#[cfg(unix)]
+fn chown(path: &Path, (uid, gid): (u32, u32)) -> Result<bool> {
+    use nix::errno::Errno;
+    use nix::fcntl::AtFlags;
+    use nix::unistd::{fchownat, Gid, Uid};
+
+    let (owner, group) = (Uid::from_raw(uid), Gid::from_raw(gid));
+    match fchownat(None, path, Some(owner), Some(group), AtFlags::AT_SYMLINK_NOFOLLOW) {
+        Ok(()) => Ok(true),
+        Err(Errno::EPERM) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(not(unix))]
+fn chown(path: &Path, _owner: (u32, u32)) -> Result<bool> {
+    anyhow::bail!("{}: file owners can only be set on unix", path.display())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testutil::*;
+    use crate::DT_REG;
+
+    #[test]
+    fn test_owner_manifest() {
+        let mut shadow = InodeNode::file(3, 1, 0, b"root:*:0\n");
+        shadow.uid = 0;
+        shadow.gid = 42;
+        let mut home = InodeNode::file(4, 1, 0, b"hi\n");
+        home.uid = 1000;
+        home.gid = 100;
+        let image = ImageBuilder::new()
+            .dir(1, 2, "etc")
+            .dirent(2, 3, 1, DT_REG, "shadow")
+            .inode(&shadow)
+            .dirent(1, 4, 1, DT_REG, "motd")
+            .inode(&home)
+            .build();
+        let mut reader = Jffs2Reader::from_bytes(&image[..]).unwrap();
+        reader.scan().unwrap();
+
+        let mut manifest = vec![];
+        reader.write_owner_manifest(&mut manifest).unwrap();
+        let mut lines: Vec<_> = std::str::from_utf8(&manifest).unwrap().lines().collect();
+        lines.sort();
+        assert_eq!(lines, ["0:0\tetc", "0:42\tetc/shadow", "1000:100\tmotd"]);
+        assert!(apply_owner_manifest(&b"0:0 etc\n"[..], "/nonexistent").is_err());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+
+            let output =
+                std::env::temp_dir().join(format!("jffs2-rs-manifest-{}", std::process::id()));
+            reader.dump(&output).unwrap();
+            let changed = apply_owner_manifest(&manifest[..], &output).unwrap();
+            if nix::unistd::geteuid().is_root() {
+                assert_eq!(changed, 3);
+                let metadata = std::fs::metadata(output.join("motd")).unwrap();
+                assert_eq!((metadata.uid(), metadata.gid()), (1000, 100));
+            }
+            std::fs::remove_dir_all(output).unwrap();
+        }
+    }
+}
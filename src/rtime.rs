@@ -0,0 +1,130 @@
+//! Decoder for JFFS2_COMPR_RTIME, the "real time" compressor of jffs2.
+//!
+//! The stream is a sequence of `(value, repeat)` byte pairs: `value` is
+//! emitted, then `repeat` bytes are copied from just after the previous
+//! occurrence of `value` in the output. The copy may overlap the bytes it
+//! produces.
+
+use anyhow::{bail, Result};
+
+/// Decompress `input`, which expands to `dstlen` bytes. Like the kernel,
+/// the last run may carry the output a little past `dstlen`.
+pub fn decompress(input: &[u8], dstlen: usize) -> Result<Vec<u8>> {
+    let mut dst = Vec::new();
+    decompress_into(input, dstlen, &mut dst)?;
+    Ok(dst)
+}
+
+/// Like [`decompress`], into `dst` which is cleared first so its
+/// allocation can be reused
+pub fn decompress_into(input: &[u8], dstlen: usize, dst: &mut Vec<u8>) -> Result<()> {
+    dst.clear();
+    dst.reserve(dstlen);
+    let mut positions = [0usize; 256];
+    let mut pos = 0;
+
+    while dst.len() < dstlen {
+        let (value, repeat) = match input.get(pos..pos + 2) {
+            Some(pair) => (pair[0], pair[1] as usize),
+            None => bail!("rtime stream ends after {} of {} bytes", dst.len(), dstlen),
+        };
+        pos += 2;
+
+        dst.push(value);
+        let mut backoffs = positions[value as usize];
+        positions[value as usize] = dst.len();
+
+        // an overlapping run reads bytes it is still producing, copy what
+        // exists so far and go around again
+        let mut remaining = repeat;
+        while remaining > 0 {
+            let len = remaining.min(dst.len() - backoffs);
+            dst.extend_from_within(backoffs..backoffs + len);
+            backoffs += len;
+            remaining -= len;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testutil::*;
+    use crate::Jffs2Reader;
+
+    /// xorshift32, enough to vary the test inputs without a dependency
+    fn next(state: &mut u32) -> u32 {
+        *state ^= *state << 13;
+        *state ^= *state >> 17;
+        *state ^= *state << 5;
+        *state
+    }
+
+    #[test]
+    fn test_rtime_image_nodes() {
+        let texts: [&[u8]; 3] = [
+            b"#!/bin/sh\necho hello\necho hello\necho hello\n",
+            &[0u8; 4096],
+            b"abababababababababcabcabcabcabcabc",
+        ];
+        let mut builder = ImageBuilder::new();
+        for (i, text) in texts.iter().enumerate() {
+            let ino = i as u32 + 2;
+            builder.dirent(1, ino, 1, DT_REG, format!("file{}", ino));
+            let mut inode = InodeNode::file(ino, 1, 0, &rtime_compress(text));
+            inode.compr = crate::Compression::Rtime as u8;
+            inode.dsize = text.len() as u32;
+            inode.isize = text.len() as u32;
+            builder.inode(&inode);
+        }
+        let path = write_temp_image("rtime", &builder.build());
+        let mut reader = Jffs2Reader::new(&path).expect("Failed to open image");
+        reader.scan().expect("Failed to scan");
+
+        for (i, text) in texts.iter().enumerate() {
+            let inode = &reader.inodes[&(i as u32 + 2)][0];
+            let input = reader
+                .buffer
+                .read(inode.data as usize, inode.csize as usize)
+                .unwrap();
+            let output = decompress(&input, inode.dsize as usize).expect("Failed to decompress");
+            assert_eq!(
+                output,
+                rtime_decompress_reference(&input, inode.dsize as usize)
+            );
+            assert_eq!(&output, text);
+        }
+    }
+
+    #[test]
+    fn test_rtime_roundtrip() {
+        let mut state = 0x1985_e002;
+        for round in 0..200 {
+            // a small alphabet makes long and overlapping runs likely
+            let alphabet = 1 + round % 16;
+            let len = next(&mut state) as usize % 8192;
+            let data: Vec<u8> = (0..len)
+                .map(|_| (next(&mut state) % alphabet) as u8)
+                .collect();
+
+            let compressed = rtime_compress(&data);
+            let output = decompress(&compressed, data.len()).expect("Failed to decompress");
+            assert_eq!(output, data);
+            assert_eq!(output, rtime_decompress_reference(&compressed, data.len()));
+
+            // arbitrary pair streams decode too, compare the raw behavior
+            let stream: Vec<u8> = (0..len & !1).map(|_| next(&mut state) as u8).collect();
+            let expected = rtime_decompress_reference(&stream, len / 2);
+            assert_eq!(decompress(&stream, len / 2).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_rtime_truncated_input() {
+        let compressed = rtime_compress(b"hello hello hello");
+        assert!(decompress(&compressed[..compressed.len() - 3], 17).is_err());
+        assert!(decompress(&[], 1).is_err());
+    }
+}
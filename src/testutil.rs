@@ -0,0 +1,495 @@
+// Helpers to assemble small JFFS2 images in memory for the unit tests.
+
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::crc::jffs2_crc32;
+
+pub(crate) use crate::{DT_DIR, DT_LNK, DT_REG, S_IFDIR, S_IFLNK, S_IFREG};
+
+#[derive(Debug, Clone)]
+pub struct InodeNode {
+    pub ino: u32,
+    pub version: u32,
+    pub mode: u32,
+    pub uid: u16,
+    pub gid: u16,
+    pub isize: u32,
+    pub atime: u32,
+    pub mtime: u32,
+    pub ctime: u32,
+    pub offset: u32,
+    pub dsize: u32,
+    pub compr: u8,
+    pub data: Vec<u8>,
+}
+
+impl InodeNode {
+    /// An uncompressed regular file fragment
+    pub fn file(ino: u32, version: u32, offset: u32, data: &[u8]) -> Self {
+        InodeNode {
+            ino,
+            version,
+            mode: S_IFREG | 0o644,
+            uid: 0,
+            gid: 0,
+            isize: offset + data.len() as u32,
+            atime: 0,
+            mtime: 0,
+            ctime: 0,
+            offset,
+            dsize: data.len() as u32,
+            compr: 0,
+            data: data.to_vec(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ImageBuilder {
+    image: Vec<u8>,
+    little_endian: bool,
+    /// Start of the current erase block and the summary entries of its nodes
+    block_start: usize,
+    summary: Vec<Vec<u8>>,
+}
+
+impl ImageBuilder {
+    pub fn new() -> Self {
+        ImageBuilder {
+            image: Vec::new(),
+            little_endian: true,
+            block_start: 0,
+            summary: Vec::new(),
+        }
+    }
+
+    pub fn big_endian() -> Self {
+        ImageBuilder {
+            image: Vec::new(),
+            little_endian: false,
+            block_start: 0,
+            summary: Vec::new(),
+        }
+    }
+
+    fn u16(&self, v: u16) -> [u8; 2] {
+        if self.little_endian {
+            v.to_le_bytes()
+        } else {
+            v.to_be_bytes()
+        }
+    }
+
+    fn u32(&self, v: u32) -> [u8; 4] {
+        if self.little_endian {
+            v.to_le_bytes()
+        } else {
+            v.to_be_bytes()
+        }
+    }
+
+    /// Append raw bytes (padding, garbage) to the image
+    pub fn raw(&mut self, bytes: &[u8]) -> &mut Self {
+        self.image.extend_from_slice(bytes);
+        self
+    }
+
+    /// Append a node with a valid header CRC, `body` excludes the common header
+    pub fn node(&mut self, nodetype: u16, body: &[u8]) -> &mut Self {
+        let mut header = Vec::with_capacity(12);
+        header.extend(self.u16(0x1985));
+        header.extend(self.u16(nodetype));
+        header.extend(self.u32(12 + body.len() as u32));
+        let hdr_crc = jffs2_crc32(&header);
+        header.extend(self.u32(hdr_crc));
+
+        self.image.extend(header);
+        self.image.extend_from_slice(body);
+        self.image
+            .resize(self.image.len().next_multiple_of(4), 0xff);
+        self
+    }
+
+    pub fn dirent(
+        &mut self,
+        pino: u32,
+        ino: u32,
+        version: u32,
+        ntype: u8,
+        name: impl AsRef<[u8]>,
+    ) -> &mut Self {
+        let name = name.as_ref();
+        let mut node = Vec::new();
+        node.extend(self.u16(0x1985));
+        node.extend(self.u16(0xE001));
+        node.extend(self.u32(40 + name.len() as u32));
+        let hdr_crc = jffs2_crc32(&node);
+        node.extend(self.u32(hdr_crc));
+        node.extend(self.u32(pino));
+        node.extend(self.u32(version));
+        node.extend(self.u32(ino));
+        node.extend(self.u32(0));
+        node.push(name.len() as u8);
+        node.push(ntype);
+        node.extend([0, 0]);
+        let node_crc = jffs2_crc32(&node);
+        node.extend(self.u32(node_crc));
+        node.extend(self.u32(jffs2_crc32(name)));
+
+        let mut entry = Vec::new();
+        entry.extend(self.u16(0xE001));
+        entry.extend(self.u32(40 + name.len() as u32));
+        entry.extend(self.u32((self.image.len() - self.block_start) as u32));
+        entry.extend(self.u32(pino));
+        entry.extend(self.u32(version));
+        entry.extend(self.u32(ino));
+        entry.push(name.len() as u8);
+        entry.push(ntype);
+        entry.extend(name);
+        self.summary.push(entry);
+
+        self.node(
+            0xE001,
+            &node[12..]
+                .iter()
+                .chain(name)
+                .copied()
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    pub fn inode(&mut self, inode: &InodeNode) -> &mut Self {
+        let mut node = Vec::new();
+        node.extend(self.u16(0x1985));
+        node.extend(self.u16(0xE002));
+        node.extend(self.u32(68 + inode.data.len() as u32));
+        let hdr_crc = jffs2_crc32(&node);
+        node.extend(self.u32(hdr_crc));
+        node.extend(self.u32(inode.ino));
+        node.extend(self.u32(inode.version));
+        node.extend(self.u32(inode.mode));
+        node.extend(self.u16(inode.uid));
+        node.extend(self.u16(inode.gid));
+        node.extend(self.u32(inode.isize));
+        node.extend(self.u32(inode.atime));
+        node.extend(self.u32(inode.mtime));
+        node.extend(self.u32(inode.ctime));
+        node.extend(self.u32(inode.offset));
+        node.extend(self.u32(inode.data.len() as u32));
+        node.extend(self.u32(inode.dsize));
+        node.push(inode.compr);
+        node.push(0);
+        node.extend(self.u16(0));
+        node.extend(self.u32(jffs2_crc32(&inode.data)));
+        // the data CRC is left out, as the kernel does
+        let node_crc = jffs2_crc32(&node[..60]);
+        node.extend(self.u32(node_crc));
+
+        let mut entry = Vec::new();
+        entry.extend(self.u16(0xE002));
+        entry.extend(self.u32(inode.ino));
+        entry.extend(self.u32(inode.version));
+        entry.extend(self.u32((self.image.len() - self.block_start) as u32));
+        entry.extend(self.u32(68 + inode.data.len() as u32));
+        self.summary.push(entry);
+
+        self.node(
+            0xE002,
+            &node[12..]
+                .iter()
+                .chain(&inode.data)
+                .copied()
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    /// An extended attribute `xid`, `name` excludes the prefix selected by
+    /// `xprefix`
+    pub fn xattr(
+        &mut self,
+        xid: u32,
+        version: u32,
+        xprefix: u8,
+        name: &str,
+        value: &[u8],
+    ) -> &mut Self {
+        let data: Vec<u8> = [name.as_bytes(), &[0], value].concat();
+        let mut node = Vec::new();
+        node.extend(self.u16(0x1985));
+        node.extend(self.u16(0xE008));
+        node.extend(self.u32(32 + data.len() as u32));
+        let hdr_crc = jffs2_crc32(&node);
+        node.extend(self.u32(hdr_crc));
+        node.extend(self.u32(xid));
+        node.extend(self.u32(version));
+        node.push(xprefix);
+        node.push(name.len() as u8);
+        node.extend(self.u16(value.len() as u16));
+        node.extend(self.u32(jffs2_crc32(&data)));
+        let node_crc = jffs2_crc32(&node);
+        node.extend(self.u32(node_crc));
+
+        let mut entry = Vec::new();
+        entry.extend(self.u16(0xE008));
+        entry.extend(self.u32(xid));
+        entry.extend(self.u32(version));
+        entry.extend(self.u32((self.image.len() - self.block_start) as u32));
+        entry.extend(self.u32(32 + data.len() as u32));
+        self.summary.push(entry);
+
+        self.node(0xE008, &[&node[12..], &data].concat())
+    }
+
+    /// Attach the extended attribute `xid` to inode `ino`, an odd `xseqno`
+    /// detaches it
+    pub fn xref(&mut self, ino: u32, xid: u32, xseqno: u32) -> &mut Self {
+        let mut node = Vec::new();
+        node.extend(self.u16(0x1985));
+        node.extend(self.u16(0xE009));
+        node.extend(self.u32(28));
+        let hdr_crc = jffs2_crc32(&node);
+        node.extend(self.u32(hdr_crc));
+        node.extend(self.u32(ino));
+        node.extend(self.u32(xid));
+        node.extend(self.u32(xseqno));
+        let node_crc = jffs2_crc32(&node);
+        node.extend(self.u32(node_crc));
+
+        let mut entry = Vec::new();
+        entry.extend(self.u16(0xE009));
+        entry.extend(self.u32((self.image.len() - self.block_start) as u32));
+        self.summary.push(entry);
+
+        self.node(0xE009, &node[12..])
+    }
+
+    /// Close the current erase block of `block_size` bytes with a summary of
+    /// the dirents and inodes written since the previous one, as mkfs.jffs2
+    /// does when given --with-summary
+    pub fn summary(&mut self, block_size: usize) -> &mut Self {
+        let entries = self.summary.concat();
+        let block_end = self.block_start + block_size;
+        let at = block_end - (32 + entries.len() + 8).next_multiple_of(4);
+        assert!(at >= self.image.len(), "erase block overflow");
+        self.image.resize(at, 0xff);
+
+        let mut data = entries;
+        data.resize(block_end - at - 32 - 8, 0xff);
+        data.extend(self.u32((at - self.block_start) as u32));
+        data.extend(self.u32(0x0285_1885));
+
+        let mut node = Vec::new();
+        node.extend(self.u16(0x1985));
+        node.extend(self.u16(0x2006));
+        node.extend(self.u32((block_end - at) as u32));
+        let hdr_crc = jffs2_crc32(&node);
+        node.extend(self.u32(hdr_crc));
+        node.extend(self.u32(self.summary.len() as u32));
+        node.extend(self.u32(0));
+        node.extend(self.u32(0));
+        node.extend(self.u32(jffs2_crc32(&data)));
+        let node_crc = jffs2_crc32(&node[..24]);
+        node.extend(self.u32(node_crc));
+        node.extend(data);
+
+        self.image.extend(node);
+        self.block_start = block_end;
+        self.summary.clear();
+        self
+    }
+
+    /// A directory dirent plus its directory inode
+    pub fn dir(&mut self, pino: u32, ino: u32, name: impl AsRef<[u8]>) -> &mut Self {
+        self.dirent(pino, ino, 1, DT_DIR, name);
+        let mut inode = InodeNode::file(ino, 1, 0, &[]);
+        inode.mode = S_IFDIR | 0o755;
+        self.inode(&inode)
+    }
+
+    /// A regular file dirent plus a single uncompressed data node
+    pub fn file(&mut self, pino: u32, ino: u32, name: impl AsRef<[u8]>, data: &[u8]) -> &mut Self {
+        self.dirent(pino, ino, 1, DT_REG, name);
+        self.inode(&InodeNode::file(ino, 1, 0, data))
+    }
+
+    /// A symbolic link dirent plus the node holding its target
+    pub fn symlink(&mut self, pino: u32, ino: u32, name: &str, target: &str) -> &mut Self {
+        self.dirent(pino, ino, 1, DT_LNK, name);
+        let mut inode = InodeNode::file(ino, 1, 0, target.as_bytes());
+        inode.mode = S_IFLNK | 0o777;
+        self.inode(&inode)
+    }
+
+    /// A device node, FIFO or socket dirent, `data` holds the device number
+    pub fn special(
+        &mut self,
+        pino: u32,
+        ino: u32,
+        name: &str,
+        ntype: u8,
+        mode: u32,
+        data: &[u8],
+    ) -> &mut Self {
+        self.dirent(pino, ino, 1, ntype, name);
+        let mut inode = InodeNode::file(ino, 1, 0, data);
+        inode.mode = mode;
+        self.inode(&inode)
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        self.image.clone()
+    }
+}
+
+/// Write `image` to a fresh file in the temporary directory
+pub fn write_temp_image(name: &str, image: &[u8]) -> TempImage {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let path = std::env::temp_dir().join(format!(
+        "jffs2-rs-{}-{}-{}.img",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed),
+        name
+    ));
+    std::fs::write(&path, image).expect("Failed to write image");
+    TempImage(path)
+}
+
+/// An image written by [`write_temp_image`], removed when dropped. A reader
+/// maps the image, so it outlives the file.
+#[derive(Debug)]
+pub struct TempImage(PathBuf);
+
+impl Deref for TempImage {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempImage {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempImage {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Port of the kernel's jffs2_rtime_compress, without an output limit
+pub fn rtime_compress(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut positions = [0usize; 256];
+    let mut pos = 0;
+    while pos < data.len() {
+        let value = data[pos];
+        out.push(value);
+        pos += 1;
+        let mut backpos = positions[value as usize];
+        positions[value as usize] = pos;
+
+        let mut runlen = 0u8;
+        while backpos < pos && pos < data.len() && data[pos] == data[backpos] && runlen < 255 {
+            pos += 1;
+            backpos += 1;
+            runlen += 1;
+        }
+        out.push(runlen);
+    }
+    out
+}
+
+/// Port of the kernel's rubin encoder with the fixed rubinmips bit weights
+#[cfg(feature = "rubin")]
+pub fn rubinmips_compress(data: &[u8]) -> Vec<u8> {
+    const UPPER_BIT: u64 = 1 << 15;
+    const LOWER_BITS: u64 = UPPER_BIT - 1;
+    const BIT_DIVIDER: u64 = 1043;
+    const BITS: [u64; 8] = [277, 249, 290, 267, 229, 341, 212, 241];
+
+    let mut out = Vec::new();
+    let mut nbits = 0;
+    let mut push = |bit: bool| {
+        if nbits % 8 == 0 {
+            out.push(0);
+        }
+        if bit {
+            *out.last_mut().unwrap() |= 0x80 >> (nbits % 8);
+        }
+        nbits += 1;
+    };
+
+    let (mut p, mut q) = (2 * UPPER_BIT, 0);
+    for &byte in data {
+        for (i, weight) in BITS.iter().enumerate() {
+            while q >= UPPER_BIT || p + q <= UPPER_BIT {
+                push(q & UPPER_BIT != 0);
+                q = (q & LOWER_BITS) << 1;
+                p <<= 1;
+            }
+            let i0 = ((BIT_DIVIDER - weight) * p / BIT_DIVIDER).clamp(1, p - 1);
+            if byte >> i & 1 == 0 {
+                p = i0;
+            } else {
+                p -= i0;
+                q += i0;
+            }
+        }
+    }
+    for _ in 0..16 {
+        push(q & UPPER_BIT != 0);
+        q = (q & LOWER_BITS) << 1;
+    }
+    out
+}
+
+/// A raw LZMA stream as jffs2 stores it, without the header. lzma-rs always
+/// encodes with lc=3, lp=0 and pb=2.
+pub fn lzma_compress(data: &[u8]) -> Vec<u8> {
+    let options = lzma_rs::compress::Options {
+        unpacked_size: lzma_rs::compress::UnpackedSize::SkipWritingToHeader,
+    };
+    let mut out = Vec::new();
+    lzma_rs::lzma_compress_with_options(&mut &data[..], &mut out, &options)
+        .expect("Failed to compress");
+    // properties and dictionary size
+    out.split_off(5)
+}
+
+/// The original byte-at-a-time rtime decoder, kept to check the faster one
+pub fn rtime_decompress_reference(compressed_buffer: &[u8], dstlen: usize) -> Vec<u8> {
+    let mut dst = vec![];
+    let mut pos = 0;
+    let mut position = vec![0; 256];
+
+    while dst.len() < dstlen {
+        let val = compressed_buffer[pos];
+        pos += 1;
+        dst.push(val);
+
+        let mut repeat = compressed_buffer[pos];
+        pos += 1;
+        let mut backoffs = position[val as usize];
+
+        position[val as usize] = dst.len();
+        if repeat != 0 {
+            if backoffs + repeat as usize >= dst.len() {
+                while repeat != 0 {
+                    dst.push(dst[backoffs]);
+                    backoffs += 1;
+                    repeat -= 1;
+                }
+            } else {
+                let slice = &dst[backoffs..backoffs + repeat as usize].to_owned();
+                dst.extend(slice);
+            }
+        }
+    }
+
+    dst
+}
@@ -0,0 +1,289 @@
+//! Image verification.
+//!
+//! [`Jffs2Reader::verify`] goes over every node of the image again and checks
+//! all of its CRCs, whatever the reader was built to check, then looks for
+//! dirents and inodes that don't hold together. Every problem found is
+//! listed rather than failing on the first one.
+
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fmt;
+
+use anyhow::Result;
+
+use crate::crc::jffs2_crc32;
+use crate::{
+    Jffs2Reader, NodeType, JFFS2_MAGIC, QUARANTINE_INO, ROOT_INO, SIZE_OF_INODE,
+    SIZE_OF_NODE_HEADER,
+};
+
+/// Something wrong with the image, see [`Jffs2Reader::verify`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyProblem {
+    /// A node magic whose header doesn't match its CRC
+    HeaderCrc { offset: u64 },
+    /// A node running past the end of the image or too short for its type
+    Truncated { offset: u64 },
+    /// A dirent or inode node whose node CRC doesn't match
+    NodeCrc { offset: u64 },
+    /// A dirent whose name doesn't match its CRC
+    NameCrc { offset: u64 },
+    /// An inode node whose compressed data doesn't match its CRC
+    DataCrc { offset: u64 },
+    /// A dirent that doesn't lead back to the root directory
+    Unresolved { pino: u32, name: OsString, reason: String },
+    /// A dirent naming an inode that has no node in the image
+    MissingInode { pino: u32, name: OsString, ino: u32 },
+    /// An inode that no dirent names
+    Orphan { ino: u32 },
+}
+
+impl fmt::Display for VerifyProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyProblem::HeaderCrc { offset } => {
+                write!(f, "bad header CRC at offset {:#x}", offset)
+            }
+            VerifyProblem::Truncated { offset } => {
+                write!(f, "truncated node at offset {:#x}", offset)
+            }
+            VerifyProblem::NodeCrc { offset } => write!(f, "bad node CRC at offset {:#x}", offset),
+            VerifyProblem::NameCrc { offset } => write!(f, "bad name CRC at offset {:#x}", offset),
+            VerifyProblem::DataCrc { offset } => write!(f, "bad data CRC at offset {:#x}", offset),
+            VerifyProblem::Unresolved { pino, name, reason } => {
+                write!(f, "dirent {:?} in directory {}: {}", name, pino, reason)
+            }
+            VerifyProblem::MissingInode { pino, name, ino } => {
+                write!(f, "dirent {:?} in directory {} names missing inode {}", name, pino, ino)
+            }
+            VerifyProblem::Orphan { ino } => write!(f, "inode {} has no dirent", ino),
+        }
+    }
+}
+
+/// Outcome of [`Jffs2Reader::verify`]
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    nodes: u64,
+    problems: Vec<VerifyProblem>,
+}
+
+impl VerifyReport {
+    /// Number of nodes whose header CRC matched
+    pub fn nodes(&self) -> u64 {
+        self.nodes
+    }
+
+    /// The problems found, the node ones by offset followed by the dirent
+    /// and inode ones
+    pub fn problems(&self) -> &[VerifyProblem] {
+        &self.problems
+    }
+
+    /// Whether no problem was found
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl Jffs2Reader<'_> {
+    /// Check the header, node, name and data CRCs of every node of the image,
+    /// that every dirent resolves to a path from the root, that every dirent
+    /// names an existing inode and that every inode is named by some dirent.
+    /// The dirent and inode checks use what the scan kept, so the reader
+    /// needs to be scanned first.
+    pub fn verify(&self) -> Result<VerifyReport> {
+        let mut report = VerifyReport::default();
+        self.verify_nodes(&mut report)?;
+        self.verify_tree(&mut report);
+        Ok(report)
+    }
+
+    /// Walk the image node by node like the scan, summaries aside, and check
+    /// their CRCs
+    fn verify_nodes(&self, report: &mut VerifyReport) -> Result<()> {
+        let le = self.little_endian;
+        let end = self.buffer.len();
+        let mut idx = 0;
+        while idx + SIZE_OF_NODE_HEADER <= end {
+            let header = self.buffer.read(idx, SIZE_OF_NODE_HEADER)?;
+            if Jffs2Reader::read_uint16(&header, le, 0)? != JFFS2_MAGIC {
+                let empty = header.iter().all(|b| *b == 0) || header.iter().all(|b| *b == 0xff);
+                drop(header);
+                idx += 4;
+                if empty {
+                    idx = self.skip_empty(idx, end)?;
+                }
+                continue;
+            }
+            let offset = idx as u64;
+            let nodetype = Jffs2Reader::read_uint16(&header, le, 2)?;
+            let totlen = Jffs2Reader::read_uint32(&header, le, 4)? as usize;
+            if Jffs2Reader::read_uint32(&header, le, 8)? != jffs2_crc32(&header[..8]) {
+                report.problems.push(VerifyProblem::HeaderCrc { offset });
+                idx += 4;
+                continue;
+            }
+            drop(header);
+            report.nodes += 1;
+
+            let nodetype = NodeType::try_from(nodetype).ok();
+            if nodetype == Some(NodeType::CleanMarker) {
+                idx += SIZE_OF_NODE_HEADER;
+                continue;
+            }
+            if !(SIZE_OF_NODE_HEADER..=end - idx).contains(&totlen) {
+                if nodetype != Some(NodeType::Padding) {
+                    report.problems.push(VerifyProblem::Truncated { offset });
+                }
+                idx += 4;
+                continue;
+            }
+
+            match nodetype {
+                Some(NodeType::Dirent) => {
+                    let node = self.buffer.read(idx, totlen)?;
+                    if totlen < 40 {
+                        report.problems.push(VerifyProblem::Truncated { offset });
+                    } else if !self.node_crc_matches(&node, 32, 32) {
+                        report.problems.push(VerifyProblem::NodeCrc { offset });
+                    } else if !self.name_crc_matches(&node) {
+                        report.problems.push(VerifyProblem::NameCrc { offset });
+                    }
+                }
+                Some(NodeType::Inode) => {
+                    let len = totlen.min(SIZE_OF_NODE_HEADER + SIZE_OF_INODE);
+                    let node = self.buffer.read(idx, len)?;
+                    if len < SIZE_OF_NODE_HEADER + SIZE_OF_INODE {
+                        report.problems.push(VerifyProblem::Truncated { offset });
+                    } else if !self.node_crc_matches(&node, 60, 64) {
+                        report.problems.push(VerifyProblem::NodeCrc { offset });
+                    } else {
+                        let csize = Jffs2Reader::read_uint32(&node, le, 48)? as usize;
+                        let data_crc = Jffs2Reader::read_uint32(&node, le, 60)?;
+                        drop(node);
+                        if csize > totlen - len {
+                            report.problems.push(VerifyProblem::Truncated { offset });
+                        } else if jffs2_crc32(&self.buffer.read(idx + len, csize)?) != data_crc {
+                            report.problems.push(VerifyProblem::DataCrc { offset });
+                        }
+                    }
+                }
+                _ => {}
+            }
+            idx += Jffs2Reader::pad(totlen);
+        }
+        Ok(())
+    }
+
+    /// Check that the dirents kept by the scan resolve and name an inode, and
+    /// that every inode has a dirent
+    fn verify_tree(&self, report: &mut VerifyReport) {
+        let mut named = HashSet::new();
+        for dirent in self.rooted_dirents() {
+            named.insert(dirent.ino);
+            if dirent.ino == QUARANTINE_INO {
+                continue;
+            }
+            if let Err(e) = self.resolve_dirent(dirent) {
+                report.problems.push(VerifyProblem::Unresolved {
+                    pino: dirent.pino,
+                    name: dirent.fname.clone(),
+                    reason: e.to_string(),
+                });
+            }
+            if !self.inodes.contains_key(&dirent.ino) {
+                report.problems.push(VerifyProblem::MissingInode {
+                    pino: dirent.pino,
+                    name: dirent.fname.clone(),
+                    ino: dirent.ino,
+                });
+            }
+        }
+        // a custom root leaves dirents out, their inodes are not orphans
+        named.extend(self.all_dirents().map(|dirent| dirent.ino));
+        let mut orphans: Vec<u32> = self
+            .inodes
+            .keys()
+            .copied()
+            .filter(|ino| *ino != ROOT_INO && *ino != self.root_ino && !named.contains(ino))
+            .collect();
+        orphans.sort_unstable();
+        report.problems.extend(orphans.into_iter().map(|ino| VerifyProblem::Orphan { ino }));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testutil::*;
+    use crate::DT_REG;
+
+    /// Offset of the first occurrence of `needle` in `image`
+    fn find(image: &[u8], needle: &[u8]) -> usize {
+        image.windows(needle.len()).position(|w| w == needle).unwrap()
+    }
+
+    #[test]
+    fn test_verify() {
+        let image = ImageBuilder::new()
+            .dir(1, 2, "etc")
+            .file(2, 3, "passwd", b"root:x:0:0\n")
+            .build();
+        let mut reader = Jffs2Reader::from_bytes(&image[..]).unwrap();
+        reader.scan().unwrap();
+        let report = reader.verify().unwrap();
+        assert!(report.is_ok(), "{:?}", report.problems());
+        assert_eq!(report.nodes(), 4);
+
+        let mut image = ImageBuilder::new()
+            .dir(1, 2, "etc")
+            .file(2, 3, "passwd", b"root:x:0:0\n")
+            .file(1, 4, "motd", b"hello\n")
+            .dirent(1, 5, 1, DT_REG, "ghost")
+            .inode(&InodeNode::file(6, 1, 0, b"lost\n"))
+            .dirent(7, 8, 1, DT_REG, "stray")
+            .inode(&InodeNode::file(8, 1, 0, b"stray\n"))
+            .file(1, 9, "broken", b"")
+            .build();
+        // the name and the data are left out of the node CRC
+        let passwd = find(&image, b"passwd");
+        image[passwd] = b'P';
+        let data = find(&image, b"hello");
+        image[data] = b'j';
+        // the modification time is not
+        let broken = find(&image, b"broken") - 16;
+        image[broken] ^= 1;
+        let mut reader = Jffs2Reader::from_bytes(&image[..]).unwrap();
+        reader.scan().unwrap();
+        let problems = reader.verify().unwrap().problems().to_vec();
+        let offset = |name: &[u8]| (find(&image, name) - 40) as u64;
+        assert_eq!(
+            problems,
+            [
+                VerifyProblem::NameCrc { offset: offset(b"Passwd") },
+                VerifyProblem::DataCrc { offset: (find(&image, b"jello") - 68) as u64 },
+                VerifyProblem::NodeCrc { offset: offset(b"broken") },
+                VerifyProblem::MissingInode { pino: 1, name: "ghost".into(), ino: 5 },
+                VerifyProblem::Unresolved {
+                    pino: 7,
+                    name: "stray".into(),
+                    reason: "cannot find parent node 7".into(),
+                },
+                VerifyProblem::Orphan { ino: 6 },
+            ]
+        );
+
+        // a damaged header hides its node, the scan never saw it
+        let mut image = ImageBuilder::new().file(1, 2, "motd", b"hello\n").build();
+        image[4] ^= 1;
+        let mut reader = Jffs2Reader::from_bytes(&image[..]).unwrap();
+        reader.scan().unwrap();
+        let problems = reader.verify().unwrap().problems().to_vec();
+        assert_eq!(
+            problems,
+            [VerifyProblem::HeaderCrc { offset: 0 }, VerifyProblem::Orphan { ino: 2 }]
+        );
+        assert_eq!(problems[0].to_string(), "bad header CRC at offset 0x0");
+    }
+}